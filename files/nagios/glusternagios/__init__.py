@@ -1 +0,0 @@
-__author__ = 'Chris Holcombe <chris.holcombe@canonical.com>'
@@ -1,41 +0,0 @@
-# Copyright 2014 Red Hat, Inc.
-#
-# This program is free software; you can redistribute it and/or modify
-# it under the terms of the GNU General Public License as published by
-# the Free Software Foundation; either version 2 of the License, or
-# (at your option) any later version.
-#
-# This program is distributed in the hope that it will be useful,
-# but WITHOUT ANY WARRANTY; without even the implied warranty of
-# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
-# GNU General Public License for more details.
-#
-# You should have received a copy of the GNU General Public License
-# along with this program; if not, write to the Free Software
-# Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
-#
-# Refer to the README and COPYING files for full details of the license
-#
-
-import utils
-
-_hostNameCommandPath = utils.CommandPath("hostname",
-                                         "/bin/hostname",
-                                         )
-
-
-class HostNameException(Exception):
-    def __init__(self, rc):
-        self.rc = rc
-        self.message = 'hostname execution failed with error code %s' % self.rc
-
-    def __str__(self):
-        return self.message
-
-
-def getHostNameFqdn():
-    rc, out, err = utils.execCmd([_hostNameCommandPath.cmd, '--fqdn'])
-    if rc:
-        raise HostNameException(rc)
-    else:
-        return out[0]
@@ -1,10 +1,33 @@
+extern crate chrono;
+
 use gluster;
+use gluster::volume::Brick;
 use gluster::volume::{quota_list, volume_add_quota, volume_enable_quotas, volume_quotas_enabled,
                       volume_remove_quota, volume_set_options};
 use juju;
+use super::block;
+use super::ctdb::{ctdb_nodes, move_ip, public_ips, resolve_node};
+use super::georep::{checkpoint_completed, georep_status, pause_session, resume_session,
+                    session_status, sessions, set_checkpoint, sync_lag};
+use super::heal::{crawl_in_progress, heal_count, heal_info, heal_split_brain, heal_volume,
+                  pending_heal_entries, split_brain_info, HealCount, HealInfo, HealType,
+                  SplitBrainPolicy};
+use super::rebalance::{rebalance_start, rebalance_status, rebalance_stop, RebalanceStatus,
+                       RebalanceType};
+use super::replace_brick::replace_brick_commit_force;
+use super::snapshot::{activate_snapshot, create_snapshot, delete_snapshot, list_snapshots,
+                      restore_snapshot};
+use super::upgrade;
+use super::upgrade::UpgradePlan;
+use super::volumes::{default_volume_name, get_volumes};
+use super::{finish_initialization, get_config_value, initialize_storage, release_device};
 
-use std::path::PathBuf;
+use self::chrono::Local;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
 pub fn enable_volume_quota() -> Result<(), String> {
     // Gather our action parameters
@@ -140,3 +163,697 @@ pub fn set_volume_options() -> Result<(), String> {
     volume_set_options(&volume, settings).map_err(|e| e.to_string())?;
     return Ok(());
 }
+
+// Report the rebalance progress of every node back to the user
+fn set_rebalance_results(status: &RebalanceStatus) -> Result<(), String> {
+    for (i, node) in status.nodes.iter().enumerate() {
+        let prefix = format!("node-{}", i);
+        juju::action_set(&format!("{}.host", prefix), &node.node).map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.status", prefix), &node.status)
+            .map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.rebalanced-files", prefix),
+                         &node.rebalanced_files.to_string()).map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.bytes-moved", prefix), &node.size.to_string())
+            .map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.scanned", prefix), &node.scanned.to_string())
+            .map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.failures", prefix), &node.failures.to_string())
+            .map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.skipped", prefix), &node.skipped.to_string())
+            .map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.elapsed-seconds", prefix),
+                         &node.run_time.to_string()).map_err(|e| e.to_string())?;
+    }
+    juju::action_set("bytes-moved", &status.bytes_moved().to_string())
+        .map_err(|e| e.to_string())?;
+    juju::action_set("failures", &status.failures().to_string()).map_err(|e| e.to_string())?;
+    juju::action_set("elapsed-seconds", &status.elapsed().to_string())
+        .map_err(|e| e.to_string())?;
+    juju::action_set("progress", &format!("{}%", status.progress()))
+        .map_err(|e| e.to_string())?;
+    juju::action_set("finished", &status.finished().to_string()).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn rebalance_volume() -> Result<(), String> {
+    // Gather our action parameters
+    let volume = match juju::action_get("volume") {
+            Ok(v) => v,
+            Err(e) => {
+                // Notify the user of the failure and then return the error up the stack
+                juju::action_fail(&e.to_string()).map_err(|e| e.to_string())?;
+                return Err(e.to_string());
+            }
+        }
+        .unwrap();
+    // These all have defaults in actions.yaml
+    let operation = juju::action_get("operation")
+        .map_err(|e| e.to_string())?
+        .unwrap_or("start".to_string());
+    let rebalance_type = juju::action_get("rebalance-type")
+        .map_err(|e| e.to_string())?
+        .unwrap_or("full".to_string());
+    let force = juju::action_get("force")
+        .map_err(|e| e.to_string())?
+        .unwrap_or("false".to_string()) == "true";
+    let wait = juju::action_get("wait")
+        .map_err(|e| e.to_string())?
+        .unwrap_or("true".to_string()) == "true";
+    let timeout = action_timeout()?;
+
+    let result = match operation.as_ref() {
+        "start" => {
+            RebalanceType::from_str(&rebalance_type)
+                .and_then(|rebalance| rebalance_start(&volume, &rebalance, force))
+                .and_then(|_| {
+                    log!(format!("Started {} rebalance on volume {}", rebalance_type, volume),
+                         Info);
+                    if wait {
+                        wait_for_rebalance(&volume, timeout)
+                    } else {
+                        rebalance_status(&volume)
+                    }
+                })
+        }
+        "status" => rebalance_status(&volume),
+        "stop" => rebalance_stop(&volume).and_then(|_| rebalance_status(&volume)),
+        _ => Err(format!("Unknown rebalance operation: {}", operation)),
+    };
+    match result {
+        Ok(status) => {
+            set_rebalance_results(&status)?;
+            Ok(())
+        }
+        Err(e) => {
+            log!(format!("Rebalance {} failed: {}", operation, e), Error);
+            juju::action_fail(&e).map_err(|e| e.to_string())?;
+            Err(e)
+        }
+    }
+}
+
+// Poll the rebalance until every node has finished or timeout minutes
+// passed.  The rebalance carries on in the background after a timeout and
+// the last status is returned.
+fn wait_for_rebalance(volume: &str, timeout: u64) -> Result<RebalanceStatus, String> {
+    let deadline = Instant::now() + Duration::from_secs(timeout * 60);
+    loop {
+        let status = rebalance_status(volume)?;
+        log!(format!("Rebalance of {} is {}% complete. {} bytes moved",
+                     volume,
+                     status.progress(),
+                     status.bytes_moved()));
+        if status.finished() {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            log!(format!("Rebalance of {} still running after {} minutes", volume, timeout),
+                 Info);
+            return Ok(status);
+        }
+        thread::sleep(Duration::from_secs(10));
+    }
+}
+
+// The volume parameter is optional for actions that default to the first volume
+fn action_volume() -> Result<String, String> {
+    match juju::action_get("volume").map_err(|e| e.to_string())? {
+        Some(ref v) if !v.is_empty() => Ok(v.clone()),
+        _ => default_volume_name(),
+    }
+}
+
+// Report how many entries each brick still has to heal
+fn set_heal_results(counts: &Vec<HealCount>) -> Result<(), String> {
+    for (i, count) in counts.iter().enumerate() {
+        let prefix = format!("brick-{}", i);
+        juju::action_set(&format!("{}.brick", prefix), &count.brick)
+            .map_err(|e| e.to_string())?;
+        let entries = match count.entries {
+            Some(entries) => entries.to_string(),
+            None => "unknown".to_string(),
+        };
+        juju::action_set(&format!("{}.pending-entries", prefix), &entries)
+            .map_err(|e| e.to_string())?;
+    }
+    juju::action_set("pending-entries", &pending_heal_entries(counts).to_string())
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Grow the thin pool of an lvm-thin brick onto another device
+pub fn extend_brick() -> Result<(), String> {
+    // brick and device are required parameters
+    let brick_path = juju::action_get("brick").map_err(|e| e.to_string())?.unwrap_or_default();
+    let device = juju::action_get("device").map_err(|e| e.to_string())?.unwrap_or_default();
+    match extend_brick_device(&brick_path, &device) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            log!(format!("Extending brick {} failed: {}", brick_path, e), Error);
+            juju::action_fail(&e).map_err(|e| e.to_string())?;
+            Err(e)
+        }
+    }
+}
+
+fn extend_brick_device(brick_path: &str, device: &str) -> Result<(), String> {
+    let mut devices = block::get_manual_bricks()?;
+    devices.extend(block::get_juju_bricks()?);
+    // The brick may be in a subdirectory of the device's mount
+    let brick = match devices.into_iter()
+        .find(|brick| Path::new(brick_path).starts_with(&brick.mount_path)) {
+        Some(brick) => brick,
+        None => return Err(format!("{} is not on a brick device of this server", brick_path)),
+    };
+    let thin = match brick.thin_volume {
+        Some(ref thin) if thin.exists() => thin.clone(),
+        _ => return Err(format!("{} is not an lvm-thin brick", brick_path)),
+    };
+    let new_device = match block::scan_devices(vec![device.to_string()])?.pop() {
+        Some(new_device) => new_device,
+        None => return Err(format!("{} is not a usable block device", device)),
+    };
+    if new_device.initialized {
+        return Err(format!("{} is already in use.  Use a new device", device));
+    }
+    let filesystem_type = block::FilesystemType::from_str(&get_config_value("filesystem_type")?);
+    log!(format!("Extending {} onto {}", thin.volume_group, device), Info);
+    block::extend_thin_volume(&thin, &new_device.dev_path, &brick.mount_path, &filesystem_type)?;
+    let size = block::thin_pool_size(&thin)?;
+    juju::action_set("size", &format!("{}k", size)).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn replace_brick() -> Result<(), String> {
+    // brick and device are required parameters
+    let brick_path = juju::action_get("brick").map_err(|e| e.to_string())?.unwrap_or_default();
+    let device = juju::action_get("device").map_err(|e| e.to_string())?.unwrap_or_default();
+    let wait = juju::action_get("wait")
+        .map_err(|e| e.to_string())?
+        .unwrap_or("false".to_string()) == "true";
+    let timeout = action_timeout()?;
+
+    let result = replace_failed_device(&brick_path, &device).and_then(|replaced| {
+        let mut counts: Vec<HealCount> = Vec::new();
+        for (i, &(ref volume, ref new_brick)) in replaced.iter().enumerate() {
+            juju::action_set(&format!("new-brick-{}.volume", i), volume)
+                .map_err(|e| e.to_string())?;
+            juju::action_set(&format!("new-brick-{}.brick", i), new_brick)
+                .map_err(|e| e.to_string())?;
+            if wait {
+                counts.extend(wait_for_heal(volume, timeout)?);
+            } else {
+                counts.extend(heal_count(volume)?);
+            }
+        }
+        Ok(counts)
+    });
+    match result {
+        Ok(counts) => {
+            set_heal_results(&counts)?;
+            Ok(())
+        }
+        Err(e) => {
+            log!(format!("Replacing brick {} failed: {}", brick_path, e), Error);
+            juju::action_fail(&e).map_err(|e| e.to_string())?;
+            Err(e)
+        }
+    }
+}
+
+// Swap the bricks every volume has on the failed device for bricks on a
+// freshly formatted device and start healing the data back onto them.
+// Returns the volume and new brick of every replaced brick.
+fn replace_failed_device(brick_path: &str, device: &str) -> Result<Vec<(String, String)>, String> {
+    let volumes = get_volumes()?;
+    // The brick may be in a subdirectory of the failed device's mount
+    let failed_mount = match volumes.iter()
+        .filter_map(|volume| {
+            let mount = match volume.brick_dir {
+                Some(_) => Path::new(brick_path).parent().map(|p| p.to_string_lossy().into_owned()),
+                None => Some(brick_path.to_string()),
+            };
+            mount.filter(|mount| volume.brick_path(mount) == brick_path)
+        })
+        .next() {
+        Some(mount) => mount,
+        None => return Err(format!("{} is not a brick path of any volume", brick_path)),
+    };
+
+    let mut old_bricks: Vec<(String, Brick)> = Vec::new();
+    for volume in &volumes {
+        let old_path = PathBuf::from(volume.brick_path(&failed_mount));
+        match gluster::get_local_bricks(&volume.name) {
+            Ok(bricks) => {
+                for brick in bricks.into_iter().filter(|brick| brick.path == old_path) {
+                    old_bricks.push((volume.name.clone(), brick));
+                }
+            }
+            Err(gluster::GlusterError::NoVolumesPresent) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    if old_bricks.is_empty() {
+        return Err(format!("{} is not a brick of any volume on this server", brick_path));
+    }
+    let new_device = match block::scan_devices(vec![device.to_string()])?.pop() {
+        Some(new_device) => new_device,
+        None => return Err(format!("{} is not a usable block device", device)),
+    };
+    if new_device.initialized || new_device.mount_path == failed_mount {
+        return Err(format!("{} is already in use.  Use a new device", device));
+    }
+
+    // The failed device is gone so forget everything finish_initialization set
+    // up for it.  A dead disk may refuse to unmount cleanly so this is best effort.
+    let mut old_devices = block::get_manual_bricks()?;
+    old_devices.extend(block::get_juju_bricks()?);
+    let old_device_path = match old_devices.into_iter()
+        .find(|old_device| old_device.mount_path == failed_mount) {
+        Some(old_device) => old_device.dev_path,
+        None => {
+            PathBuf::from("/dev")
+                .join(PathBuf::from(&failed_mount).file_name().unwrap_or_default())
+        }
+    };
+    log!(format!("Releasing failed device {:?}", old_device_path), Info);
+    if let Err(e) = release_device(&old_device_path) {
+        log!(format!("Unable to fully release {:?}: {}", old_device_path, e),
+             Error);
+    }
+
+    let new_mount_path = new_device.mount_path.clone();
+    let init = initialize_storage(new_device)?;
+    let output = init.format_child.wait_with_output().map_err(|e| e.to_string())?;
+    block::process_output(output)?;
+    finish_initialization(&init.device.dev_path).map_err(|e| e.to_string())?;
+
+    let mut replaced: Vec<(String, String)> = Vec::new();
+    for (volume_name, old_brick) in old_bricks {
+        let volume = match volumes.iter().find(|volume| volume.name == volume_name) {
+            Some(volume) => volume,
+            None => continue,
+        };
+        let mut new_brick = old_brick.clone();
+        new_brick.path = PathBuf::from(volume.brick_path(&new_mount_path));
+        log!(format!("Replacing brick {} with {}",
+                     old_brick.to_string(),
+                     new_brick.to_string()),
+             Info);
+        replace_brick_commit_force(&volume.name, &old_brick, &new_brick)?;
+
+        // The new brick is empty.  A full heal copies everything back onto it
+        // from the rest of the replica set.
+        heal_volume(&volume.name, &HealType::Full)?;
+        replaced.push((volume.name.clone(), new_brick.to_string()));
+    }
+    Ok(replaced)
+}
+
+// Poll the heal counters until the crawl is over and nothing is left to heal
+// or timeout minutes passed.  The heal carries on in the background after a
+// timeout and the last counts are returned.
+fn wait_for_heal(volume: &str, timeout: u64) -> Result<Vec<HealCount>, String> {
+    let deadline = Instant::now() + Duration::from_secs(timeout * 60);
+    loop {
+        let counts = heal_count(volume)?;
+        let pending = pending_heal_entries(&counts);
+        log!(format!("{} entries left to heal on {}", pending, volume));
+        if pending == 0 && !crawl_in_progress(volume)? {
+            return Ok(counts);
+        }
+        if Instant::now() >= deadline {
+            log!(format!("Heal of {} still running after {} minutes", volume, timeout),
+                 Info);
+            return Ok(counts);
+        }
+        thread::sleep(Duration::from_secs(10));
+    }
+}
+
+// The timeout parameter of the actions that wait, in minutes
+fn action_timeout() -> Result<u64, String> {
+    Ok(juju::action_get("timeout")
+        .map_err(|e| e.to_string())?
+        .and_then(|t| t.parse::<u64>().ok())
+        .unwrap_or(60))
+}
+
+// Report the entries of every brick back to the user
+fn set_heal_info_results(infos: &Vec<HealInfo>) -> Result<(), String> {
+    let mut total = 0;
+    for (i, info) in infos.iter().enumerate() {
+        let prefix = format!("brick-{}", i);
+        juju::action_set(&format!("{}.brick", prefix), &info.brick)
+            .map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.status", prefix), &info.status)
+            .map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.count", prefix), &info.entries.len().to_string())
+            .map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.entries", prefix), &info.entries.join("\n"))
+            .map_err(|e| e.to_string())?;
+        total += info.entries.len();
+    }
+    juju::action_set("count", &total.to_string()).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn list_heal_info() -> Result<(), String> {
+    let volume = action_volume()?;
+    match heal_info(&volume) {
+        Ok(infos) => set_heal_info_results(&infos),
+        Err(e) => {
+            log!(format!("Heal info of {} failed: {}", volume, e), Error);
+            juju::action_fail(&e).map_err(|e| e.to_string())?;
+            Err(e)
+        }
+    }
+}
+
+pub fn list_split_brain() -> Result<(), String> {
+    let volume = action_volume()?;
+    match split_brain_info(&volume) {
+        Ok(infos) => set_heal_info_results(&infos),
+        Err(e) => {
+            log!(format!("Split-brain info of {} failed: {}", volume, e), Error);
+            juju::action_fail(&e).map_err(|e| e.to_string())?;
+            Err(e)
+        }
+    }
+}
+
+pub fn start_heal() -> Result<(), String> {
+    let volume = action_volume()?;
+    // These have defaults in actions.yaml
+    let heal_type = juju::action_get("heal-type")
+        .map_err(|e| e.to_string())?
+        .unwrap_or("index".to_string());
+    let wait = juju::action_get("wait")
+        .map_err(|e| e.to_string())?
+        .unwrap_or("false".to_string()) == "true";
+    let timeout = action_timeout()?;
+
+    let result = HealType::from_str(&heal_type)
+        .and_then(|heal| heal_volume(&volume, &heal))
+        .and_then(|_| {
+            log!(format!("Started {} heal on volume {}", heal_type, volume), Info);
+            if wait {
+                wait_for_heal(&volume, timeout)
+            } else {
+                heal_count(&volume)
+            }
+        });
+    match result {
+        Ok(counts) => set_heal_results(&counts),
+        Err(e) => {
+            log!(format!("Heal of {} failed: {}", volume, e), Error);
+            juju::action_fail(&e).map_err(|e| e.to_string())?;
+            Err(e)
+        }
+    }
+}
+
+pub fn resolve_split_brain() -> Result<(), String> {
+    let volume = action_volume()?;
+    // file and policy are required parameters
+    let file = juju::action_get("file").map_err(|e| e.to_string())?.unwrap_or_default();
+    let policy = juju::action_get("policy").map_err(|e| e.to_string())?.unwrap_or_default();
+    let source_brick = juju::action_get("source-brick").map_err(|e| e.to_string())?;
+
+    let result = SplitBrainPolicy::from_str(&policy, source_brick)
+        .and_then(|policy| heal_split_brain(&volume, &file, &policy))
+        .and_then(|_| split_brain_info(&volume));
+    match result {
+        Ok(infos) => {
+            log!(format!("Resolved split-brain of {} using {}", file, policy), Info);
+            // Report whatever is still in split-brain
+            set_heal_info_results(&infos)
+        }
+        Err(e) => {
+            log!(format!("Resolving split-brain of {} failed: {}", file, e), Error);
+            juju::action_fail(&e).map_err(|e| e.to_string())?;
+            Err(e)
+        }
+    }
+}
+
+// The geo-replication session an action works on.  slave can be left out
+// when the volume only has one session.
+fn action_georep_session() -> Result<(String, String), String> {
+    let volume = action_volume()?;
+    let slave = juju::action_get("slave").map_err(|e| e.to_string())?;
+    let workers = georep_status()?;
+    let volume_sessions: Vec<(String, String)> = sessions(&workers)
+        .into_iter()
+        .filter(|&(ref master, ref session_slave)| {
+            *master == volume &&
+            match slave {
+                Some(ref slave) if !slave.is_empty() => session_slave == slave,
+                _ => true,
+            }
+        })
+        .collect();
+    match volume_sessions.len() {
+        0 => Err(format!("Volume {} has no matching geo-replication session", volume)),
+        1 => Ok(volume_sessions[0].clone()),
+        n => {
+            Err(format!("Volume {} has {} geo-replication sessions.  Pick one with slave",
+                        volume,
+                        n))
+        }
+    }
+}
+
+// Run a geo-replication command on the session the action names and report
+// the session's status afterwards
+fn run_georep_action(name: &str, f: fn(&str, &str) -> Result<(), String>) -> Result<(), String> {
+    let result = action_georep_session().and_then(|(volume, slave)| {
+        f(&volume, &slave)?;
+        log!(format!("Geo-replication {} of {} to {} succeeded", name, volume, slave),
+             Info);
+        set_georep_results(&volume, &slave)
+    });
+    if let Err(ref e) = result {
+        log!(format!("Geo-replication {} failed: {}", name, e), Error);
+        juju::action_fail(e).map_err(|e| e.to_string())?;
+    }
+    result
+}
+
+fn set_georep_results(volume: &str, slave: &str) -> Result<(), String> {
+    let workers = georep_status()?;
+    juju::action_set("slave", slave).map_err(|e| e.to_string())?;
+    juju::action_set("status", &session_status(&workers, volume, slave))
+        .map_err(|e| e.to_string())?;
+    let lag = match sync_lag(&workers, volume, slave, Local::now().naive_local()) {
+        Some(lag) => lag.to_string(),
+        None => "unknown".to_string(),
+    };
+    juju::action_set("lag-seconds", &lag).map_err(|e| e.to_string())?;
+    let checkpoint = match checkpoint_completed(&workers, volume, slave) {
+        Some(true) => "completed",
+        Some(false) => "pending",
+        None => "none",
+    };
+    juju::action_set("checkpoint", checkpoint).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn georep_pause() -> Result<(), String> {
+    run_georep_action("pause", pause_session)
+}
+
+pub fn georep_resume() -> Result<(), String> {
+    run_georep_action("resume", resume_session)
+}
+
+pub fn georep_checkpoint() -> Result<(), String> {
+    run_georep_action("checkpoint", set_checkpoint)
+}
+
+pub fn georep_session_status() -> Result<(), String> {
+    run_georep_action("status", |_, _| Ok(()))
+}
+
+// name is a required parameter of the snapshot actions that take one
+fn action_snapshot_name() -> Result<String, String> {
+    Ok(juju::action_get("name").map_err(|e| e.to_string())?.unwrap_or_default())
+}
+
+fn fail_action(what: &str, result: Result<(), String>) -> Result<(), String> {
+    if let Err(ref e) = result {
+        log!(format!("{} failed: {}", what, e), Error);
+        juju::action_fail(e).map_err(|e| e.to_string())?;
+    }
+    result
+}
+
+pub fn snapshot_create() -> Result<(), String> {
+    let result = action_volume().and_then(|volume| {
+        let name = action_snapshot_name()?;
+        let snapshot = create_snapshot(&volume, &name)?;
+        log!(format!("Took snapshot {} of {}", snapshot, volume), Info);
+        juju::action_set("snapshot", &snapshot).map_err(|e| e.to_string())?;
+        Ok(())
+    });
+    fail_action("Snapshot create", result)
+}
+
+pub fn snapshot_list() -> Result<(), String> {
+    let result = action_volume().and_then(|volume| {
+        for (i, snapshot) in list_snapshots(&volume)?.iter().enumerate() {
+            let prefix = format!("snapshot-{}", i);
+            let created = match snapshot.created {
+                Some(created) => format!("{} GMT", created),
+                None => "unknown".to_string(),
+            };
+            juju::action_set(&format!("{}.name", prefix), &snapshot.name)
+                .map_err(|e| e.to_string())?;
+            juju::action_set(&format!("{}.created", prefix), &created)
+                .map_err(|e| e.to_string())?;
+            juju::action_set(&format!("{}.status", prefix), &snapshot.status)
+                .map_err(|e| e.to_string())?;
+            if !snapshot.description.is_empty() {
+                juju::action_set(&format!("{}.volume-state", prefix), &snapshot.description)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    });
+    fail_action("Snapshot list", result)
+}
+
+pub fn snapshot_delete() -> Result<(), String> {
+    let result = action_snapshot_name().and_then(|name| delete_snapshot(&name));
+    fail_action("Snapshot delete", result)
+}
+
+pub fn snapshot_activate() -> Result<(), String> {
+    let result = action_snapshot_name().and_then(|name| activate_snapshot(&name));
+    fail_action("Snapshot activate", result)
+}
+
+pub fn snapshot_restore() -> Result<(), String> {
+    let result = action_volume().and_then(|volume| {
+        let name = action_snapshot_name()?;
+        log!(format!("Restoring {} to snapshot {}", volume, name), Info);
+        restore_snapshot(&volume, &name)
+    });
+    fail_action("Snapshot restore", result)
+}
+
+// The ctdb pnn of a node given by pnn or address
+fn find_ctdb_node(node: &str) -> Result<u32, String> {
+    if let Ok(pnn) = u32::from_str(node) {
+        return Ok(pnn);
+    }
+    let address = resolve_node(node)?;
+    match ctdb_nodes()?.iter().find(|ctdb_node| ctdb_node.address == address) {
+        Some(ctdb_node) => Ok(ctdb_node.pnn),
+        None => Err(format!("{} is not a ctdb node", node)),
+    }
+}
+
+// Optionally move one virtual ip and then show where each one is hosted
+fn move_and_list_virtual_ips() -> Result<(), String> {
+    let address = juju::action_get("address").map_err(|e| e.to_string())?.unwrap_or_default();
+    let node = juju::action_get("node").map_err(|e| e.to_string())?.unwrap_or_default();
+    match (address.is_empty(), node.is_empty()) {
+        (true, true) => {}
+        (false, false) => {
+            let address = IpAddr::from_str(&address).map_err(|e| e.to_string())?;
+            move_ip(&address, find_ctdb_node(&node)?)?;
+        }
+        _ => return Err("address and node have to be given together".to_string()),
+    }
+    let nodes = ctdb_nodes()?;
+    for (i, &(address, pnn)) in public_ips()?.iter().enumerate() {
+        let prefix = format!("vip-{}", i);
+        juju::action_set(&format!("{}.address", prefix), &address.to_string())
+            .map_err(|e| e.to_string())?;
+        // Unassigned addresses have pnn -1
+        let host = match nodes.iter().find(|node| node.pnn as i32 == pnn) {
+            Some(node) => node.address.to_string(),
+            None => "unassigned".to_string(),
+        };
+        juju::action_set(&format!("{}.node", prefix), &host).map_err(|e| e.to_string())?;
+        juju::action_set(&format!("{}.pnn", prefix), &pnn.to_string())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+pub fn virtual_ips() -> Result<(), String> {
+    let result = move_and_list_virtual_ips();
+    if let Err(ref e) = result {
+        log!(format!("Virtual ips failed: {}", e), Error);
+        juju::action_fail(e).map_err(|e| e.to_string())?;
+    }
+    result
+}
+
+// The upgrade plan lives in leader settings so only the leader can change it
+fn require_leader() -> Result<(), String> {
+    if !juju::is_leader().map_err(|e| e.to_string())? {
+        return Err("Run this action on the leader unit".to_string());
+    }
+    Ok(())
+}
+
+fn set_upgrade_results(plan: &UpgradePlan) -> Result<(), String> {
+    juju::action_set("version", &plan.version).map_err(|e| e.to_string())?;
+    juju::action_set("order", &plan.order.join(" ")).map_err(|e| e.to_string())?;
+    juju::action_set("current",
+                     plan.current.as_ref().map(|unit| unit.as_ref()).unwrap_or("none"))
+        .map_err(|e| e.to_string())?;
+    juju::action_set("done", &plan.done.join(" ")).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn pause_upgrade() -> Result<(), String> {
+    let result = require_leader().and_then(|_| upgrade::pause_upgrade()).and_then(|plan| {
+        log!(format!("Paused the upgrade to {}", plan.version), Info);
+        set_upgrade_results(&plan)
+    });
+    fail_action("Pausing the upgrade", result)
+}
+
+pub fn resume_upgrade() -> Result<(), String> {
+    let result = require_leader().and_then(|_| upgrade::resume_upgrade()).and_then(|plan| {
+        log!(format!("Resumed the upgrade to {}", plan.version), Info);
+        set_upgrade_results(&plan)
+    });
+    fail_action("Resuming the upgrade", result)
+}
+
+pub fn upgrade_plan() -> Result<(), String> {
+    let result = upgrade::upgrade_report().and_then(|report| {
+        juju::action_set("current-version", &report.current_version.to_string())
+            .map_err(|e| e.to_string())?;
+        juju::action_set("candidate-version", &report.candidate_version.to_string())
+            .map_err(|e| e.to_string())?;
+        let mode = match report.mode {
+            upgrade::UpgradeMode::Auto => "auto",
+            upgrade::UpgradeMode::Manual => "manual",
+        };
+        juju::action_set("upgrade-mode", mode).map_err(|e| e.to_string())?;
+        juju::action_set("order", &report.order.join(" ")).map_err(|e| e.to_string())?;
+        let status = match report.plan {
+            Some(ref plan) if plan.paused => "paused",
+            Some(_) => "in progress",
+            None => "not started",
+        };
+        juju::action_set("status", status).map_err(|e| e.to_string())?;
+        if let Some(ref plan) = report.plan {
+            set_upgrade_results(plan)?;
+        }
+        let blockers = if report.blockers.is_empty() {
+            "none".to_string()
+        } else {
+            report.blockers.join("\n")
+        };
+        juju::action_set("blockers", &blockers).map_err(|e| e.to_string())?;
+        Ok(())
+    });
+    fail_action("Upgrade plan", result)
+}
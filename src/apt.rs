@@ -5,7 +5,7 @@ extern crate juju;
 use std::process::Command;
 
 use self::init_daemon::{detect_daemon, Daemon};
-use super::debian::version::Version;
+use super::package::PackageVersion;
 
 // Add a ppa source to apt
 pub fn add_source(source_string: &str) -> Result<(), String> {
@@ -104,8 +104,58 @@ pub fn service_start(name: &str) -> Result<(), String> {
         }
     };
 }
+pub fn service_restart(name: &str) -> Result<(), String> {
+    let init_daemon = try!(detect_daemon());
+    match init_daemon {
+        Daemon::Systemd => {
+            let mut cmd = Command::new("systemctl");
+            cmd.arg("restart");
+            cmd.arg(name);
+            let output = cmd.output().map_err(|e| e.to_string())?;
+            if !output.status.success() {
+                return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+            }
+            return Ok(());
+        }
+        Daemon::Upstart => {
+            let mut cmd = Command::new("service");
+            cmd.arg("restart");
+            cmd.arg(name);
+            let output = cmd.output().map_err(|e| e.to_string())?;
+            if !output.status.success() {
+                return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+            }
+            return Ok(());
+        }
+        Daemon::Unknown => {
+            return Err("Unknown init system.  Cannot restart service".to_string());
+        }
+    };
+}
+/// Ask dpkg which version of a package is installed
+pub fn installed_version(package_name: &str) -> Result<PackageVersion, String> {
+    let mut cmd = Command::new("dpkg");
+    cmd.arg("-s");
+    cmd.arg(package_name);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    parse_dpkg_status(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The Version field of dpkg -s
+pub fn parse_dpkg_status(output: &str) -> Result<PackageVersion, String> {
+    for line in output.lines() {
+        if line.starts_with("Version:") {
+            return PackageVersion::parse(&line["Version:".len()..]);
+        }
+    }
+    Err(format!("dpkg -s printed no version: {}", output))
+}
+
 /// Ask apt-cache for the new candidate package that is available
-pub fn get_candidate_package_version(package_name: &str) -> Result<Version, String> {
+pub fn get_candidate_package_version(package_name: &str) -> Result<PackageVersion, String> {
     let mut cmd = Command::new("apt-cache");
     cmd.arg("policy");
     cmd.arg(package_name);
@@ -113,14 +163,18 @@ pub fn get_candidate_package_version(package_name: &str) -> Result<Version, Stri
     if !output.status.success() {
         return Err(String::from_utf8_lossy(&output.stderr).into_owned());
     }
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_apt_cache_policy(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The Candidate line of apt-cache policy.  It's (none) when no source
+/// offers the package.
+pub fn parse_apt_cache_policy(stdout: &str) -> Result<PackageVersion, String> {
     for line in stdout.lines() {
         if line.contains("Candidate") {
             let parts: Vec<&str> = line.split(' ').collect();
             match parts.last() {
                 Some(p) => {
-                    let version: Version = Version::parse(p).map_err(|e| e.msg)?;
-                    return Ok(version);
+                    return PackageVersion::parse(p);
                 }
                 None => {
                     return Err(format!("Unknown candidate line format: {:?}", parts));
@@ -131,3 +185,16 @@ pub fn get_candidate_package_version(package_name: &str) -> Result<Version, Stri
     Err(format!("Unable to find candidate upgrade package from stdout: {}",
                 stdout))
 }
+
+#[test]
+fn test_parse_apt_output() {
+    assert_eq!("3.10.5-ubuntu1~xenial1",
+               parse_dpkg_status(include_str!("testdata/dpkg_status.txt")).unwrap().to_string());
+    assert_eq!("3.12.3-ubuntu1~xenial1",
+               parse_apt_cache_policy(include_str!("testdata/apt_cache_policy.txt"))
+                   .unwrap()
+                   .to_string());
+    assert!(parse_apt_cache_policy("glusterfs-server:\n  Installed: (none)\n  Candidate: \
+                                    (none)\n")
+        .is_err());
+}
@@ -4,7 +4,7 @@ extern crate regex;
 extern crate shellscript;
 
 use self::regex::Regex;
-use super::apt::apt_install;
+use super::package::package_manager;
 use super::{device_initialized, get_config_value};
 use uuid::Uuid;
 
@@ -45,6 +45,66 @@ pub struct BrickDevice {
     pub initialized: bool,
     pub mount_path: String,
     pub dev_path: PathBuf,
+    /// Set when the brick filesystem lives on a thin LV on top of the device
+    /// instead of the device itself
+    pub thin_volume: Option<ThinVolume>,
+}
+
+/// The LVM layout of an lvm-thin brick.  Each device gets its own volume
+/// group with a thin pool filling it and one thin LV for the filesystem.
+/// More devices can be added to the volume group to grow the pool later with
+/// extend_thin_volume.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThinVolume {
+    pub volume_group: String,
+    pub thin_pool: String,
+    pub logical_volume: String,
+}
+
+impl ThinVolume {
+    /// The names the charm uses for a device.  /dev/xvdb gets the volume
+    /// group gluster_xvdb.
+    pub fn for_device(device_path: &PathBuf) -> Option<ThinVolume> {
+        let name = match device_path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return None,
+        };
+        Some(ThinVolume {
+            volume_group: format!("gluster_{}", name),
+            thin_pool: "brick_pool".to_string(),
+            logical_volume: "brick".to_string(),
+        })
+    }
+
+    /// The path of the thin LV that holds the filesystem
+    pub fn lv_path(&self) -> PathBuf {
+        Path::new("/dev").join(&self.volume_group).join(&self.logical_volume)
+    }
+
+    /// Whether the thin LV has been created
+    pub fn exists(&self) -> bool {
+        self.lv_path().exists()
+    }
+}
+
+/// How the brick devices are laid out under the filesystem
+#[derive(Debug, Eq, PartialEq)]
+pub enum BrickBackend {
+    /// The filesystem goes straight on the device
+    Raw,
+    /// The filesystem goes on a thin LV.  Gluster snapshots need this.
+    LvmThin,
+}
+
+impl FromStr for BrickBackend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "raw" => Ok(BrickBackend::Raw),
+            "lvm-thin" => Ok(BrickBackend::LvmThin),
+            _ => Err(format!("Unknown brick_backend {}", s)),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -276,10 +336,149 @@ pub fn process_output(output: Output) -> Result<i32, String> {
     }
 }
 
+/// Thin pool chunk size in KiB.  On a RAID array a chunk covers a full
+/// stripe.  Otherwise this is the 256KiB gluster recommends for plain disks.
+pub fn thin_pool_chunk_size(stripe_unit: Option<u64>, stripe_width: Option<u64>) -> u64 {
+    match (stripe_unit, stripe_width) {
+        (Some(unit), Some(width)) if unit > 0 && width > 0 => {
+            let kib = unit * width / 1024;
+            // LVM wants a multiple of 64KiB between 64KiB and 1GiB
+            let rounded = (kib + 63) / 64 * 64;
+            ::std::cmp::min(::std::cmp::max(rounded, 64), 1024 * 1024)
+        }
+        _ => 256,
+    }
+}
+
+fn run_lvm(command: &str, arg_list: &[&str]) -> Result<(), String> {
+    log!(format!("Running {} {:?}", command, arg_list), Info);
+    let output = Command::new(command).args(arg_list).output().map_err(|e| e.to_string())?;
+    process_output(output)?;
+    Ok(())
+}
+
+fn run_lvm_output(command: &str, arg_list: &[&str]) -> Result<String, String> {
+    let output = Command::new(command).args(arg_list).output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// lvs -o lv_size --units k --nosuffix prints sizes like "  20967424.00"
+pub fn parse_lv_size(output: &str) -> Result<u64, String> {
+    let size = output.trim();
+    size.parse::<f64>()
+        .map(|kib| kib as u64)
+        .map_err(|e| format!("Invalid lv size {}: {}", size, e))
+}
+
+/// Size of the thin pool in KiB
+pub fn thin_pool_size(thin: &ThinVolume) -> Result<u64, String> {
+    let pool = format!("{}/{}", thin.volume_group, thin.thin_pool);
+    let output = run_lvm_output("lvs",
+                                &["--noheadings", "--nosuffix", "--units", "k", "-o",
+                                  "lv_size", &pool])?;
+    parse_lv_size(&output)
+}
+
+/// Turn the device into a physical volume with a volume group, a thin pool
+/// and a thin LV the size of the pool.  chunk_size is in KiB.
+pub fn create_thin_volume(device: &PathBuf,
+                          thin: &ThinVolume,
+                          chunk_size: u64)
+                          -> Result<(), String> {
+    if !Path::new("/sbin/lvcreate").exists() {
+        log!("Installing lvm2");
+        package_manager().install(vec!["lvm2"])?;
+    }
+    let device_path = device.to_string_lossy().into_owned();
+    let pool = format!("{}/{}", thin.volume_group, thin.thin_pool);
+    run_lvm("pvcreate", &["-ff", "-y", &device_path])?;
+    run_lvm("vgcreate", &[&thin.volume_group, &device_path])?;
+    // Leave room for the pool metadata to grow.  Zeroing new chunks only
+    // slows down writes because the filesystem doesn't need it.
+    run_lvm("lvcreate",
+            &["--type",
+              "thin-pool",
+              "--extents",
+              "95%FREE",
+              "--chunksize",
+              &format!("{}k", chunk_size),
+              "--zero",
+              "n",
+              "--name",
+              &thin.thin_pool,
+              &thin.volume_group])?;
+    // A thin LV bigger than its pool fills the pool up and then fails writes
+    let pool_size = thin_pool_size(thin)?;
+    run_lvm("lvcreate",
+            &["--virtualsize",
+              &format!("{}k", pool_size),
+              "--thin",
+              &pool,
+              "--name",
+              &thin.logical_volume])?;
+    Ok(())
+}
+
+/// Add another device to the volume group of a thin brick and grow the pool,
+/// the thin LV and the filesystem mounted at mount_path onto it
+pub fn extend_thin_volume(thin: &ThinVolume,
+                          device: &PathBuf,
+                          mount_path: &str,
+                          filesystem_type: &FilesystemType)
+                          -> Result<(), String> {
+    let device_path = device.to_string_lossy().into_owned();
+    let pool = format!("{}/{}", thin.volume_group, thin.thin_pool);
+    let lv = format!("{}/{}", thin.volume_group, thin.logical_volume);
+    run_lvm("pvcreate", &["-ff", "-y", &device_path])?;
+    run_lvm("vgextend", &[&thin.volume_group, &device_path])?;
+    // Keep the same share free for metadata as create_thin_volume does
+    run_lvm("lvextend", &["--extents", "+95%PVS", &pool, &device_path])?;
+    let pool_size = thin_pool_size(thin)?;
+    run_lvm("lvextend", &["--size", &format!("{}k", pool_size), &lv])?;
+    match *filesystem_type {
+        FilesystemType::Xfs => run_lvm("xfs_growfs", &[mount_path]),
+        FilesystemType::Ext4 => run_lvm("resize2fs", &[&thin.lv_path().to_string_lossy()]),
+        _ => Err("lvm-thin bricks need an xfs or ext4 filesystem".to_string()),
+    }
+}
+
+/// Tear down everything create_thin_volume and extend_thin_volume made so
+/// the devices are empty again
+pub fn remove_thin_volume(device: &PathBuf, thin: &ThinVolume) -> Result<(), String> {
+    let mut physical_volumes: Vec<String> =
+        run_lvm_output("vgs", &["--noheadings", "-o", "pv_name", &thin.volume_group])
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|pv| pv.to_string())
+            .collect();
+    let device_path = device.to_string_lossy().into_owned();
+    if !physical_volumes.contains(&device_path) {
+        physical_volumes.push(device_path);
+    }
+    run_lvm("vgremove", &["-f", "-y", &thin.volume_group])?;
+    for pv in physical_volumes {
+        run_lvm("pvremove", &["-y", &pv])?;
+    }
+    Ok(())
+}
+
 pub fn format_block_device(brick_device: BrickDevice,
                            filesystem: &Filesystem)
                            -> Result<AsyncInit, String> {
-    let device = brick_device.dev_path.clone();
+    // lvm-thin bricks put the filesystem on the thin LV
+    let device = match brick_device.thin_volume {
+        Some(ref thin) => {
+            match filesystem {
+                &Filesystem::Xfs { .. } |
+                &Filesystem::Ext4 { .. } => thin.lv_path(),
+                _ => return Err("lvm-thin bricks need an xfs or ext4 filesystem".to_string()),
+            }
+        }
+        None => brick_device.dev_path.clone(),
+    };
     match filesystem {
         &Filesystem::Btrfs { ref metadata_profile, ref leaf_size, ref node_size } => {
             let arg_list: Vec<String> = vec!["-m".to_string(),
@@ -292,7 +491,7 @@ pub fn format_block_device(brick_device: BrickDevice,
             // Check if mkfs.btrfs is installed
             if !Path::new("/sbin/mkfs.btrfs").exists() {
                 log!("Installing btrfs utils");
-                apt_install(vec!["btrfs-tools"])?;
+                package_manager().install(vec!["btrfs-tools"])?;
             }
             return Ok(AsyncInit {
                           format_child: Command::new("mkfs.btrfs").args(&arg_list)
@@ -329,7 +528,7 @@ pub fn format_block_device(brick_device: BrickDevice,
             // Check if mkfs.xfs is installed
             if !Path::new("/sbin/mkfs.xfs").exists() {
                 log!("Installing xfs utils");
-                apt_install(vec!["xfsprogs"])?;
+                package_manager().install(vec!["xfsprogs"])?;
             }
             let format_handle = Command::new("/sbin/mkfs.xfs").args(&arg_list)
                 .spawn()
@@ -344,7 +543,7 @@ pub fn format_block_device(brick_device: BrickDevice,
             // Check if zfs is installed
             if !Path::new("/sbin/zfs").exists() {
                 log!("Installing zfs utils");
-                apt_install(vec!["zfsutils-linux"])?;
+                package_manager().install(vec!["zfsutils-linux"])?;
             }
             let base_name = device.file_name();
             match base_name {
@@ -425,6 +624,24 @@ pub fn format_block_device(brick_device: BrickDevice,
     }
 }
 
+#[test]
+fn test_thin_volume() {
+    let thin = ThinVolume::for_device(&PathBuf::from("/dev/xvdb")).unwrap();
+    assert_eq!(thin.volume_group, "gluster_xvdb");
+    assert_eq!(thin.lv_path(), PathBuf::from("/dev/gluster_xvdb/brick"));
+
+    assert_eq!(thin_pool_chunk_size(None, None), 256);
+    // 64KiB stripe unit across 10 data disks is a 640KiB stripe
+    assert_eq!(thin_pool_chunk_size(Some(65536), Some(10)), 640);
+    // Rounded up to a multiple of 64KiB
+    assert_eq!(thin_pool_chunk_size(Some(65536), Some(3)) % 64, 0);
+    assert_eq!(thin_pool_chunk_size(Some(4096), Some(2)), 64);
+    assert_eq!(BrickBackend::from_str("lvm-thin"), Ok(BrickBackend::LvmThin));
+    assert_eq!(parse_lv_size("  20967424.00\n"), Ok(20967424));
+    assert!(parse_lv_size("").is_err());
+    assert!(BrickBackend::from_str("lvm").is_err());
+}
+
 #[test]
 fn test_get_device_info() {
     print!("{:?}", get_device_info(&PathBuf::from("/dev/sda1")));
@@ -557,8 +774,9 @@ pub fn get_device_info(device_path: &PathBuf) -> Result<Device, String> {
     return Err(format!("Unable to find device with name {:?}", device_path));
 }
 
-fn scan_devices(devices: Vec<String>) -> Result<Vec<BrickDevice>, String> {
+pub fn scan_devices(devices: Vec<String>) -> Result<Vec<BrickDevice>, String> {
     let mut brick_devices: Vec<BrickDevice> = Vec::new();
+    let backend = BrickBackend::from_str(&get_config_value("brick_backend")?)?;
     for brick in devices {
         let device_path = PathBuf::from(brick);
         // Translate to mount location
@@ -586,12 +804,22 @@ fn scan_devices(devices: Vec<String>) -> Result<Vec<BrickDevice>, String> {
         log!(format!("Checking if {:?} is initialized", &device_path));
         let initialized = device_initialized(&device_path).unwrap_or(false);
         let mount_path = format!("/mnt/{}", brick_filename.to_string_lossy());
+        // Devices keep the layout they were initialized with.  New ones
+        // follow brick_backend.
+        let thin_volume = ThinVolume::for_device(&device_path).and_then(|thin| {
+            if thin.exists() || (!initialized && backend == BrickBackend::LvmThin) {
+                Some(thin)
+            } else {
+                None
+            }
+        });
         brick_devices.push(BrickDevice {
                                is_block_device: is_block_device,
                                // All devices start at initialized is false
                                initialized: initialized,
                                dev_path: device_path.clone(),
                                mount_path: mount_path,
+                               thin_volume: thin_volume,
                            });
     }
     Ok(brick_devices)
@@ -620,6 +848,22 @@ pub fn set_elevator(device_path: &PathBuf,
     Ok(bytes_written)
 }
 
+/// Remove the elevator line that set_elevator added to /etc/rc.local for this device
+pub fn remove_elevator(device_path: &PathBuf) -> Result<usize, ::std::io::Error> {
+    let device_name = match device_path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => "".to_string(),
+    };
+    let mut f = File::open("/etc/rc.local")?;
+    let scheduler_path = format!("/sys/block/{}/queue/scheduler", device_name);
+
+    let mut script = shellscript::parse(&mut f)?;
+    script.commands.retain(|cmd| !cmd.contains(&scheduler_path));
+    let mut f = File::create("/etc/rc.local")?;
+    let bytes_written = script.write(&mut f)?;
+    Ok(bytes_written)
+}
+
 pub fn weekly_defrag(mount: &str,
                      fs_type: &FilesystemType,
                      interval: &str)
@@ -661,6 +905,24 @@ pub fn weekly_defrag(mount: &str,
     Ok(written_bytes)
 }
 
+/// Remove the defrag job that weekly_defrag added to root's crontab for this mount
+pub fn remove_weekly_defrag(mount: &str) -> Result<usize, ::std::io::Error> {
+    let crontab = Path::new("/var/spool/cron/crontabs/root");
+    if !crontab.exists() {
+        return Ok(0);
+    }
+    let mut buff = String::new();
+    let mut f = File::open(crontab)?;
+    f.read_to_string(&mut buff)?;
+    let remaining_jobs: Vec<&str> = buff.split("\n")
+        .filter(|line| !line.ends_with(&format!(" {}", mount)))
+        .collect();
+
+    let mut f = File::create(crontab)?;
+    let written_bytes = f.write(&remaining_jobs.join("\n").as_bytes())?;
+    Ok(written_bytes)
+}
+
 pub fn get_manual_bricks() -> Result<Vec<BrickDevice>, String> {
     log!("Gathering list of manually specified brick devices");
     let manual_config_brick_devices: Vec<String> = get_config_value("brick_devices")
@@ -0,0 +1,179 @@
+extern crate chrono;
+
+use self::chrono::{Datelike, DateTime, Duration, Local, Timelike};
+
+/// A parsed 5 field cron style expression: minute hour day-of-month month day-of-week
+/// This is used to describe maintenance windows.  A time is inside the window
+/// when every field matches it, except that restricting both day fields
+/// matches either day as cron does.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Schedule {
+    pub minutes: Vec<u32>,
+    pub hours: Vec<u32>,
+    pub days_of_month: Vec<u32>,
+    pub months: Vec<u32>,
+    /// 0 is Sunday
+    pub days_of_week: Vec<u32>,
+    /// Whether the day fields were * or started with it.  When both are
+    /// restricted a day matches if either field does, like cron.
+    pub any_day_of_month: bool,
+    pub any_day_of_week: bool,
+}
+
+// Expand one cron field such as *, */15, 1-5, 1-10/2 or 1,3,5 into its values
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let mut values: Vec<u32> = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(pos) => {
+                let step = part[pos + 1..]
+                    .parse::<u32>()
+                    .map_err(|e| format!("Invalid step in cron field {}: {}", field, e))?;
+                if step == 0 {
+                    return Err(format!("Invalid step in cron field {}", field));
+                }
+                (&part[..pos], step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(pos) = range.find('-') {
+            let start = range[..pos]
+                .parse::<u32>()
+                .map_err(|e| format!("Invalid range in cron field {}: {}", field, e))?;
+            let end = range[pos + 1..]
+                .parse::<u32>()
+                .map_err(|e| format!("Invalid range in cron field {}: {}", field, e))?;
+            (start, end)
+        } else {
+            let value = range.parse::<u32>()
+                .map_err(|e| format!("Invalid value in cron field {}: {}", field, e))?;
+            (value, value)
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("Cron field {} is out of range {}-{}", field, min, max));
+        }
+        let mut value = start;
+        while value <= end {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+            value += step;
+        }
+    }
+    values.sort();
+    Ok(values)
+}
+
+impl Schedule {
+    pub fn from_str(s: &str) -> Result<Schedule, String> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Cron expression {} must have 5 fields", s));
+        }
+        // Cron allows 7 to also mean Sunday
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        if days_of_week.contains(&7) {
+            days_of_week.retain(|d| *d != 7);
+            if !days_of_week.contains(&0) {
+                days_of_week.insert(0, 0);
+            }
+        }
+        Ok(Schedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week: days_of_week,
+            any_day_of_month: fields[2].starts_with('*'),
+            any_day_of_week: fields[4].starts_with('*'),
+        })
+    }
+
+    /// Returns true if the time falls inside this schedule
+    pub fn matches(&self, time: &DateTime<Local>) -> bool {
+        let day_of_month = self.days_of_month.contains(&time.day());
+        let day_of_week = self.days_of_week.contains(&time.weekday().num_days_from_sunday());
+        let day = if self.any_day_of_month || self.any_day_of_week {
+            day_of_month && day_of_week
+        } else {
+            day_of_month || day_of_week
+        };
+        self.minutes.contains(&time.minute()) && self.hours.contains(&time.hour()) &&
+        self.months.contains(&time.month()) && day
+    }
+
+    /// Returns true if the schedule matches any minute after `after` up to
+    /// and including `until`.  Hooks like update-status only run every few
+    /// minutes so checking a single time would miss most schedules.  Only
+    /// the last week before `until` is checked.
+    pub fn due_between(&self, after: &DateTime<Local>, until: &DateTime<Local>) -> bool {
+        let earliest = *until - Duration::weeks(1);
+        let start = if *after < earliest { earliest } else { *after };
+        let mut time = match start.with_second(0).and_then(|t| t.with_nanosecond(0)) {
+            Some(time) => time + Duration::minutes(1),
+            None => return false,
+        };
+        while time <= *until {
+            if self.matches(&time) {
+                return true;
+            }
+            time = time + Duration::minutes(1);
+        }
+        false
+    }
+}
+
+#[test]
+fn test_parse_schedule() {
+    let schedule = Schedule::from_str("*/15 1-3 * * 6,7").unwrap();
+    println!("test_parse_schedule: {:?}", schedule);
+    assert_eq!(schedule.minutes, vec![0, 15, 30, 45]);
+    assert_eq!(schedule.hours, vec![1, 2, 3]);
+    assert_eq!(schedule.days_of_month.len(), 31);
+    assert_eq!(schedule.months.len(), 12);
+    assert_eq!(schedule.days_of_week, vec![0, 6]);
+
+    assert!(Schedule::from_str("* * * *").is_err());
+    assert!(Schedule::from_str("61 * * * *").is_err());
+    assert!(Schedule::from_str("*/0 * * * *").is_err());
+}
+
+#[test]
+fn test_schedule_matches() {
+    use self::chrono::TimeZone;
+    // Saturday 2017-06-03 02:10
+    let inside = Local.ymd(2017, 6, 3).and_hms(2, 10, 0);
+    // Saturday 2017-06-03 05:10
+    let outside = Local.ymd(2017, 6, 3).and_hms(5, 10, 0);
+    let schedule = Schedule::from_str("* 1-4 * * 6").unwrap();
+    assert!(schedule.matches(&inside));
+    assert!(!schedule.matches(&outside));
+
+    // Both day fields restricted means either one, so the 1st or any Sunday
+    let schedule = Schedule::from_str("0 3 1 * 0").unwrap();
+    assert!(schedule.matches(&Local.ymd(2017, 6, 1).and_hms(3, 0, 0)));
+    assert!(schedule.matches(&Local.ymd(2017, 6, 4).and_hms(3, 0, 0)));
+    assert!(!schedule.matches(&Local.ymd(2017, 6, 3).and_hms(3, 0, 0)));
+    // With * for the day of month only Sundays match
+    let schedule = Schedule::from_str("0 3 * * 0").unwrap();
+    assert!(!schedule.matches(&Local.ymd(2017, 6, 1).and_hms(3, 0, 0)));
+    assert!(schedule.matches(&Local.ymd(2017, 6, 4).and_hms(3, 0, 0)));
+}
+
+#[test]
+fn test_schedule_due_between() {
+    use self::chrono::TimeZone;
+    let schedule = Schedule::from_str("0 2 * * *").unwrap();
+    let before = Local.ymd(2017, 6, 3).and_hms(1, 57, 30);
+    let after = Local.ymd(2017, 6, 3).and_hms(2, 2, 30);
+    assert!(schedule.due_between(&before, &after));
+    // The run at 02:02 already covered 02:00
+    let later = Local.ymd(2017, 6, 3).and_hms(2, 7, 30);
+    assert!(!schedule.due_between(&after, &later));
+    // Exactly on the minute counts once
+    let on_time = Local.ymd(2017, 6, 3).and_hms(2, 0, 0);
+    assert!(schedule.due_between(&before, &on_time));
+    assert!(!schedule.due_between(&on_time, &after));
+}
@@ -2,12 +2,17 @@
 extern crate ipnetwork;
 extern crate pnet;
 
+use std::fs::File;
 use std::io::{Read, Write};
-use std::net::IpAddr;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::Path;
+use std::process::Command;
 use std::str::FromStr;
 
 use self::ipnetwork::{IpNetworkError, IpNetwork, Ipv4Network, Ipv6Network};
 use self::pnet::datalink::{interfaces, NetworkInterface};
+use super::ganesha::{CTDB_CALLOUT, NfsServer};
+use super::samba::SambaSecurity;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct VirtualIp {
@@ -24,18 +29,63 @@ impl ToString for VirtualIp {
     }
 }
 
-/// Write the ctdb configuration file out to disk
-pub fn render_ctdb_configuration<T: Write>(f: &mut T) -> Result<usize, ::std::io::Error> {
+/// Write the ctdb configuration file out to disk.  With ganesha exporting
+/// the volumes ctdb also moves the NFS service along with the virtual ips.
+/// samba is Some with its security mode when cifs is enabled.  ctdb then runs
+/// smbd, and winbind too for ads.
+pub fn render_ctdb_configuration<T: Write>(f: &mut T,
+                                           nfs_server: &NfsServer,
+                                           samba: Option<&SambaSecurity>)
+                                           -> Result<usize, ::std::io::Error> {
     let mut bytes_written = 0;
     bytes_written += f.write(b"CTDB_LOGGING=file:/var/log/ctdb/ctdb.log\n")?;
     bytes_written += f.write(b"CTDB_NODES=/etc/ctdb/nodes\n")?;
     bytes_written += f.write(b"CTDB_PUBLIC_ADDRESSES=/etc/ctdb/public_addresses\n")?;
     bytes_written += f.write(b"CTDB_RECOVERY_LOCK=/mnt/glusterfs/.CTDB-lockfile\n")?;
+    if *nfs_server == NfsServer::Ganesha {
+        bytes_written += f.write(b"CTDB_MANAGES_NFS=yes\n")?;
+        bytes_written += f.write(format!("CTDB_NFS_CALLOUT={}\n", CTDB_CALLOUT).as_bytes())?;
+        // NFSv4 client state lives on the volume so a takeover can reclaim it
+        bytes_written += f.write(b"CTDB_NFS_STATE_FS_TYPE=glusterfs\n")?;
+        bytes_written += f.write(b"CTDB_NFS_STATE_MNT=/mnt/glusterfs\n")?;
+        bytes_written += f.write(b"CTDB_NFS_SKIP_SHARE_CHECK=yes\n")?;
+    }
+    if let Some(security) = samba {
+        bytes_written += f.write(b"CTDB_MANAGES_SAMBA=yes\n")?;
+        if *security == SambaSecurity::Ads {
+            bytes_written += f.write(b"CTDB_MANAGES_WINBIND=yes\n")?;
+        }
+    }
     Ok(bytes_written)
 }
 
+#[test]
+fn test_render_ctdb_configuration() {
+    let mut buff: Vec<u8> = Vec::new();
+    render_ctdb_configuration(&mut buff, &NfsServer::Gnfs, None).unwrap();
+    let result = String::from_utf8_lossy(&buff).into_owned();
+    assert!(!result.contains("CTDB_MANAGES_NFS"));
+    assert!(!result.contains("CTDB_MANAGES_SAMBA"));
+
+    let mut buff: Vec<u8> = Vec::new();
+    render_ctdb_configuration(&mut buff, &NfsServer::Ganesha, Some(&SambaSecurity::User))
+        .unwrap();
+    let result = String::from_utf8_lossy(&buff).into_owned();
+    assert!(result.contains("CTDB_MANAGES_NFS=yes\n"));
+    assert!(result.contains("CTDB_NFS_CALLOUT=/etc/ctdb/nfs-ganesha-callout\n"));
+    assert!(result.contains("CTDB_MANAGES_SAMBA=yes\n"));
+    assert!(!result.contains("CTDB_MANAGES_WINBIND"));
+
+    let mut buff: Vec<u8> = Vec::new();
+    render_ctdb_configuration(&mut buff, &NfsServer::Disabled, Some(&SambaSecurity::Ads))
+        .unwrap();
+    assert!(String::from_utf8_lossy(&buff).contains("CTDB_MANAGES_WINBIND=yes\n"));
+}
+
 /// Create the public nodes file for ctdb cluster to find all the other peers
-/// the cluster Vec should contain all nodes that are participating in the cluster
+/// the cluster Vec should contain all nodes that are participating in the cluster.
+/// update_ctdb_nodes is used instead once the cluster can change.
+#[allow(dead_code)]
 pub fn render_ctdb_cluster_nodes<T: Write>(f: &mut T,
                                            cluster: &Vec<IpAddr>)
                                            -> Result<usize, ::std::io::Error> {
@@ -95,6 +145,28 @@ pub fn ipnetwork_from_str(s: &str) -> Result<IpNetwork, String> {
     }
 }
 
+/// Parse a virtual_ip_addresses entry.  The interface to put the address on
+/// can be given after an @ like 10.0.0.6/24@bond0.
+pub fn parse_vip(entry: &str) -> Result<(IpNetwork, Option<String>), String> {
+    let mut parts = entry.trim().splitn(2, '@');
+    let network = ipnetwork_from_str(parts.next().unwrap_or_default())?;
+    match parts.next() {
+        Some(interface) if !interface.is_empty() => Ok((network, Some(interface.to_string()))),
+        Some(_) => Err(format!("Missing interface after @ in {}", entry)),
+        None => Ok((network, None)),
+    }
+}
+
+#[test]
+fn test_parse_vip() {
+    let network = ipnetwork_from_str("10.0.0.6/24").unwrap();
+    assert_eq!(parse_vip("10.0.0.6/24"), Ok((network, None)));
+    assert_eq!(parse_vip("10.0.0.6/24@bond0"),
+               Ok((network, Some("bond0".to_string()))));
+    assert!(parse_vip("10.0.0.6/24@").is_err());
+    assert!(parse_vip("bond0@10.0.0.6/24").is_err());
+}
+
 /// Return all virtual ip cidr networks that are being managed by ctdb
 /// located at file f. /etc/ctdb/public_addresses is the usual location
 #[allow(dead_code)]
@@ -296,7 +368,11 @@ pub fn get_ctdb_nodes<T: Read>(f: &mut T) -> Result<Vec<IpAddr>, String> {
     let mut buf = String::new();
     f.read_to_string(&mut buf).map_err(|e| e.to_string())?;
     for line in buf.lines() {
-        let addr = IpAddr::from_str(line).map_err(|e| e.to_string())?;
+        // Departed nodes are commented out
+        if line.trim().starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let addr = IpAddr::from_str(line.trim()).map_err(|e| e.to_string())?;
         addrs.push(addr);
     }
     Ok(addrs)
@@ -327,3 +403,255 @@ fn test_get_ctdb_nodes_v6() {
     let expected = vec![IpAddr::V6(addr1), IpAddr::V6(addr2)];
     assert_eq!(expected, result);
 }
+
+/// Resolve a gluster peer name to the address ctdb should use for it
+pub fn resolve_node(hostname: &str) -> Result<IpAddr, String> {
+    // dig hands back the CNAME chain first.  The address is last.
+    let hostname = hostname.trim().lines().last().unwrap_or_default().trim();
+    if let Ok(addr) = IpAddr::from_str(hostname) {
+        return Ok(addr);
+    }
+    let addrs: Vec<IpAddr> = (hostname, 0)
+        .to_socket_addrs()
+        .map_err(|e| format!("Unable to resolve {}: {}", hostname, e))?
+        .map(|socket_addr| socket_addr.ip())
+        .collect();
+    // Prefer ipv4 like the rest of the cluster
+    match addrs.iter().find(|addr| addr.is_ipv4()).or(addrs.first()) {
+        Some(addr) => Ok(*addr),
+        None => Err(format!("{} has no addresses", hostname)),
+    }
+}
+
+/// Merge the cluster into an existing nodes file.  ctdb numbers the nodes
+/// by their line so departed nodes are commented out instead of removed and
+/// new nodes are appended in address order.  Every node needs the same file
+/// so only the leader merges and the others copy its result.
+pub fn update_ctdb_nodes(existing: &str, cluster: &Vec<IpAddr>) -> String {
+    let mut nodes = String::new();
+    let mut seen: Vec<IpAddr> = Vec::new();
+    for line in existing.lines() {
+        let addr = match IpAddr::from_str(line.trim().trim_left_matches('#').trim()) {
+            Ok(addr) => addr,
+            Err(_) => continue,
+        };
+        if cluster.contains(&addr) && !seen.contains(&addr) {
+            nodes.push_str(&format!("{}\n", addr));
+        } else {
+            nodes.push_str(&format!("#{}\n", addr));
+        }
+        seen.push(addr);
+    }
+    let mut new_nodes: Vec<&IpAddr> = cluster.iter().filter(|addr| !seen.contains(addr)).collect();
+    new_nodes.sort();
+    new_nodes.dedup();
+    for addr in new_nodes {
+        nodes.push_str(&format!("{}\n", addr));
+    }
+    nodes
+}
+
+#[test]
+fn test_update_ctdb_nodes() {
+    let addr = |s: &str| IpAddr::from_str(s).unwrap();
+    // A new node is appended and a departed one is commented out in place
+    let existing = "10.0.0.1\n10.0.0.2\n10.0.0.3\n";
+    let cluster = vec![addr("10.0.0.4"), addr("10.0.0.3"), addr("10.0.0.1")];
+    assert_eq!(update_ctdb_nodes(existing, &cluster),
+               "10.0.0.1\n#10.0.0.2\n10.0.0.3\n10.0.0.4\n");
+    // A node that comes back takes its old place
+    let existing = "10.0.0.1\n#10.0.0.2\n";
+    let cluster = vec![addr("10.0.0.2"), addr("10.0.0.1")];
+    assert_eq!(update_ctdb_nodes(existing, &cluster), "10.0.0.1\n10.0.0.2\n");
+    // New nodes go in address order whatever order the pool lists them
+    let existing = "10.0.0.1\n";
+    let cluster = vec![addr("10.0.0.9"), addr("10.0.0.1"), addr("10.0.0.5")];
+    assert_eq!(update_ctdb_nodes(existing, &cluster), "10.0.0.1\n10.0.0.5\n10.0.0.9\n");
+    let cluster = vec![addr("10.0.0.2"), addr("10.0.0.1")];
+    // Nothing to merge with.  Every unit seeds the same order.
+    assert_eq!(update_ctdb_nodes("", &cluster), "10.0.0.1\n10.0.0.2\n");
+}
+
+/// Write contents to path unless it already holds exactly that.  Returns
+/// whether the file changed.
+pub fn write_if_changed(path: &str, contents: &[u8]) -> Result<bool, String> {
+    if Path::new(path).exists() {
+        let mut existing: Vec<u8> = Vec::new();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut existing))
+            .map_err(|e| e.to_string())?;
+        if existing == contents {
+            return Ok(false);
+        }
+    }
+    log!(format!("writing {}", path));
+    let mut f = File::create(path).map_err(|e| e.to_string())?;
+    f.write_all(contents).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+fn run_ctdb(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("ctdb").args(args).output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether the local ctdb daemon is up and answering
+pub fn ctdb_running() -> bool {
+    run_ctdb(&["ping"]).is_ok()
+}
+
+/// Have the running cluster pick up changes to the nodes file
+pub fn reload_nodes() -> Result<(), String> {
+    log!("Reloading ctdb nodes", Info);
+    run_ctdb(&["reloadnodes"])?;
+    Ok(())
+}
+
+/// Have the running cluster pick up changes to the public addresses file
+pub fn reload_ips() -> Result<(), String> {
+    log!("Reloading ctdb public addresses", Info);
+    run_ctdb(&["reloadips"])?;
+    Ok(())
+}
+
+/// A node as ctdb status describes it
+#[derive(Debug, Eq, PartialEq)]
+pub struct CtdbNode {
+    pub pnn: u32,
+    pub address: IpAddr,
+    /// OK or a combination of BANNED, DISABLED, UNHEALTHY, DISCONNECTED,
+    /// STOPPED and INACTIVE
+    pub flags: Vec<String>,
+    pub this_node: bool,
+}
+
+impl CtdbNode {
+    pub fn healthy(&self) -> bool {
+        self.flags.iter().all(|flag| flag == "OK")
+    }
+
+    pub fn banned(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "BANNED")
+    }
+}
+
+// Number of nodes:3
+// pnn:0 10.0.0.1         OK (THIS NODE)
+// pnn:1 10.0.0.2         BANNED|UNHEALTHY
+// pnn:2 10.0.0.3         DISCONNECTED|UNHEALTHY|INACTIVE
+// Generation:1362079228
+// Size:3
+// Recovery mode:NORMAL (0)
+// Recovery master:0
+pub fn parse_ctdb_status(output: &str) -> Result<Vec<CtdbNode>, String> {
+    let mut nodes: Vec<CtdbNode> = Vec::new();
+    for line in output.lines() {
+        if !line.starts_with("pnn:") {
+            continue;
+        }
+        let parts: Vec<&str> = line["pnn:".len()..].split_whitespace().collect();
+        if parts.len() < 3 {
+            return Err(format!("Unable to parse ctdb node: {}", line));
+        }
+        nodes.push(CtdbNode {
+            pnn: u32::from_str(parts[0]).map_err(|e| e.to_string())?,
+            address: IpAddr::from_str(parts[1]).map_err(|e| e.to_string())?,
+            flags: parts[2].split('|').map(|flag| flag.to_string()).collect(),
+            this_node: line.contains("(THIS NODE)"),
+        });
+    }
+    Ok(nodes)
+}
+
+// Public IPs on node 0
+// 10.0.0.6 0
+// 10.0.0.7 -1
+/// The public addresses and the pnn hosting each.  Unassigned ones are -1.
+pub fn parse_ctdb_ip(output: &str) -> Vec<(IpAddr, i32)> {
+    output.lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() != 2 {
+                return None;
+            }
+            match (IpAddr::from_str(parts[0]), i32::from_str(parts[1])) {
+                (Ok(addr), Ok(pnn)) => Some((addr, pnn)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_ctdb_status() {
+    let status = "Number of nodes:3
+pnn:0 10.0.0.1         OK (THIS NODE)
+pnn:1 10.0.0.2         BANNED|UNHEALTHY
+pnn:2 10.0.0.3         DISCONNECTED|UNHEALTHY|INACTIVE
+Generation:1362079228
+Size:3
+hash:0 lmaster:0
+hash:1 lmaster:1
+hash:2 lmaster:2
+Recovery mode:NORMAL (0)
+Recovery master:0
+";
+    let nodes = parse_ctdb_status(status).unwrap();
+    assert_eq!(nodes.len(), 3);
+    assert_eq!(nodes[0],
+               CtdbNode {
+                   pnn: 0,
+                   address: IpAddr::from_str("10.0.0.1").unwrap(),
+                   flags: vec!["OK".to_string()],
+                   this_node: true,
+               });
+    assert!(nodes[0].healthy());
+    assert!(nodes[1].banned());
+    assert!(!nodes[2].healthy() && !nodes[2].banned());
+
+    let ips = parse_ctdb_ip("Public IPs on node 0\n10.0.0.6 0\n10.0.0.7 -1\n");
+    assert_eq!(ips,
+               vec![(IpAddr::from_str("10.0.0.6").unwrap(), 0),
+                    (IpAddr::from_str("10.0.0.7").unwrap(), -1)]);
+}
+
+/// The nodes of the cluster with their health
+pub fn ctdb_nodes() -> Result<Vec<CtdbNode>, String> {
+    parse_ctdb_status(&run_ctdb(&["status"])?)
+}
+
+/// Every public address in the cluster and the pnn hosting it
+pub fn public_ips() -> Result<Vec<(IpAddr, i32)>, String> {
+    Ok(parse_ctdb_ip(&run_ctdb(&["ip", "all"])?))
+}
+
+/// Move a public address to the node with pnn
+pub fn move_ip(address: &IpAddr, pnn: u32) -> Result<(), String> {
+    log!(format!("Moving {} to ctdb node {}", address, pnn), Info);
+    run_ctdb(&["moveip", &address.to_string(), &pnn.to_string()])?;
+    Ok(())
+}
+
+/// A short description of the ctdb cluster for the unit status
+pub fn ctdb_summary(nodes: &Vec<CtdbNode>) -> String {
+    let healthy = nodes.iter().filter(|node| node.healthy()).count();
+    let banned = nodes.iter().filter(|node| node.banned()).count();
+    let mut summary = format!("ctdb {}/{} healthy", healthy, nodes.len());
+    if banned > 0 {
+        summary = format!("{}, {} banned", summary, banned);
+    }
+    if let Some(local) = nodes.iter().find(|node| node.this_node) {
+        let hosted: Vec<String> = public_ips()
+            .unwrap_or_default()
+            .iter()
+            .filter(|&&(_, pnn)| pnn == local.pnn as i32)
+            .map(|&(addr, _)| addr.to_string())
+            .collect();
+        if !hosted.is_empty() {
+            summary = format!("{}, hosting {}", summary, hosted.join(" "));
+        }
+    }
+    summary
+}
@@ -0,0 +1,277 @@
+use std::fs::{copy, set_permissions, File, Permissions};
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use super::apt::{service_restart, service_start, service_stop};
+use super::{get_config_flag, get_config_value};
+use super::juju;
+use super::package::package_manager;
+use super::volumes::VolumeConfig;
+
+/// Where ctdb's nfs event script looks for the ganesha callout
+pub const CTDB_CALLOUT: &'static str = "/etc/ctdb/nfs-ganesha-callout";
+const CTDB_CALLOUT_EXAMPLE: &'static str = "/usr/share/doc/ctdb/examples/nfs-ganesha-callout";
+/// Debian packaging compresses the docs
+const CTDB_CALLOUT_EXAMPLE_GZ: &'static str = "/usr/share/doc/ctdb/examples/nfs-ganesha-callout.gz";
+
+/// Which NFS server exports the volumes
+#[derive(Debug, Eq, PartialEq)]
+pub enum NfsServer {
+    /// The NFSv3 server built into gluster.  Gluster 3.8+ turns it off by
+    /// default and later releases drop it
+    Gnfs,
+    /// NFS-Ganesha with the FSAL_GLUSTER backend
+    Ganesha,
+    /// No NFS at all
+    Disabled,
+}
+
+impl FromStr for NfsServer {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gnfs" => Ok(NfsServer::Gnfs),
+            "ganesha" => Ok(NfsServer::Ganesha),
+            "none" => Ok(NfsServer::Disabled),
+            _ => Err(format!("Unknown nfs_server {}.  Use gnfs, ganesha or none", s)),
+        }
+    }
+}
+
+/// The configured NFS server.  Defaults to gnfs.
+pub fn get_nfs_server() -> Result<NfsServer, String> {
+    let value = get_config_value("nfs_server")?;
+    if value.is_empty() {
+        return Ok(NfsServer::Gnfs);
+    }
+    NfsServer::from_str(&value)
+}
+
+/// The part this unit plays in pNFS.  The metadata server hands out layouts
+/// and every data server serves reads and writes for its own bricks.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Pnfs {
+    Disabled,
+    DataServer,
+    MetadataServer,
+}
+
+/// Write the ganesha configuration out.  Every volume is exported over
+/// NFSv3 and NFSv4.1 under its own name.
+pub fn render_ganesha_configuration<T: Write>(f: &mut T,
+                                              volumes: &Vec<VolumeConfig>,
+                                              pnfs: &Pnfs)
+                                              -> Result<usize, ::std::io::Error> {
+    let mut bytes_written = 0;
+    bytes_written += f.write(b"NFS_CORE_PARAM {\n")?;
+    bytes_written += f.write(b"    Protocols = 3, 4;\n")?;
+    bytes_written += f.write(b"    Enable_NLM = true;\n")?;
+    bytes_written += f.write(b"}\n")?;
+    bytes_written += f.write(b"NFSV4 {\n")?;
+    bytes_written += f.write(b"    Minor_Versions = 0, 1;\n")?;
+    // Long enough for clients to reclaim their locks after a VIP moves
+    bytes_written += f.write(b"    Grace_Period = 90;\n")?;
+    bytes_written += f.write(b"}\n")?;
+    if *pnfs != Pnfs::Disabled {
+        bytes_written += f.write(b"GLUSTER {\n")?;
+        bytes_written += f.write(format!("    PNFS_MDS = {};\n",
+                                         *pnfs == Pnfs::MetadataServer)
+                                     .as_bytes())?;
+        bytes_written += f.write(b"    PNFS_DS = true;\n")?;
+        bytes_written += f.write(b"}\n")?;
+    }
+    for (index, volume) in volumes.iter().enumerate() {
+        bytes_written += f.write(b"EXPORT {\n")?;
+        // Export ids have to stay the same across units for failover
+        bytes_written += f.write(format!("    Export_Id = {};\n", index + 1).as_bytes())?;
+        bytes_written += f.write(format!("    Path = \"/{}\";\n", volume.name).as_bytes())?;
+        bytes_written += f.write(format!("    Pseudo = \"/{}\";\n", volume.name).as_bytes())?;
+        bytes_written += f.write(b"    Access_Type = RW;\n")?;
+        bytes_written += f.write(b"    Squash = No_root_squash;\n")?;
+        bytes_written += f.write(b"    Disable_ACL = true;\n")?;
+        bytes_written += f.write(b"    Protocols = 3, 4;\n")?;
+        bytes_written += f.write(b"    Transports = UDP, TCP;\n")?;
+        bytes_written += f.write(b"    SecType = sys;\n")?;
+        bytes_written += f.write(b"    FSAL {\n")?;
+        bytes_written += f.write(b"        Name = GLUSTER;\n")?;
+        bytes_written += f.write(b"        Hostname = localhost;\n")?;
+        bytes_written += f.write(format!("        Volume = \"{}\";\n", volume.name).as_bytes())?;
+        // Lets the other ganesha heads see lease and lock changes
+        bytes_written += f.write(b"        enable_upcall = true;\n")?;
+        bytes_written += f.write(b"    }\n")?;
+        bytes_written += f.write(b"}\n")?;
+    }
+    Ok(bytes_written)
+}
+
+fn ganesha_config_changed(volumes: &Vec<VolumeConfig>,
+                          pnfs: &Pnfs)
+                          -> Result<bool, ::std::io::Error> {
+    if Path::new("/etc/ganesha/ganesha.conf").exists() {
+        let mut f = File::open("/etc/ganesha/ganesha.conf")?;
+        let mut existing_config: Vec<u8> = Vec::new();
+        f.read_to_end(&mut existing_config)?;
+        let mut new_config: Vec<u8> = Vec::new();
+        let _ = render_ganesha_configuration(&mut new_config, volumes, pnfs)?;
+        return Ok(new_config != existing_config);
+    }
+    // Config doesn't exist.
+    return Ok(true);
+}
+
+/// Install and configure NFS-Ganesha when nfs_server is ganesha.  With
+/// virtual_ip_addresses set ctdb starts and stops ganesha as the addresses
+/// move, otherwise it runs on every unit.
+pub fn setup_ganesha(volumes: &Vec<VolumeConfig>) -> Result<(), String> {
+    if get_nfs_server()? != NfsServer::Ganesha {
+        if Path::new("/etc/ganesha/ganesha.conf").exists() {
+            log!("nfs_server is no longer ganesha.  Stopping nfs-ganesha");
+            service_stop("nfs-ganesha")?;
+        }
+        return Ok(());
+    }
+    let pnfs = if !get_config_flag("nfs_ganesha_pnfs")? {
+        Pnfs::Disabled
+    } else if juju::is_leader().map_err(|e| e.to_string())? {
+        // Only one metadata server per cluster
+        Pnfs::MetadataServer
+    } else {
+        Pnfs::DataServer
+    };
+    if !ganesha_config_changed(volumes, &pnfs).map_err(|e| e.to_string())? {
+        log!("NFS-Ganesha is already setup.  Not reinstalling");
+        return Ok(());
+    }
+
+    // A running ganesha has to be restarted to pick up a changed config
+    let reconfigured = Path::new("/etc/ganesha/ganesha.conf").exists();
+    status_set!(Maintenance "Installing NFS-Ganesha");
+    package_manager().install(vec!["nfs-ganesha", "nfs-ganesha-gluster"])?;
+    status_set!(Maintenance "Configuring NFS-Ganesha");
+    log!("Setting up NFS-Ganesha");
+    let mut ganesha_conf = File::create("/etc/ganesha/ganesha.conf").map_err(|e| e.to_string())?;
+    let bytes_written = render_ganesha_configuration(&mut ganesha_conf, volumes, &pnfs)
+        .map_err(|e| e.to_string())?;
+    log!(format!("Wrote {} bytes to /etc/ganesha/ganesha.conf", bytes_written));
+
+    let vips = juju::config_get("virtual_ip_addresses").map_err(|e| e.to_string())?;
+    if vips.is_some() {
+        // ctdb's nfs event script drives ganesha through the callout
+        install_callout()?;
+        if reconfigured && ganesha_running() {
+            log!("Restarting NFS-Ganesha through the ctdb callout");
+            run_callout("stop")?;
+            run_callout("start")?;
+        }
+        return Ok(());
+    }
+    log!("Starting NFS-Ganesha service");
+    status_set!(Maintenance "Starting NFS-Ganesha");
+    if reconfigured {
+        // ganesha only reads its config at startup
+        service_restart("nfs-ganesha")?;
+    } else {
+        service_start("nfs-ganesha")?;
+    }
+    Ok(())
+}
+
+// The ctdb packages only ship the callout as an example.  Depending on the
+// distribution it is gzipped and not executable.
+fn install_callout() -> Result<(), String> {
+    if !Path::new(CTDB_CALLOUT).exists() {
+        log!(format!("Installing {}", CTDB_CALLOUT));
+        if Path::new(CTDB_CALLOUT_EXAMPLE).exists() {
+            copy(CTDB_CALLOUT_EXAMPLE, CTDB_CALLOUT).map_err(|e| e.to_string())?;
+        } else if Path::new(CTDB_CALLOUT_EXAMPLE_GZ).exists() {
+            let output = Command::new("gunzip").arg("-c")
+                .arg(CTDB_CALLOUT_EXAMPLE_GZ)
+                .output()
+                .map_err(|e| e.to_string())?;
+            if !output.status.success() {
+                return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+            }
+            File::create(CTDB_CALLOUT)
+                .and_then(|mut f| f.write_all(&output.stdout))
+                .map_err(|e| e.to_string())?;
+        } else {
+            return Err(format!("ctdb didn't ship {}", CTDB_CALLOUT_EXAMPLE));
+        }
+    }
+    set_permissions(CTDB_CALLOUT, Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ctdb only runs ganesha on the nodes that are healthy
+fn ganesha_running() -> bool {
+    Command::new("pidof")
+        .arg("ganesha.nfsd")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+fn run_callout(operation: &str) -> Result<(), String> {
+    let output = Command::new(CTDB_CALLOUT).arg(operation)
+        .arg("nfs")
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_render_ganesha_configuration() {
+    let volumes = vec![VolumeConfig {
+                           name: "test".to_string(),
+                           volume_type: "Replicate".to_string(),
+                           replicas: 3,
+                           arbiter_count: 1,
+                           stripe_count: 3,
+                           disperse_data: 2,
+                           disperse_redundancy: 1,
+                           brick_dir: None,
+                           mount_point: "/mnt/glusterfs".to_string(),
+                       }];
+    let mut buff: Vec<u8> = Vec::new();
+    render_ganesha_configuration(&mut buff, &volumes, &Pnfs::MetadataServer).unwrap();
+    let result = String::from_utf8_lossy(&buff).into_owned();
+    let expected = r#"NFS_CORE_PARAM {
+    Protocols = 3, 4;
+    Enable_NLM = true;
+}
+NFSV4 {
+    Minor_Versions = 0, 1;
+    Grace_Period = 90;
+}
+GLUSTER {
+    PNFS_MDS = true;
+    PNFS_DS = true;
+}
+EXPORT {
+    Export_Id = 1;
+    Path = "/test";
+    Pseudo = "/test";
+    Access_Type = RW;
+    Squash = No_root_squash;
+    Disable_ACL = true;
+    Protocols = 3, 4;
+    Transports = UDP, TCP;
+    SecType = sys;
+    FSAL {
+        Name = GLUSTER;
+        Hostname = localhost;
+        Volume = "test";
+        enable_upcall = true;
+    }
+}
+"#;
+    assert_eq!(expected, result);
+    assert_eq!(NfsServer::from_str("none"), Ok(NfsServer::Disabled));
+    assert!(NfsServer::from_str("nfs").is_err());
+}
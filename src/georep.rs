@@ -0,0 +1,433 @@
+extern crate chrono;
+extern crate juju;
+
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::process::Command;
+
+use self::chrono::{Local, NaiveDateTime};
+use self::juju::unitdata;
+
+/// One row of geo-replication status detail.  Every master brick of a
+/// session has its own worker.
+#[derive(Debug, PartialEq)]
+pub struct GeorepWorker {
+    pub master_node: String,
+    pub master_volume: String,
+    pub master_brick: String,
+    /// host::volume without the ssh:// prefix newer versions add
+    pub slave: String,
+    pub slave_node: String,
+    /// Active, Passive, Faulty, Paused, Stopped, Created or Initializing...
+    pub status: String,
+    pub crawl_status: String,
+    /// Time of the last change synced to the slave in the master's local
+    /// time.  None until the first sync.
+    pub last_synced: Option<NaiveDateTime>,
+    /// None when no checkpoint is set
+    pub checkpoint_completed: Option<bool>,
+}
+
+fn run_georep(master_volume: &str, slave: &str, args: Vec<&str>) -> Result<String, String> {
+    let mut cmd = Command::new("gluster");
+    cmd.arg("--mode=script");
+    cmd.arg("volume");
+    cmd.arg("geo-replication");
+    cmd.arg(master_volume);
+    cmd.arg(slave);
+    cmd.args(&args);
+    log!(format!("geo-replication cmd: {:?}", cmd), Debug);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Create the session and push the common pem key to every slave node.
+/// This needs passwordless root ssh to the slave host.
+pub fn create_session(master_volume: &str, slave: &str) -> Result<(), String> {
+    // Generates the common secret pem that push-pem hands out
+    let output = Command::new("gluster")
+        .args(&["--mode=script", "system::", "execute", "gsec_create"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    run_georep(master_volume, slave, vec!["create", "push-pem"])?;
+    Ok(())
+}
+
+pub fn start_session(master_volume: &str, slave: &str) -> Result<(), String> {
+    run_georep(master_volume, slave, vec!["start"])?;
+    Ok(())
+}
+
+/// Stop syncing without tearing down the session
+pub fn pause_session(master_volume: &str, slave: &str) -> Result<(), String> {
+    run_georep(master_volume, slave, vec!["pause"])?;
+    Ok(())
+}
+
+pub fn resume_session(master_volume: &str, slave: &str) -> Result<(), String> {
+    run_georep(master_volume, slave, vec!["resume"])?;
+    Ok(())
+}
+
+/// Mark the current time.  status detail reports the checkpoint as completed
+/// once everything written before it reached the slave.
+pub fn set_checkpoint(master_volume: &str, slave: &str) -> Result<(), String> {
+    run_georep(master_volume, slave, vec!["config", "checkpoint", "now"])?;
+    Ok(())
+}
+
+/// The workers of every geo-replication session this cluster is the master
+/// of.  Slave clusters have none.
+pub fn georep_status() -> Result<Vec<GeorepWorker>, String> {
+    let output = Command::new("gluster")
+        .args(&["--mode=script", "volume", "geo-replication", "status", "detail"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if stdout.contains("No active geo-replication sessions") {
+        return Ok(Vec::new());
+    }
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(parse_georep_status(&stdout))
+}
+
+/// root's ssh public key.  The key pair is created the first time.  The
+/// master needs root ssh access to the slave to create a session.
+pub fn root_ssh_public_key() -> Result<String, String> {
+    if !Path::new("/root/.ssh/id_rsa.pub").exists() {
+        let output = Command::new("ssh-keygen")
+            .args(&["-q", "-t", "rsa", "-b", "4096", "-N", "", "-f", "/root/.ssh/id_rsa"])
+            .output()
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+        }
+    }
+    let mut key = String::new();
+    File::open("/root/.ssh/id_rsa.pub")
+        .and_then(|mut f| f.read_to_string(&mut key))
+        .map_err(|e| e.to_string())?;
+    Ok(key.trim().to_string())
+}
+
+/// Options put in front of master keys in authorized_keys.  push-pem and
+/// its checks run ordinary commands over the login so no forced command can
+/// be set.  Once the session exists gluster's own gsyncd-only keys take over
+/// and the master keys are revoked.
+const KEY_OPTIONS: &'static str = "no-port-forwarding,no-X11-forwarding,no-agent-forwarding,\
+                                   no-pty";
+
+/// unitdata key of the master keys this unit put in authorized_keys
+const AUTHORIZED_KEYS_KEY: &'static str = "georep-authorized-keys";
+
+/// Add the keys that are missing from an authorized_keys file and drop the
+/// lines of the revoked ones.  Other lines are left alone.
+pub fn update_authorized_keys(contents: &str, add: &Vec<String>, revoke: &Vec<String>) -> String {
+    let mut lines: Vec<String> = contents.lines()
+        .filter(|line| !revoke.iter().any(|key| line.trim().ends_with(key.trim())))
+        .map(|line| line.to_string())
+        .collect();
+    for key in add {
+        if !lines.iter().any(|line| line.trim().ends_with(key.trim())) {
+            lines.push(format!("{} {}", KEY_OPTIONS, key.trim()));
+        }
+    }
+    let mut updated = lines.join("\n");
+    if !updated.is_empty() {
+        updated.push('\n');
+    }
+    updated
+}
+
+/// Let exactly the given master keys ssh in as root.  Keys this unit
+/// authorized before that aren't in the list any more are revoked.
+pub fn set_authorized_master_keys(keys: &Vec<String>) -> Result<(), String> {
+    let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+    let revoke: Vec<String> = unit_storage.get::<Vec<String>>(AUTHORIZED_KEYS_KEY)
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|key| !keys.contains(key))
+        .collect();
+    create_dir_all("/root/.ssh").map_err(|e| e.to_string())?;
+    let mut authorized_keys = String::new();
+    if let Ok(mut f) = File::open("/root/.ssh/authorized_keys") {
+        f.read_to_string(&mut authorized_keys).map_err(|e| e.to_string())?;
+    }
+    let updated = update_authorized_keys(&authorized_keys, keys, &revoke);
+    if updated != authorized_keys {
+        for key in &revoke {
+            log!(format!("Revoking ssh key {}", key), Info);
+        }
+        let mut f = OpenOptions::new().create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o600)
+            .open("/root/.ssh/authorized_keys")
+            .map_err(|e| e.to_string())?;
+        f.write_all(updated.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    unit_storage.set(AUTHORIZED_KEYS_KEY, keys).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The distinct (master volume, slave) sessions in the order they're listed
+pub fn sessions(workers: &Vec<GeorepWorker>) -> Vec<(String, String)> {
+    let mut sessions: Vec<(String, String)> = Vec::new();
+    for worker in workers {
+        let session = (worker.master_volume.clone(), worker.slave.clone());
+        if !sessions.contains(&session) {
+            sessions.push(session);
+        }
+    }
+    sessions
+}
+
+fn session_workers<'a>(workers: &'a Vec<GeorepWorker>,
+                       master_volume: &str,
+                       slave: &str)
+                       -> Vec<&'a GeorepWorker> {
+    workers.iter()
+        .filter(|worker| worker.master_volume == master_volume && worker.slave == slave)
+        .collect()
+}
+
+/// One status for the whole session.  A single faulty worker means the
+/// session is Faulty.  Otherwise it's Active while any worker syncs.
+pub fn session_status(workers: &Vec<GeorepWorker>, master_volume: &str, slave: &str) -> String {
+    let statuses: Vec<&str> = session_workers(workers, master_volume, slave)
+        .iter()
+        .map(|worker| worker.status.as_ref())
+        .collect();
+    for status in &["Faulty", "Active"] {
+        if statuses.contains(status) {
+            return status.to_string();
+        }
+    }
+    match statuses.first() {
+        Some(status) => status.to_string(),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// How many seconds the slave is behind.  This is the age of the oldest last
+/// synced time across the active workers.  None until every active worker
+/// synced once.
+pub fn sync_lag(workers: &Vec<GeorepWorker>,
+                master_volume: &str,
+                slave: &str,
+                now: NaiveDateTime)
+                -> Option<i64> {
+    let active: Vec<&GeorepWorker> = session_workers(workers, master_volume, slave)
+        .into_iter()
+        .filter(|worker| worker.status == "Active")
+        .collect();
+    if active.is_empty() {
+        return None;
+    }
+    let mut lag = 0;
+    for worker in active {
+        match worker.last_synced {
+            Some(synced) => {
+                lag = ::std::cmp::max(lag, now.signed_duration_since(synced).num_seconds())
+            }
+            None => return None,
+        }
+    }
+    Some(lag)
+}
+
+/// Whether the last checkpoint reached the slave on every worker that has
+/// one.  None when no checkpoint was set.
+pub fn checkpoint_completed(workers: &Vec<GeorepWorker>,
+                            master_volume: &str,
+                            slave: &str)
+                            -> Option<bool> {
+    let checkpoints: Vec<bool> = session_workers(workers, master_volume, slave)
+        .iter()
+        .filter(|worker| worker.status == "Active")
+        .filter_map(|worker| worker.checkpoint_completed)
+        .collect();
+    if checkpoints.is_empty() {
+        return None;
+    }
+    Some(checkpoints.iter().all(|completed| *completed))
+}
+
+/// A short description of every session for the unit status
+pub fn georep_summary() -> Option<String> {
+    let workers = match georep_status() {
+        Ok(workers) => workers,
+        Err(e) => {
+            log!(format!("Geo-replication status failed: {}", e), Error);
+            return None;
+        }
+    };
+    let now = Local::now().naive_local();
+    let summaries: Vec<String> = sessions(&workers)
+        .iter()
+        .map(|&(ref master_volume, ref slave)| {
+            let status = session_status(&workers, master_volume, slave);
+            match sync_lag(&workers, master_volume, slave, now) {
+                Some(lag) => {
+                    format!("geo-replication {} to {} {} ({}s behind)",
+                            master_volume,
+                            slave,
+                            status,
+                            lag)
+                }
+                None => format!("geo-replication {} to {} {}", master_volume, slave, status),
+            }
+        })
+        .collect();
+    if summaries.is_empty() {
+        return None;
+    }
+    Some(summaries.join(", "))
+}
+
+// Columns are padded to their widest value so the header gives every
+// column's offset.  Titles and values can contain single spaces.
+fn column_offsets(header: &str) -> Vec<(String, usize)> {
+    let mut columns: Vec<(String, usize)> = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut spaces = 0;
+    for (i, c) in header.char_indices() {
+        if c == ' ' {
+            spaces += 1;
+            continue;
+        }
+        if start.is_none() || spaces >= 2 {
+            if let Some(s) = start {
+                columns.push((header[s..i].trim().to_string(), s));
+            }
+            start = Some(i);
+        }
+        spaces = 0;
+    }
+    if let Some(s) = start {
+        columns.push((header[s..].trim().to_string(), s));
+    }
+    columns
+}
+
+fn optional(value: Option<&String>) -> Option<&str> {
+    match value {
+        Some(value) if !value.is_empty() && value != "N/A" => Some(value),
+        _ => None,
+    }
+}
+
+// Wrapped here, gluster prints every row on one line:
+// MASTER NODE    MASTER VOL    MASTER BRICK    SLAVE USER    SLAVE
+//    SLAVE NODE    STATUS    CRAWL STATUS       LAST_SYNCED            ENTRY    DATA
+//    META    FAILURES    CHECKPOINT TIME    CHECKPOINT COMPLETED
+//    CHECKPOINT COMPLETION TIME
+// ------------------------------------------------------------------------------
+// host-1         test          /mnt/xvdb       root          ssh://host-3::backup
+//    host-3        Active    Changelog Crawl    2017-06-01 10:39:03    0        0
+//    0       0           N/A                N/A
+//    N/A
+pub fn parse_georep_status(output: &str) -> Vec<GeorepWorker> {
+    let mut workers: Vec<GeorepWorker> = Vec::new();
+    let mut columns: Vec<(String, usize)> = Vec::new();
+    for line in output.lines() {
+        if line.trim().is_empty() || line.starts_with("---") {
+            continue;
+        }
+        if line.trim_left().starts_with("MASTER NODE") {
+            columns = column_offsets(line);
+            continue;
+        }
+        if columns.is_empty() {
+            continue;
+        }
+        let mut fields: HashMap<String, String> = HashMap::new();
+        for (i, &(ref title, start)) in columns.iter().enumerate() {
+            let end = match columns.get(i + 1) {
+                Some(&(_, next)) => ::std::cmp::min(next, line.len()),
+                None => line.len(),
+            };
+            let value = if start < end { line[start..end].trim() } else { "" };
+            fields.insert(title.clone(), value.to_string());
+        }
+        let field = |title: &str| fields.get(title).cloned().unwrap_or_default();
+        workers.push(GeorepWorker {
+            master_node: field("MASTER NODE"),
+            master_volume: field("MASTER VOL"),
+            master_brick: field("MASTER BRICK"),
+            slave: field("SLAVE").trim_left_matches("ssh://").to_string(),
+            slave_node: field("SLAVE NODE"),
+            status: field("STATUS"),
+            crawl_status: field("CRAWL STATUS"),
+            last_synced: optional(fields.get("LAST_SYNCED"))
+                .and_then(|t| NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S").ok()),
+            checkpoint_completed: optional(fields.get("CHECKPOINT COMPLETED"))
+                .map(|completed| completed == "Yes"),
+        });
+    }
+    workers
+}
+
+#[test]
+fn test_parse_georep_status() {
+    let output = r#"
+MASTER NODE    MASTER VOL    MASTER BRICK    SLAVE USER    SLAVE                  SLAVE NODE    STATUS     CRAWL STATUS       LAST_SYNCED            ENTRY    DATA    META    FAILURES    CHECKPOINT TIME        CHECKPOINT COMPLETED    CHECKPOINT COMPLETION TIME
+-----------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+host-1         test          /mnt/xvdb       root          ssh://host-3::backup   host-3        Active     Changelog Crawl    2017-06-01 10:39:03    0        0       0       0           2017-06-01 10:30:00    Yes                     2017-06-01 10:35:12
+host-2         test          /mnt/xvdb       root          ssh://host-3::backup   host-4        Passive    N/A                N/A                    N/A      N/A     N/A     N/A         N/A                    N/A                     N/A
+host-1         scratch       /mnt/xvdc       root          ssh://host-3::archive  host-3        Faulty     N/A                N/A                    N/A      N/A     N/A     N/A         N/A                    N/A                     N/A
+"#;
+    let workers = parse_georep_status(output);
+    assert_eq!(workers.len(), 3);
+    assert_eq!(workers[0],
+               GeorepWorker {
+                   master_node: "host-1".to_string(),
+                   master_volume: "test".to_string(),
+                   master_brick: "/mnt/xvdb".to_string(),
+                   slave: "host-3::backup".to_string(),
+                   slave_node: "host-3".to_string(),
+                   status: "Active".to_string(),
+                   crawl_status: "Changelog Crawl".to_string(),
+                   last_synced: Some(NaiveDateTime::parse_from_str("2017-06-01 10:39:03",
+                                                                   "%Y-%m-%d %H:%M:%S")
+                       .unwrap()),
+                   checkpoint_completed: Some(true),
+               });
+    assert_eq!(workers[1].last_synced, None);
+    assert_eq!(sessions(&workers),
+               vec![("test".to_string(), "host-3::backup".to_string()),
+                    ("scratch".to_string(), "host-3::archive".to_string())]);
+    assert_eq!(session_status(&workers, "test", "host-3::backup"), "Active");
+    assert_eq!(session_status(&workers, "scratch", "host-3::archive"), "Faulty");
+    assert_eq!(checkpoint_completed(&workers, "test", "host-3::backup"), Some(true));
+
+    let now = NaiveDateTime::parse_from_str("2017-06-01 10:41:03", "%Y-%m-%d %H:%M:%S").unwrap();
+    assert_eq!(sync_lag(&workers, "test", "host-3::backup", now), Some(120));
+    assert_eq!(sync_lag(&workers, "scratch", "host-3::archive", now), None);
+}
+
+#[test]
+fn test_update_authorized_keys() {
+    let existing = "ssh-rsa AAAAadmin admin@host\n".to_string();
+    let master = vec!["ssh-rsa AAAAmaster root@master".to_string()];
+    let added = update_authorized_keys(&existing, &master, &vec![]);
+    assert_eq!(format!("{}{} ssh-rsa AAAAmaster root@master\n", existing, KEY_OPTIONS),
+               added);
+    // Already there
+    assert_eq!(added, update_authorized_keys(&added, &master, &vec![]));
+    // Revoking leaves the other keys alone
+    assert_eq!(existing, update_authorized_keys(&added, &vec![], &master));
+    assert_eq!("", update_authorized_keys("", &vec![], &master));
+}
@@ -0,0 +1,344 @@
+use std::process::Command;
+use std::str::FromStr;
+
+/// The kinds of self-heal gluster can be asked to run
+#[derive(Debug, PartialEq)]
+pub enum HealType {
+    /// Heal only the files that are flagged as needing a heal
+    Index,
+    /// Crawl the whole volume and heal everything.  Needed after a brick was
+    /// replaced with an empty one.
+    Full,
+}
+
+impl FromStr for HealType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "index" => Ok(HealType::Index),
+            "full" => Ok(HealType::Full),
+            _ => Err(format!("Unknown heal type: {}", s)),
+        }
+    }
+}
+
+/// Number of entries waiting to be healed on a brick
+#[derive(Debug, PartialEq)]
+pub struct HealCount {
+    /// host:/path as gluster prints it
+    pub brick: String,
+    /// None when the brick is offline and can't be queried
+    pub entries: Option<u64>,
+}
+
+/// Entries gluster reports for a brick in heal info or heal info split-brain
+#[derive(Debug, PartialEq)]
+pub struct HealInfo {
+    /// host:/path as gluster prints it
+    pub brick: String,
+    /// Connected or the reason the brick couldn't be queried
+    pub status: String,
+    /// Paths relative to the volume root or <gfid:...> when gluster doesn't
+    /// know the path
+    pub entries: Vec<String>,
+}
+
+/// How to pick the good copy of a file that is in split-brain
+#[derive(Debug, PartialEq)]
+pub enum SplitBrainPolicy {
+    /// Keep the biggest copy
+    BiggerFile,
+    /// Keep the copy that was modified last
+    LatestMtime,
+    /// Keep the copy on this host:/path brick
+    SourceBrick(String),
+}
+
+impl SplitBrainPolicy {
+    /// source_brick is only used by the source-brick policy
+    pub fn from_str(policy: &str, source_brick: Option<String>) -> Result<Self, String> {
+        match policy {
+            "bigger-file" => Ok(SplitBrainPolicy::BiggerFile),
+            "latest-mtime" => Ok(SplitBrainPolicy::LatestMtime),
+            "source-brick" => {
+                match source_brick {
+                    Some(ref brick) if !brick.is_empty() => {
+                        Ok(SplitBrainPolicy::SourceBrick(brick.clone()))
+                    }
+                    _ => Err("The source-brick policy needs a source brick".to_string()),
+                }
+            }
+            _ => Err(format!("Unknown split-brain policy: {}", policy)),
+        }
+    }
+
+    fn to_args(&self) -> Vec<String> {
+        match *self {
+            SplitBrainPolicy::BiggerFile => vec!["bigger-file".to_string()],
+            SplitBrainPolicy::LatestMtime => vec!["latest-mtime".to_string()],
+            SplitBrainPolicy::SourceBrick(ref brick) => {
+                vec!["source-brick".to_string(), brick.clone()]
+            }
+        }
+    }
+}
+
+fn run_heal(volume: &str, args: Vec<&str>) -> Result<String, String> {
+    let mut cmd = Command::new("gluster");
+    cmd.arg("--mode=script");
+    cmd.arg("volume");
+    cmd.arg("heal");
+    cmd.arg(volume);
+    cmd.args(&args);
+    log!(format!("heal cmd: {:?}", cmd), Debug);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Kick off a self-heal of the volume.  This returns as soon as gluster has
+/// launched the heal.  Use heal_count to follow the progress.
+pub fn heal_volume(volume: &str, heal_type: &HealType) -> Result<(), String> {
+    match *heal_type {
+        HealType::Index => run_heal(volume, vec![])?,
+        HealType::Full => run_heal(volume, vec!["full"])?,
+    };
+    Ok(())
+}
+
+/// Query how many entries are still waiting to be healed on every brick
+pub fn heal_count(volume: &str) -> Result<Vec<HealCount>, String> {
+    let output = run_heal(volume, vec!["statistics", "heal-count"])?;
+    Ok(parse_heal_count(&output))
+}
+
+/// Total number of entries waiting to be healed across the volume.  Offline
+/// bricks are skipped.
+pub fn pending_heal_entries(counts: &Vec<HealCount>) -> u64 {
+    counts.iter().filter_map(|count| count.entries).sum()
+}
+
+/// Whether the self-heal daemon is still crawling any brick.  A full heal
+/// only queues entries as the crawl finds them so the heal count can read 0
+/// long before it is done.
+pub fn crawl_in_progress(volume: &str) -> Result<bool, String> {
+    let output = run_heal(volume, vec!["statistics"])?;
+    Ok(parse_crawl_in_progress(&output))
+}
+
+/// List the entries every brick still has to heal
+pub fn heal_info(volume: &str) -> Result<Vec<HealInfo>, String> {
+    let output = run_heal(volume, vec!["info"])?;
+    Ok(parse_heal_info(&output))
+}
+
+/// List the entries on every brick that are in split-brain
+pub fn split_brain_info(volume: &str) -> Result<Vec<HealInfo>, String> {
+    let output = run_heal(volume, vec!["info", "split-brain"])?;
+    Ok(parse_heal_info(&output))
+}
+
+/// Resolve the split-brain of a single file.  The file is either a path from
+/// the root of the volume or a <gfid:...> entry from split_brain_info.
+pub fn heal_split_brain(volume: &str,
+                        file: &str,
+                        policy: &SplitBrainPolicy)
+                        -> Result<(), String> {
+    let mut args = policy.to_args();
+    args.insert(0, "split-brain".to_string());
+    args.push(file.to_string());
+    run_heal(volume, args.iter().map(|arg| arg.as_ref()).collect())?;
+    Ok(())
+}
+
+// Brick 172.31.41.135:/mnt/xvdf
+// /dir/file1
+// <gfid:5b9e0a5c-8fa8-4d4c-a0e9-9e4b1a1c3e51>
+// Status: Connected
+// Number of entries: 2
+//
+// Brick 172.31.26.65:/mnt/xvdf
+// Status: Transport endpoint is not connected
+// Number of entries: -
+pub fn parse_heal_info(output: &str) -> Vec<HealInfo> {
+    let mut infos: Vec<HealInfo> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with("Brick ") {
+            infos.push(HealInfo {
+                brick: line.trim_left_matches("Brick ").to_string(),
+                status: String::new(),
+                entries: Vec::new(),
+            });
+            continue;
+        }
+        let info = match infos.last_mut() {
+            Some(info) => info,
+            None => continue,
+        };
+        if line.starts_with("Status:") {
+            info.status = line.trim_left_matches("Status:").trim().to_string();
+        } else if line.starts_with("/") || line.starts_with("<gfid:") {
+            info.entries.push(line.to_string());
+        }
+    }
+    infos
+}
+
+// Gathering count of entries to be healed on volume test has been successful
+//
+// Brick 172.31.41.135:/mnt/xvdf
+// Number of entries: 0
+//
+// Brick 172.31.26.65:/mnt/xvdf
+// No gathered input for this brick
+pub fn parse_heal_count(output: &str) -> Vec<HealCount> {
+    let mut counts: Vec<HealCount> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with("Brick ") {
+            counts.push(HealCount {
+                brick: line.trim_left_matches("Brick ").to_string(),
+                entries: None,
+            });
+            continue;
+        }
+        if line.starts_with("Number of entries:") {
+            if let Some(count) = counts.last_mut() {
+                count.entries = line.trim_left_matches("Number of entries:")
+                    .trim()
+                    .parse::<u64>()
+                    .ok();
+            }
+        }
+    }
+    counts
+}
+
+// Crawl statistics for brick no 0
+// Hostname of brick 172.31.41.135
+//
+// Starting time of crawl: Tue Jun  6 10:28:21 2017
+//
+// Crawl is in progress
+//
+// Type of crawl: FULL
+pub fn parse_crawl_in_progress(output: &str) -> bool {
+    output.lines().any(|line| line.trim() == "Crawl is in progress")
+}
+
+#[test]
+fn test_parse_crawl_in_progress() {
+    let output = r#"Gathering crawl statistics on volume test has been successful
+------------------------------------------------
+
+Crawl statistics for brick no 0
+Hostname of brick 172.31.41.135
+
+Starting time of crawl: Tue Jun  6 10:28:21 2017
+
+Ending time of crawl: Tue Jun  6 10:28:22 2017
+
+Type of crawl: INDEX
+No. of entries healed: 0
+No. of entries in split-brain: 0
+No. of heal failed entries: 0
+
+Crawl statistics for brick no 1
+Hostname of brick 172.31.26.65
+
+Starting time of crawl: Tue Jun  6 10:29:01 2017
+
+Crawl is in progress
+
+Type of crawl: FULL
+No. of entries healed: 210
+No. of entries in split-brain: 0
+No. of heal failed entries: 0
+"#;
+    assert!(parse_crawl_in_progress(output));
+    let finished = output.replace("Crawl is in progress", "Ending time of crawl: Tue Jun  6");
+    assert!(!parse_crawl_in_progress(&finished));
+}
+
+#[test]
+fn test_parse_heal_count() {
+    let output = r#"Gathering count of entries to be healed on volume test has been successful
+
+Brick 172.31.41.135:/mnt/xvdf
+Number of entries: 0
+
+Brick 172.31.26.65:/mnt/xvdf
+Number of entries: 1520
+
+Brick 172.31.29.11:/mnt/xvdf
+No gathered input for this brick
+"#;
+    let result = parse_heal_count(output);
+    println!("test_parse_heal_count: {:?}", result);
+    assert_eq!(result,
+               vec![HealCount {
+                        brick: "172.31.41.135:/mnt/xvdf".to_string(),
+                        entries: Some(0),
+                    },
+                    HealCount {
+                        brick: "172.31.26.65:/mnt/xvdf".to_string(),
+                        entries: Some(1520),
+                    },
+                    HealCount {
+                        brick: "172.31.29.11:/mnt/xvdf".to_string(),
+                        entries: None,
+                    }]);
+    assert_eq!(pending_heal_entries(&result), 1520);
+}
+
+#[test]
+fn test_parse_heal_info() {
+    let output = r#"Brick 172.31.41.135:/mnt/xvdf
+/dir/file1
+<gfid:5b9e0a5c-8fa8-4d4c-a0e9-9e4b1a1c3e51>
+Status: Connected
+Number of entries: 2
+
+Brick 172.31.26.65:/mnt/xvdf
+Status: Transport endpoint is not connected
+Number of entries: -
+"#;
+    let result = parse_heal_info(output);
+    println!("test_parse_heal_info: {:?}", result);
+    assert_eq!(result,
+               vec![HealInfo {
+                        brick: "172.31.41.135:/mnt/xvdf".to_string(),
+                        status: "Connected".to_string(),
+                        entries: vec!["/dir/file1".to_string(),
+                                      "<gfid:5b9e0a5c-8fa8-4d4c-a0e9-9e4b1a1c3e51>".to_string()],
+                    },
+                    HealInfo {
+                        brick: "172.31.26.65:/mnt/xvdf".to_string(),
+                        status: "Transport endpoint is not connected".to_string(),
+                        entries: vec![],
+                    }]);
+
+    let output = r#"Brick 172.31.41.135:/mnt/xvdf
+/file1
+Status: Connected
+Number of entries in split-brain: 1
+"#;
+    let result = parse_heal_info(output);
+    assert_eq!(result[0].entries, vec!["/file1".to_string()]);
+}
+
+#[test]
+fn test_split_brain_policy() {
+    assert_eq!(SplitBrainPolicy::from_str("bigger-file", None).unwrap().to_args(),
+               vec!["bigger-file".to_string()]);
+    assert_eq!(SplitBrainPolicy::from_str("source-brick",
+                                          Some("172.31.41.135:/mnt/xvdf".to_string()))
+                   .unwrap()
+                   .to_args(),
+               vec!["source-brick".to_string(), "172.31.41.135:/mnt/xvdf".to_string()]);
+    assert!(SplitBrainPolicy::from_str("source-brick", None).is_err());
+    assert!(SplitBrainPolicy::from_str("newest", None).is_err());
+}
@@ -0,0 +1,304 @@
+extern crate gluster;
+extern crate juju;
+
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+use self::gluster::BrickStatus;
+use self::gluster::peer::{Peer, State};
+use self::gluster::volume::{Brick, Volume};
+use super::ctdb::{ctdb_nodes, ctdb_summary};
+use super::heal::{heal_count, pending_heal_entries};
+use super::metrics::fs_usage;
+
+/// Seconds a stat of the fuse mount may take before it counts as hung
+const MOUNT_TIMEOUT: u64 = 10;
+
+/// How bad a condition is.  Ordered so the worst one sorts last.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Active,
+    Waiting,
+    Blocked,
+}
+
+/// One thing update_status found out about the unit
+#[derive(Debug, PartialEq)]
+pub struct Condition {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Condition {
+    pub fn active(message: &str) -> Condition {
+        Condition {
+            severity: Severity::Active,
+            message: message.to_string(),
+        }
+    }
+    pub fn waiting(message: &str) -> Condition {
+        Condition {
+            severity: Severity::Waiting,
+            message: message.to_string(),
+        }
+    }
+    pub fn blocked(message: &str) -> Condition {
+        Condition {
+            severity: Severity::Blocked,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Pick the unit status from the conditions.  The first of the worst
+/// conditions leads the message so checks should be added in order of
+/// importance.  When everything is fine the ready message is followed by
+/// the informational Active conditions.
+pub fn assess(conditions: &Vec<Condition>, ready: &str) -> (Severity, String) {
+    let worst = conditions.iter()
+        .map(|condition| condition.severity)
+        .max()
+        .unwrap_or(Severity::Active);
+    if worst == Severity::Active {
+        let mut messages = vec![ready.to_string()];
+        messages.extend(conditions.iter().map(|condition| condition.message.clone()));
+        return (worst, messages.join(", "));
+    }
+    let problems: Vec<&Condition> = conditions.iter()
+        .filter(|condition| condition.severity != Severity::Active)
+        .collect();
+    let first = problems.iter().find(|condition| condition.severity == worst).unwrap();
+    let mut message = first.message.clone();
+    if problems.len() > 1 {
+        message = format!("{} (+{} more)", message, problems.len() - 1);
+    }
+    (worst, message)
+}
+
+/// Set the juju status from the conditions
+pub fn set_status(conditions: &Vec<Condition>, ready: &str) {
+    let (severity, message) = assess(conditions, ready);
+    log!(format!("Unit health: {:?} {}", severity, message));
+    match severity {
+        Severity::Active => status_set!(Active message),
+        Severity::Waiting => status_set!(Waiting message),
+        Severity::Blocked => status_set!(Blocked message),
+    }
+}
+
+/// Whether two bricks are the same host and path.  The peer uuids aren't
+/// filled in the same way by every gluster command.
+pub fn same_brick(a: &Brick, b: &Brick) -> bool {
+    a.peer.hostname == b.peer.hostname && a.path == b.path
+}
+
+/// The bricks on this unit that are offline in a started volume
+pub fn volume_conditions(info: &Volume,
+                         local_bricks: &Vec<Brick>,
+                         brick_status: &Vec<BrickStatus>)
+                         -> Vec<Condition> {
+    brick_status.iter()
+        .filter(|status| {
+            !status.online && local_bricks.iter().any(|brick| same_brick(brick, &status.brick))
+        })
+        .map(|status| {
+            Condition::blocked(&format!("Brick {} of {} is offline",
+                                        status.brick.path.display(),
+                                        info.name))
+        })
+        .collect()
+}
+
+/// peers comes from peer status and pool from pool list.  Peers that are
+/// still joining are waited on but a disconnected or rejected peer needs
+/// someone to look at it.
+pub fn peer_conditions(peers: &Vec<Peer>, pool: &Vec<Peer>) -> Vec<Condition> {
+    let mut conditions: Vec<Condition> = Vec::new();
+    for peer in pool {
+        if peer.status == State::Disconnected {
+            conditions.push(Condition::blocked(&format!("Peer {} is disconnected",
+                                                        peer.hostname)));
+        }
+    }
+    for peer in peers {
+        match peer.status {
+            State::PeerInCluster => {}
+            State::PeerRejected => {
+                conditions.push(Condition::blocked(&format!("Peer {} was rejected",
+                                                            peer.hostname)))
+            }
+            status => {
+                conditions.push(Condition::waiting(&format!("Waiting for peer {} ({})",
+                                                            peer.hostname,
+                                                            status.to_string())))
+            }
+        }
+    }
+    conditions
+}
+
+/// Whether enough peers are connected for server quorum.  Gluster wants
+/// more than half of the pool unless cluster.server-quorum-ratio says
+/// otherwise.
+pub fn quorum_met(connected: usize, total: usize, ratio: Option<&String>) -> bool {
+    match ratio.and_then(|r| r.trim_right_matches('%').parse::<usize>().ok()) {
+        Some(ratio) => connected * 100 >= total * ratio,
+        None => connected * 2 > total,
+    }
+}
+
+/// Bricks on peers outside of server quorum are killed by glusterd so this
+/// only matters for volumes with server quorum turned on
+pub fn quorum_condition(info: &Volume, pool: &Vec<Peer>) -> Option<Condition> {
+    if info.options.get("cluster.server-quorum-type").map(|t| t.as_ref()) != Some("server") {
+        return None;
+    }
+    let connected = pool.iter().filter(|peer| peer.status == State::Connected).count();
+    if quorum_met(connected,
+                  pool.len(),
+                  info.options.get("cluster.server-quorum-ratio")) {
+        return None;
+    }
+    Some(Condition::blocked(&format!("Server quorum lost ({}/{} peers connected)",
+                                     connected,
+                                     pool.len())))
+}
+
+/// Heals run by themselves so a backlog only shows up in the message
+pub fn heal_condition(volume: &str) -> Option<Condition> {
+    match heal_count(volume) {
+        Ok(counts) => {
+            let pending = pending_heal_entries(&counts);
+            if pending > 0 {
+                return Some(Condition::active(&format!("{} healing {} entries", volume, pending)));
+            }
+            None
+        }
+        Err(e) => {
+            log!(format!("Heal count of {} failed: {}", volume, e), Error);
+            None
+        }
+    }
+}
+
+/// A dead fuse client leaves the mount point behind and any access either
+/// fails with ENOTCONN or hangs.  Stat it on another thread so a hung mount
+/// can't hang the hook too.
+pub fn mount_condition(volume: &str, mount_point: &str) -> Option<Condition> {
+    let (tx, rx) = channel();
+    let path = mount_point.to_string();
+    thread::spawn(move || {
+        let _ = tx.send(fs_usage(&path));
+    });
+    match rx.recv_timeout(Duration::from_secs(MOUNT_TIMEOUT)) {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => {
+            Some(Condition::blocked(&format!("Mount {} of {} failed: {}",
+                                             mount_point,
+                                             volume,
+                                             e)))
+        }
+        Err(_) => {
+            Some(Condition::blocked(&format!("Mount {} of {} is not responding",
+                                             mount_point,
+                                             volume)))
+        }
+    }
+}
+
+/// This unit's ctdb node.  Banned nodes stay out until someone steps in but
+/// an unhealthy node usually recovers by itself.
+pub fn ctdb_condition() -> Condition {
+    let nodes = match ctdb_nodes() {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            log!(format!("ctdb status failed: {}", e), Error);
+            return Condition::blocked("ctdb is not running");
+        }
+    };
+    match nodes.iter().find(|node| node.this_node) {
+        Some(local) if local.banned() => Condition::blocked("ctdb banned this node"),
+        Some(local) if !local.healthy() => {
+            Condition::waiting(&format!("ctdb node is {}", local.flags.join("|").to_lowercase()))
+        }
+        _ => Condition::active(&ctdb_summary(&nodes)),
+    }
+}
+
+#[cfg(test)]
+fn test_peer(hostname: &str, status: State) -> Peer {
+    use super::uuid::Uuid;
+    Peer {
+        uuid: Uuid::new_v4(),
+        hostname: hostname.to_string(),
+        status: status,
+    }
+}
+
+#[test]
+fn test_assess() {
+    let conditions = vec![Condition::active("georep 1 active"),
+                          Condition::waiting("Waiting for peer 10.0.0.3 (Connected)"),
+                          Condition::blocked("Brick /mnt/sdb of test is offline"),
+                          Condition::blocked("Peer 10.0.0.2 is disconnected")];
+    assert_eq!((Severity::Blocked, "Brick /mnt/sdb of test is offline (+2 more)".to_string()),
+               assess(&conditions, "Unit is ready (1 bricks)"));
+    assert_eq!((Severity::Active,
+                "Unit is ready (1 bricks), georep 1 active".to_string()),
+               assess(&conditions[..1].iter()
+                          .map(|c| Condition::active(&c.message))
+                          .collect(),
+                      "Unit is ready (1 bricks)"));
+}
+
+#[test]
+fn test_peer_and_quorum_conditions() {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use super::uuid::Uuid;
+    use self::gluster::volume::{Transport, VolumeType};
+
+    let peers = vec![test_peer("10.0.0.2", State::PeerInCluster),
+                     test_peer("10.0.0.3", State::ProbeSentToPeer)];
+    let pool = vec![test_peer("localhost", State::Connected),
+                    test_peer("10.0.0.2", State::Disconnected),
+                    test_peer("10.0.0.3", State::Disconnected)];
+    assert_eq!(vec![Condition::blocked("Peer 10.0.0.2 is disconnected"),
+                    Condition::blocked("Peer 10.0.0.3 is disconnected"),
+                    Condition::waiting("Waiting for peer 10.0.0.3 (probe sent to peer)")],
+               peer_conditions(&peers, &pool));
+
+    let mut options = BTreeMap::new();
+    let mut info = Volume {
+        name: "test".to_string(),
+        vol_type: VolumeType::Replicate,
+        id: Uuid::new_v4(),
+        status: "Started".to_string(),
+        transport: Transport::Tcp,
+        bricks: Vec::new(),
+        options: options.clone(),
+    };
+    assert_eq!(None, quorum_condition(&info, &pool));
+    options.insert("cluster.server-quorum-type".to_string(), "server".to_string());
+    info.options = options;
+    assert_eq!(Some(Condition::blocked("Server quorum lost (1/3 peers connected)")),
+               quorum_condition(&info, &pool));
+    assert!(quorum_met(2, 3, None));
+    assert!(!quorum_met(2, 4, None));
+    assert!(quorum_met(2, 4, Some(&"50%".to_string())));
+
+    let brick = Brick {
+        peer: test_peer("10.0.0.1", State::Connected),
+        path: PathBuf::from("/mnt/sdb"),
+    };
+    let status = vec![BrickStatus {
+                          brick: brick.clone(),
+                          tcp_port: 0,
+                          rdma_port: 0,
+                          online: false,
+                          pid: 0,
+                      }];
+    assert_eq!(vec![Condition::blocked("Brick /mnt/sdb of test is offline")],
+               volume_conditions(&info, &vec![brick], &status));
+}
@@ -1,4 +1,253 @@
+extern crate gluster;
+extern crate juju;
+
+use std::path::PathBuf;
+
+use gluster::get_local_bricks;
+use gluster::volume::{volume_info, Brick, Volume};
+use super::super::block;
+use super::super::heal::{heal_volume, HealType};
+use super::super::remove_brick::{drain_bricks, is_replicated, DrainStep};
+use super::super::replace_brick::replace_brick_commit_force;
+use super::super::release_device;
+use super::super::volumes::{get_volumes, VolumeConfig};
+
+// An initialized brick device on this unit that the volume doesn't use yet.
+// The brick on the detaching device can be swapped for one on it.
+fn spare_brick(devices: &Vec<block::BrickDevice>,
+               volume_config: &VolumeConfig,
+               volume: &Volume,
+               old_brick: &Brick,
+               detaching: &PathBuf)
+               -> Option<Brick> {
+    for device in devices {
+        if !device.initialized || device.dev_path == *detaching {
+            continue;
+        }
+        let mut new_brick = old_brick.clone();
+        new_brick.path = PathBuf::from(volume_config.brick_path(&device.mount_path));
+        if !volume.bricks.iter().any(|brick| brick.to_string() == new_brick.to_string()) {
+            return Some(new_brick);
+        }
+    }
+    None
+}
+
+/// What happens to the bricks a volume has on the detaching device
+#[derive(Debug, PartialEq)]
+enum DetachStep {
+    /// Swap the brick for this one and heal the data onto it
+    Replace(Brick),
+    /// Migrate the data off with remove-brick
+    Drain,
+}
+
+// Replicated volumes swap a lone brick for one on a spare device when there
+// is one.  Otherwise the data is migrated off.
+fn detach_step(devices: &Vec<block::BrickDevice>,
+               volume_config: &VolumeConfig,
+               volume: &Volume,
+               brick_list: &Vec<Brick>,
+               detaching: &PathBuf)
+               -> DetachStep {
+    if is_replicated(volume) && brick_list.len() == 1 {
+        if let Some(new_brick) =
+            spare_brick(devices, volume_config, volume, &brick_list[0], detaching) {
+            return DetachStep::Replace(new_brick);
+        }
+    }
+    DetachStep::Drain
+}
+
+// Swap the brick for an empty one and heal the data onto it from the rest of
+// its replica set.  Much quicker than draining a set and it keeps the
+// replica count.
+fn replace_detaching_brick(volume_name: &str, old_brick: &Brick, new_brick: &Brick)
+                           -> Result<(), String> {
+    log!(format!("Replacing brick {} with {}",
+                 old_brick.to_string(),
+                 new_brick.to_string()),
+         Info);
+    status_set!(Maintenance format!("Replacing brick {}", old_brick.to_string()));
+    replace_brick_commit_force(volume_name, old_brick, new_brick)?;
+    heal_volume(volume_name, &HealType::Full)?;
+    Ok(())
+}
+
 pub fn brick_detached() -> Result<(), String> {
-    // TODO: Do nothing for now
-    return Ok(());
+    // In a storage hook this is the device that is going away
+    let device_path = match juju::storage_get_location().map_err(|e| e.to_string())? {
+        Some(location) => PathBuf::from(location),
+        None => {
+            log!("Unable to find the location of the detaching storage");
+            return Ok(());
+        }
+    };
+    let mut devices = block::get_manual_bricks()?;
+    devices.extend(block::get_juju_bricks()?);
+    let device = match devices.iter()
+        .find(|device| device.dev_path == device_path)
+        .cloned() {
+        Some(device) => device,
+        None => {
+            log!(format!("{:?} is not a brick device.  Nothing to do", device_path));
+            return Ok(());
+        }
+    };
+    log!(format!("Brick device {:?} mounted at {} is detaching",
+                 device.dev_path,
+                 device.mount_path),
+         Info);
+
+    // Every volume may have a brick on this device
+    for volume_config in get_volumes()? {
+        let volume_name = volume_config.name.clone();
+        let brick_path = PathBuf::from(volume_config.brick_path(&device.mount_path));
+        match volume_info(&volume_name) {
+            Ok(volume) => {
+                let brick_list: Vec<Brick> = get_local_bricks(&volume_name)
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .filter(|brick| brick.path == brick_path)
+                    .collect();
+                if brick_list.is_empty() {
+                    continue;
+                }
+                if let DetachStep::Replace(new_brick) =
+                    detach_step(&devices, &volume_config, &volume, &brick_list, &device_path) {
+                    if let Err(e) = replace_detaching_brick(&volume_name,
+                                                            &brick_list[0],
+                                                            &new_brick) {
+                        log!(format!("Unable to replace brick {}: {}", brick_path.display(), e),
+                             Error);
+                        status_set!(Blocked
+                            format!("Unable to replace brick {}: {}", brick_path.display(), e));
+                        return Err(e);
+                    }
+                    continue;
+                }
+                // main exits non-zero when this hook fails.  That stops Juju
+                // from pulling the device out from under the volume until a
+                // retry of the hook finds the bricks gone.
+                match drain_bricks(&volume_name, &volume, &brick_list) {
+                    Ok(DrainStep::Done) => {}
+                    Ok(DrainStep::Draining(message)) => {
+                        // Juju retries the failed hook which checks on the
+                        // drain again
+                        status_set!(Waiting message.clone());
+                        return Err(format!("{}.  Still migrating", message));
+                    }
+                    Err(e) => {
+                        log!(format!("Unable to migrate brick {}: {}", brick_path.display(), e),
+                             Error);
+                        status_set!(Blocked
+                            format!("Unable to remove brick {}.  Add a spare brick device \
+                                     or replace it before detaching the storage: {}",
+                                    brick_path.display(),
+                                    e));
+                        return Err(e);
+                    }
+                }
+            }
+            Err(gluster::GlusterError::NoVolumesPresent) => {
+                log!(format!("Volume {} doesn't exist.  Nothing to drain", volume_name));
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+    }
+    // The bricks are out of every volume so a half finished cleanup mustn't
+    // hold up the detach
+    if let Err(e) = release_device(&device.dev_path) {
+        log!(format!("Unable to fully release {:?}: {}", device.dev_path, e),
+             Error);
+    }
+    status_set!(Active format!("Released brick device {:?}", device.dev_path));
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_volume(vol_type: gluster::volume::VolumeType, bricks: Vec<Brick>) -> Volume {
+    use std::collections::BTreeMap;
+    use gluster::volume::Transport;
+    use super::super::uuid::Uuid;
+    Volume {
+        name: "test".to_string(),
+        vol_type: vol_type,
+        id: Uuid::new_v4(),
+        status: "Started".to_string(),
+        transport: Transport::Tcp,
+        bricks: bricks,
+        options: BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+fn test_brick(host: &str, path: &str) -> Brick {
+    use gluster::peer::{Peer, State};
+    use super::super::uuid::Uuid;
+    Brick {
+        peer: Peer {
+            uuid: Uuid::new_v4(),
+            hostname: host.to_string(),
+            status: State::PeerInCluster,
+        },
+        path: PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+fn test_device(name: &str, initialized: bool) -> block::BrickDevice {
+    block::BrickDevice {
+        is_block_device: true,
+        initialized: initialized,
+        mount_path: format!("/mnt/{}", name),
+        dev_path: PathBuf::from(format!("/dev/{}", name)),
+        thin_volume: None,
+    }
+}
+
+#[test]
+fn test_detach_step() {
+    use gluster::volume::VolumeType;
+
+    let detaching = PathBuf::from("/dev/sdb");
+    let local = test_brick("10.0.0.1", "/mnt/sdb/test");
+    let volume = test_volume(VolumeType::Replicate,
+                             vec![local.clone(), test_brick("10.0.0.2", "/mnt/sdb/test")]);
+    let config = VolumeConfig {
+        name: "test".to_string(),
+        volume_type: "Replicate".to_string(),
+        replicas: 2,
+        arbiter_count: 0,
+        stripe_count: 0,
+        disperse_data: 0,
+        disperse_redundancy: 0,
+        brick_dir: Some("test".to_string()),
+        mount_point: "/mnt/test".to_string(),
+    };
+    let devices = vec![test_device("sdb", true),
+                       test_device("sdc", false),
+                       test_device("sdd", true)];
+
+    // The first initialized device that isn't going away takes the brick
+    let mut spare = local.clone();
+    spare.path = PathBuf::from("/mnt/sdd/test");
+    assert_eq!(Some(spare.clone()),
+               spare_brick(&devices, &config, &volume, &local, &detaching));
+    assert_eq!(DetachStep::Replace(spare.clone()),
+               detach_step(&devices, &config, &volume, &vec![local.clone()], &detaching));
+
+    // A device the volume already has a brick on isn't spare
+    let full = test_volume(VolumeType::Replicate, vec![local.clone(), spare]);
+    assert_eq!(None, spare_brick(&devices, &config, &full, &local, &detaching));
+    assert_eq!(DetachStep::Drain,
+               detach_step(&devices, &config, &full, &vec![local.clone()], &detaching));
+    assert_eq!(DetachStep::Drain,
+               detach_step(&devices[..2].to_vec(), &config, &volume, &vec![local.clone()],
+                           &detaching));
+
+    // Distribute volumes have no other copy to heal from
+    let distribute = test_volume(VolumeType::Distribute, vec![local.clone()]);
+    assert_eq!(DetachStep::Drain,
+               detach_step(&devices, &config, &distribute, &vec![local], &detaching));
 }
@@ -5,21 +5,56 @@ use std::fs::File;
 use std::path::Path;
 use std::process::Command;
 
+use gluster::volume::{volume_info, volume_list, volume_set_options};
 use super::super::{create_sysctl, ephemeral_unmount, finish_initialization, get_glusterfs_version,
                    initialize_storage};
-use super::super::apt;
 use super::super::block;
+use super::super::ganesha::setup_ganesha;
+use super::super::package::package_manager;
+use super::super::samba::setup_samba;
 use super::super::upgrade;
+use super::super::volumes::get_volumes;
+use super::install::install;
+use super::nrpe_external_master::update_nrpe_checks;
+use super::server_changed::{nfs_disable_option, publish_unit_info, setup_ctdb, setup_volumes};
 
 pub fn config_changed() -> Result<(), String> {
     if let Err(err) = check_for_new_devices() {
         log!(format!("Checking for new devices failed with error: {}", err),
              Error);
     }
+    if let Err(err) = publish_unit_info() {
+        log!(format!("Publishing brick devices failed with error: {}", err),
+             Error);
+    }
     if let Err(err) = check_for_sysctl() {
         log!(format!("Setting sysctl's failed with error: {}", err),
              Error);
     }
+    if let Err(err) = check_for_new_volumes() {
+        log!(format!("Setting up new volumes failed with error: {}", err),
+             Error);
+    }
+    if let Err(err) = check_for_nfs_server() {
+        log!(format!("Switching nfs_server failed with error: {}", err),
+             Error);
+    }
+    // Only rewrites smb.conf when the cifs options changed.  ctdb has to
+    // start or stop managing samba first.
+    let samba = setup_ctdb().and_then(|_| get_volumes()).and_then(|volumes| setup_samba(&volumes));
+    if let Err(err) = samba {
+        log!(format!("Setting up samba failed with error: {}", err),
+             Error);
+    }
+    // Picks up new volumes and threshold changes
+    if let Err(err) = update_nrpe_checks() {
+        log!(format!("Updating nrpe checks failed with error: {}", err),
+             Error);
+    }
+    if let Err(err) = upgrade::record_version() {
+        log!(format!("Recording the gluster version failed: {}", err),
+             Error);
+    }
     // If fails we fail the hook
     check_for_upgrade()?;
     return Ok(());
@@ -93,6 +128,56 @@ fn check_for_new_devices() -> Result<(), String> {
     Ok(())
 }
 
+// Move the volumes between gnfs and ganesha when nfs_server changes
+fn check_for_nfs_server() -> Result<(), String> {
+    let config = juju::Config::new().map_err(|e| e.to_string())?;
+    if !config.changed("nfs_server").map_err(|e| e.to_string())? {
+        return Ok(());
+    }
+    let volumes = get_volumes()?;
+    if juju::is_leader().map_err(|e| e.to_string())? {
+        // Volume options are cluster wide so only the leader sets them
+        for volume in &volumes {
+            if volume_info(&volume.name).is_err() {
+                continue;
+            }
+            log!(format!("Setting nfs.disable on {}", volume.name), Info);
+            volume_set_options(&volume.name, vec![nfs_disable_option()?])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    setup_ganesha(&volumes)?;
+    // ctdb needs to pick up or drop the ganesha callout
+    setup_ctdb()?;
+    Ok(())
+}
+
+// Create any volumes that were added to the volumes config and let the
+// clients know about them
+fn check_for_new_volumes() -> Result<(), String> {
+    let config = juju::Config::new().map_err(|e| e.to_string())?;
+    if !config.changed("volumes").map_err(|e| e.to_string())? {
+        return Ok(());
+    }
+    // Validate the config on every unit so a typo is easy to spot
+    get_volumes()?;
+    if !juju::is_leader().map_err(|e| e.to_string())? {
+        return Ok(());
+    }
+    log!("volumes config changed.  Setting up volumes", Info);
+    setup_volumes()?;
+    if let Some(vols) = volume_list() {
+        for relation_name in vec!["fuse", "nfs"] {
+            for relation in juju::relation_ids_by_identifier(relation_name)
+                .map_err(|e| e.to_string())? {
+                juju::relation_set_by_id("volumes", &vols.join(" "), &relation)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    Ok(())
+}
+
 fn check_for_sysctl() -> Result<(), String> {
     let config = juju::Config::new().map_err(|e| e.to_string())?;
     if config.changed("sysctl").map_err(|e| e.to_string())? {
@@ -126,38 +211,52 @@ fn check_for_upgrade() -> Result<(), String> {
         return Ok(());
     }
 
+    let packages = package_manager();
+    let source = juju::config_get("source").map_err(|e| e.to_string())?;
+    if let Err(reason) = packages.check_source(&source.clone().unwrap_or_default()) {
+        // Stay Blocked until source is something the package manager can use
+        log!(format!("Invalid source: {}", reason), Error);
+        status_set!(Blocked reason);
+        return upgrade::set_upgrade_blocked(Some(&reason));
+    }
+
     log!("Getting current_version");
-    let current_version = get_glusterfs_version()?;
+    let current_version = match get_glusterfs_version() {
+        Ok(version) => version,
+        Err(_) => {
+            // The install hook blocked on the source.  Now it can go ahead.
+            upgrade::set_upgrade_blocked(None)?;
+            return install();
+        }
+    };
 
     log!("Adding new source line");
-    let source = juju::config_get("source").map_err(|e| e.to_string())?;
     if !source.is_some() {
         // No upgrade requested
         log!("Source not set.  Cannot continue with upgrade");
-        return Ok(());
+        return upgrade::set_upgrade_blocked(None);
     }
-    apt::add_source(&source.unwrap())?;
-    log!("Calling apt update");
-    apt::apt_update()?;
+    packages.add_source(&source.unwrap())?;
+    log!("Updating the package lists");
+    packages.update()?;
 
     log!("Getting proposed_version");
-    let proposed_version = apt::get_candidate_package_version("glusterfs-server")?;
-
-    // Using semantic versioning if the new version is greater than we allow the upgrade
-    if proposed_version > current_version {
-        log!(format!("current_version: {}", current_version));
-        log!(format!("new_version: {}", proposed_version));
-        log!(format!("{} to {} is a valid upgrade path.  Proceeding.",
-                     current_version,
-                     proposed_version));
-        return upgrade::roll_cluster(&proposed_version);
-    } else {
-        // Log a helpful error message
-        log!(format!("Invalid upgrade path from {} to {}. The new version needs to be \
-                            greater than the old version",
-                     current_version,
-                     proposed_version),
-             Error);
-        return Ok(());
+    let proposed_version = packages.candidate_version("glusterfs-server")?;
+
+    if proposed_version == current_version {
+        log!(format!("{} is already installed", current_version));
+        return upgrade::set_upgrade_blocked(None);
+    }
+    log!(format!("current_version: {}", current_version));
+    log!(format!("new_version: {}", proposed_version));
+    if let Err(reason) = upgrade::check_upgrade_path(&current_version, &proposed_version) {
+        // Stay Blocked until source points somewhere we can upgrade to
+        log!(format!("Invalid upgrade path: {}", reason), Error);
+        return upgrade::set_upgrade_blocked(Some(&reason));
     }
+    upgrade::set_upgrade_blocked(None)?;
+    log!(format!("{} to {} is a valid upgrade path.  Proceeding.",
+                 current_version,
+                 proposed_version));
+    upgrade::start_upgrade(&proposed_version)
 }
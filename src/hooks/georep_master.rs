@@ -0,0 +1,73 @@
+extern crate juju;
+extern crate serde_yaml;
+
+use super::super::georep::{create_session, georep_status, root_ssh_public_key, sessions,
+                           start_session};
+use super::super::volumes::default_volume_name;
+
+// The slave unit that trusts our key and the host::volume to replicate to
+fn find_slave(public_key: &str) -> Result<Option<String>, String> {
+    for unit in juju::relation_list().map_err(|e| e.to_string())? {
+        let address = juju::relation_get_by_unit("slave-address", &unit)
+            .map_err(|e| e.to_string())?;
+        let volume = juju::relation_get_by_unit("slave-volume", &unit)
+            .map_err(|e| e.to_string())?;
+        let authorized_keys: Vec<String> =
+            match juju::relation_get_by_unit("authorized-keys", &unit)
+                .map_err(|e| e.to_string())? {
+                Some(keys) => serde_yaml::from_str(&keys).map_err(|e| e.to_string())?,
+                None => Vec::new(),
+            };
+        if let (Some(address), Some(volume)) = (address, volume) {
+            if authorized_keys.iter().any(|key| key.trim() == public_key) {
+                return Ok(Some(format!("{}::{}", address.trim(), volume.trim())));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Hand our ssh key to the slave application and once it's trusted create
+/// and start the geo-replication session of the first volume.
+pub fn georep_master_changed() -> Result<(), String> {
+    // Any unit may be the leader when the session gets created so they all
+    // offer a key
+    let public_key = root_ssh_public_key()?;
+    let volume = default_volume_name()?;
+    juju::relation_set("ssh-public-key", &public_key).map_err(|e| e.to_string())?;
+    juju::relation_set("master-volume", &volume).map_err(|e| e.to_string())?;
+
+    if !juju::is_leader().map_err(|e| e.to_string())? {
+        return Ok(());
+    }
+    let slave = match find_slave(&public_key)? {
+        Some(slave) => slave,
+        None => {
+            log!("Waiting for a slave unit to authorize our ssh key");
+            return Ok(());
+        }
+    };
+    // The slave volume may be reached through any of its units
+    let slave_volume = slave.split("::").last().unwrap_or_default().to_string();
+    let workers = georep_status()?;
+    if sessions(&workers).iter().any(|&(ref master, ref existing)| {
+        *master == volume && existing.ends_with(&format!("::{}", slave_volume))
+    }) {
+        log!(format!("Geo-replication of {} to {} is already set up", volume, slave_volume));
+        juju::relation_set("session-created", "true").map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+
+    log!(format!("Creating geo-replication session from {} to {}", volume, slave),
+         Info);
+    status_set!(Maintenance format!("Creating geo-replication session to {}", slave));
+    if let Err(e) = create_session(&volume, &slave).and_then(|_| start_session(&volume, &slave)) {
+        log!(format!("Geo-replication to {} failed: {}", slave, e), Error);
+        status_set!(Blocked format!("Geo-replication to {} failed: {}", slave, e.trim()));
+        return Err(e);
+    }
+    // The slave can drop our root key now that push-pem installed its own
+    juju::relation_set("session-created", "true").map_err(|e| e.to_string())?;
+    status_set!(Active format!("Geo-replication to {} started", slave));
+    Ok(())
+}
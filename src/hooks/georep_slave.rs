@@ -0,0 +1,39 @@
+extern crate juju;
+extern crate serde_yaml;
+
+use super::super::georep::set_authorized_master_keys;
+use super::super::volumes::default_volume_name;
+
+/// Trust the ssh keys of the master units and tell them where to replicate
+/// to.  The master creates the session once it sees its key in
+/// authorized-keys.  After that, or when a master unit departs, its key is
+/// revoked.
+pub fn georep_slave_changed() -> Result<(), String> {
+    let mut units: Vec<juju::Relation> = Vec::new();
+    let mut session_created = false;
+    for unit in juju::relation_list().map_err(|e| e.to_string())? {
+        if juju::relation_get_by_unit("session-created", &unit)
+            .map_err(|e| e.to_string())?
+            .is_some() {
+            session_created = true;
+        }
+        units.push(unit);
+    }
+    let mut authorized_keys: Vec<String> = Vec::new();
+    if !session_created {
+        for unit in &units {
+            if let Some(key) = juju::relation_get_by_unit("ssh-public-key", unit)
+                .map_err(|e| e.to_string())? {
+                authorized_keys.push(key.trim().to_string());
+            }
+        }
+    }
+    set_authorized_master_keys(&authorized_keys)?;
+    // The master may be in another model so hand out the public address
+    let public_addr = juju::unit_get_public_addr().map_err(|e| e.to_string())?.to_string();
+    let keys = serde_yaml::to_string(&authorized_keys).map_err(|e| e.to_string())?;
+    juju::relation_set("slave-address", &public_addr).map_err(|e| e.to_string())?;
+    juju::relation_set("slave-volume", &default_volume_name()?).map_err(|e| e.to_string())?;
+    juju::relation_set("authorized-keys", &keys).map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,28 @@
+extern crate juju;
+
+use super::super::package::{package_manager, PackageManager};
+
+pub fn install() -> Result<(), String> {
+    let packages = package_manager();
+    let source = juju::config_get("source").map_err(|e| e.to_string())?.unwrap_or_default();
+    // config-changed installs once source is something yum can use
+    if let Err(reason) = packages.check_source(&source) {
+        log!(format!("Not installing gluster: {}", reason), Error);
+        status_set!(Blocked reason);
+        return Ok(());
+    }
+    if !source.is_empty() || packages != PackageManager::Apt {
+        status_set!(Maintenance "Adding the gluster source");
+        packages.add_source(&source)?;
+    }
+    packages.update()?;
+
+    log!("Installing Gluster", Info);
+    status_set!(Maintenance "Installing gluster");
+    packages.install(vec!["ctdb",
+                          "nfs-common",
+                          "glusterfs-server",
+                          "glusterfs-common",
+                          "glusterfs-client"])?;
+    Ok(())
+}
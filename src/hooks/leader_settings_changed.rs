@@ -0,0 +1,13 @@
+extern crate juju;
+
+use super::super::upgrade::process_upgrade;
+use super::server_changed::setup_ctdb;
+
+/// The leader published new settings.  Pick up the upgrade lock if it was
+/// handed to this unit and write out the ctdb nodes the leader decided on.
+pub fn leader_settings_changed() -> Result<(), String> {
+    if let Err(e) = process_upgrade() {
+        log!(format!("Rolling upgrade failed: {}", e), Error);
+    }
+    setup_ctdb()
+}
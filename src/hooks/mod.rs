@@ -1,6 +1,15 @@
 pub mod brick_detached;
 pub mod config_changed;
 pub mod fuse_relation_joined;
+pub mod georep_master;
+pub mod georep_slave;
+pub mod install;
+pub mod leader_settings_changed;
 pub mod nfs_relation_joined;
+pub mod nrpe_external_master;
 pub mod server_changed;
 pub mod server_removed;
+pub mod start;
+pub mod stop;
+pub mod update_status;
+pub mod upgrade_charm;
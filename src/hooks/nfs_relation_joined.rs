@@ -2,10 +2,15 @@ extern crate gluster;
 extern crate juju;
 
 use gluster::volume::volume_list;
+use super::super::ganesha::{get_nfs_server, NfsServer};
 use super::super::resolve_first_vip_to_dns;
 
 
 pub fn nfs_relation_joined() -> Result<(), String> {
+    if get_nfs_server()? == NfsServer::Disabled {
+        log!("nfs_server is none.  Not exporting any volumes over NFS");
+        return Ok(());
+    }
     let config_value = juju::config_get("virtual_ip_addresses").map_err(|e| e.to_string())?;
     let volumes = volume_list();
     if let Some(vols) = volumes {
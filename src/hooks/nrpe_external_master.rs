@@ -0,0 +1,201 @@
+extern crate juju;
+
+use std::env;
+use std::fs::{copy, create_dir_all, read_dir, remove_file, set_permissions, File, Permissions};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use super::super::apt::service_restart;
+use super::super::{get_config_number, get_config_value};
+use super::super::nagios::PLUGIN_PATH;
+use super::super::volumes::{get_volumes, VolumeConfig};
+
+const NRPE_DIR: &'static str = "/etc/nagios/nrpe.d";
+const SUDOERS_FILE: &'static str = "/etc/sudoers.d/99-gluster-nagios";
+
+/// An nrpe command running one of the charm's nagios checks
+#[derive(Debug)]
+pub struct NrpeCheck {
+    pub shortname: String,
+    /// Arguments to the check_gluster nagios subcommand
+    pub args: String,
+}
+
+/// The checks for this unit.  Every volume gets its status, self-heal,
+/// quota, geo-replication and utilization checks.
+pub fn nrpe_checks(volumes: &Vec<VolumeConfig>,
+                   vol_utilization: (u8, u8),
+                   mounts: (u8, u8))
+                   -> Vec<NrpeCheck> {
+    let mut checks = vec![NrpeCheck {
+                              shortname: "gluster_quorum".to_string(),
+                              args: "quorum-status".to_string(),
+                          },
+                          NrpeCheck {
+                              shortname: "gluster_mounts".to_string(),
+                              args: format!("mounts -w {} -c {}", mounts.0, mounts.1),
+                          }];
+    for volume in volumes {
+        for &(suffix, check_type) in &[("status", "info"),
+                                       ("self_heal", "self-heal"),
+                                       ("quota", "quota"),
+                                       ("geo_rep", "geo-rep")] {
+            checks.push(NrpeCheck {
+                shortname: format!("gluster_{}_{}", volume.name, suffix),
+                args: format!("volume-status -v {} -t {}", volume.name, check_type),
+            });
+        }
+        checks.push(NrpeCheck {
+            shortname: format!("gluster_{}_utilization", volume.name),
+            args: format!("vol-utilization -v {} -m {} -w {} -c {}",
+                          volume.name,
+                          volume.mount_point,
+                          vol_utilization.0,
+                          vol_utilization.1),
+        });
+    }
+    checks
+}
+
+/// Write the nrpe command definition for a check
+pub fn render_nrpe_check<T: Write>(f: &mut T,
+                                   check: &NrpeCheck,
+                                   servicegroup: &str)
+                                   -> Result<usize, ::std::io::Error> {
+    let mut bytes_written = 0;
+    bytes_written += f.write(format!("# check {}\n", check.shortname).as_bytes())?;
+    bytes_written += f.write(b"# The following header was added automatically by juju\n")?;
+    bytes_written += f.write(b"# Modifying it will affect nagios monitoring and alerting\n")?;
+    bytes_written += f.write(format!("# servicegroups: {}\n", servicegroup).as_bytes())?;
+    // The gluster cli only talks to root
+    bytes_written += f.write(format!("command[check_{}]=sudo {} nagios {}\n",
+                                     check.shortname,
+                                     PLUGIN_PATH,
+                                     check.args)
+                                 .as_bytes())?;
+    Ok(bytes_written)
+}
+
+/// The monitors the nrpe subordinate hands to nagios
+pub fn render_monitors(checks: &Vec<NrpeCheck>) -> String {
+    let mut monitors = "monitors:\n  remote:\n    nrpe:\n".to_string();
+    for check in checks {
+        monitors.push_str(&format!("      {}:\n        command: check_{}\n",
+                                   check.shortname,
+                                   check.shortname));
+    }
+    monitors
+}
+
+// Copy the charm binary somewhere that survives charm upgrades and let
+// nagios run it as root
+fn install_plugin() -> Result<(), String> {
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    if let Some(parent) = Path::new(PLUGIN_PATH).parent() {
+        create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    copy(&exe, PLUGIN_PATH).map_err(|e| e.to_string())?;
+    set_permissions(PLUGIN_PATH, Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+
+    let mut sudoers = File::create(SUDOERS_FILE).map_err(|e| e.to_string())?;
+    sudoers.write_all(format!("nagios ALL=(root) NOPASSWD: {}\n", PLUGIN_PATH).as_bytes())
+        .map_err(|e| e.to_string())?;
+    set_permissions(SUDOERS_FILE, Permissions::from_mode(0o440)).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Drop the checks of volumes that are gone
+fn remove_stale_checks(checks: &Vec<NrpeCheck>) -> Result<(), String> {
+    if !Path::new(NRPE_DIR).exists() {
+        return Ok(());
+    }
+    let current: Vec<String> =
+        checks.iter().map(|check| format!("check_{}.cfg", check.shortname)).collect();
+    for entry in read_dir(NRPE_DIR).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with("check_gluster_") && !current.contains(&name) {
+            log!(format!("Removing stale nrpe check {}", name));
+            remove_file(entry.path()).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn threshold(name: &str, default: u8) -> u8 {
+    get_config_number::<u8>(name).unwrap_or(default)
+}
+
+/// Register the nagios checks with every nrpe-external-master relation.
+/// Does nothing until the nrpe subordinate is related.
+pub fn update_nrpe_checks() -> Result<(), String> {
+    let relations = juju::relation_ids_by_identifier("nrpe-external-master")
+        .map_err(|e| e.to_string())?;
+    if relations.is_empty() {
+        return Ok(());
+    }
+    log!("Updating nrpe checks");
+    install_plugin()?;
+    let volumes = get_volumes()?;
+    let checks = nrpe_checks(&volumes,
+                             (threshold("nagios_vol_utilization_warning", 80),
+                              threshold("nagios_vol_utilization_critical", 90)),
+                             (threshold("nagios_mounts_warning", 80),
+                              threshold("nagios_mounts_critical", 90)));
+    let mut servicegroup = get_config_value("nagios_context")?;
+    if servicegroup.is_empty() {
+        servicegroup = "juju".to_string();
+    }
+    create_dir_all(NRPE_DIR).map_err(|e| e.to_string())?;
+    remove_stale_checks(&checks)?;
+    for check in &checks {
+        let path = format!("{}/check_{}.cfg", NRPE_DIR, check.shortname);
+        let mut f = File::create(&path).map_err(|e| e.to_string())?;
+        render_nrpe_check(&mut f, check, &servicegroup).map_err(|e| e.to_string())?;
+    }
+    let monitors = render_monitors(&checks);
+    for relation in relations {
+        juju::relation_set_by_id("monitors", &monitors, &relation).map_err(|e| e.to_string())?;
+    }
+    if let Err(e) = service_restart("nagios-nrpe-server") {
+        log!(format!("Restarting nagios-nrpe-server failed: {}", e), Error);
+    }
+    Ok(())
+}
+
+pub fn nrpe_external_master_changed() -> Result<(), String> {
+    update_nrpe_checks()
+}
+
+#[test]
+fn test_render_nrpe_checks() {
+    let volumes = vec![VolumeConfig {
+                           name: "test".to_string(),
+                           volume_type: "Replicate".to_string(),
+                           replicas: 3,
+                           arbiter_count: 1,
+                           stripe_count: 3,
+                           disperse_data: 2,
+                           disperse_redundancy: 1,
+                           brick_dir: None,
+                           mount_point: "/mnt/glusterfs".to_string(),
+                       }];
+    let checks = nrpe_checks(&volumes, (80, 90), (70, 95));
+    assert_eq!(7, checks.len());
+
+    let mut buff: Vec<u8> = Vec::new();
+    render_nrpe_check(&mut buff, &checks[6], "juju").unwrap();
+    let expected = r#"# check gluster_test_utilization
+# The following header was added automatically by juju
+# Modifying it will affect nagios monitoring and alerting
+# servicegroups: juju
+command[check_gluster_test_utilization]=sudo /usr/local/lib/nagios/plugins/check_gluster \
+nagios vol-utilization -v test -m /mnt/glusterfs -w 80 -c 90
+"#;
+    assert_eq!(expected.replace("\\\n", ""), String::from_utf8_lossy(&buff));
+
+    let monitors = render_monitors(&checks);
+    assert!(monitors.starts_with("monitors:\n  remote:\n    nrpe:\n      gluster_quorum:\n        \
+                                  command: check_gluster_quorum\n"));
+}
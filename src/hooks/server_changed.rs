@@ -1,8 +1,11 @@
 extern crate gluster;
 extern crate juju;
+extern crate serde_yaml;
 
+use std::collections::HashMap;
 use std::io::Read;
 use std::net::IpAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use gluster::{GlusterOption, SplitBrainPolicy, Toggle};
@@ -11,17 +14,33 @@ use gluster::volume::*;
 use super::super::apt;
 use super::super::block;
 use super::super::ctdb;
-use super::super::samba::setup_samba;
-use super::super::{brick_and_server_cartesian_product, ephemeral_unmount, find_new_peers,
-                   finish_initialization, get_cluster_networks, get_config_value,
-                   initialize_storage, mount_cluster, probe_in_units, Status, wait_for_peers};
+use super::super::ganesha::{get_nfs_server, setup_ganesha, NfsServer};
+use super::super::rebalance::schedule_rebalance;
+use super::super::samba::{setup_samba, SambaSecurity};
+use super::super::upgrade::process_upgrade;
+use super::super::volume_error::{retry, set_volume_error, VolumeError};
+use super::super::volumes::{get_volumes, VolumeConfig};
+use super::super::placement::{place_bricks, validate_placement, Host};
+use super::super::{ephemeral_unmount, find_new_peers, finish_initialization,
+                   get_cluster_networks, get_config_flag, get_config_value, initialize_storage,
+                   mount_cluster, probe_in_units, wait_for_peers};
 
 use std::fs::File;
+use std::time::Duration;
+
+// How many times to try creating a volume while glusterd is busy
+const CREATE_ATTEMPTS: usize = 3;
+const CREATE_RETRY_DELAY: u64 = 10;
 
 pub fn server_changed() -> Result<(), String> {
     let context = juju::Context::new_from_env();
     let leader = juju::is_leader().map_err(|e| e.to_string())?;
-    let volume_name = get_config_value("volume_name")?;
+    // The leader needs every unit's devices and zone to place the bricks
+    publish_unit_info()?;
+    // Peers report their upgrade progress on this relation
+    if let Err(e) = process_upgrade() {
+        log!(format!("Rolling upgrade failed: {}", e), Error);
+    }
 
     if leader {
         log!(format!("I am the leader: {}", context.relation_id));
@@ -33,286 +52,249 @@ pub fn server_changed() -> Result<(), String> {
 
         status_set!(Maintenance "Checking for new peers to probe");
 
-        let mut peers = peer_list().map_err(|e| e.to_string())?;
+        let peers = peer_list().map_err(|e| e.to_string())?;
         log!(format!("peer list: {:?}", peers));
         let related_units = juju::relation_list().map_err(|e| e.to_string())?;
         probe_in_units(&peers, related_units)?;
-        // Update our peer list
-        peers = peer_list().map_err(|e| e.to_string())?;
-
-        // Everyone is in.  Lets see if a volume exists
-        let volume_info = volume_info(&volume_name);
-        let existing_volume: bool;
-        match volume_info {
-            Ok(_) => {
-                log!(format!("Expanding volume {}", volume_name), Info);
-                status_set!(Maintenance format!("Expanding volume {}", volume_name));
-
-                match expand_volume(peers, volume_info.ok()) {
-                    Ok(v) => {
-                        log!(format!("Expand volume succeeded.  Return code: {}", v),
-                             Info);
-                        status_set!(Active "Expand volume succeeded.");
-                        // Poke the other peers to update their status
-                        juju::relation_set("expanded", "true").map_err(|e| e.to_string())?;
-                        // Ensure the cluster is mounted
-                        mount_cluster(&volume_name)?;
-                        setup_ctdb()?;
-                        setup_samba(&volume_name)?;
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        log!(format!("Expand volume failed with output: {}", e), Error);
-                        status_set!(Blocked "Expand volume failed.  Please check juju debug-log.");
-                        return Err(e);
-                    }
-                }
-            }
-            Err(gluster::GlusterError::NoVolumesPresent) => {
-                existing_volume = false;
-            }
-            _ => {
-                return Err("Volume info command failed".to_string());
-            }
-        }
-        if !existing_volume {
-            log!(format!("Creating volume {}", volume_name), Info);
-            status_set!(Maintenance format!("Creating volume {}", volume_name));
-            create_gluster_volume(&volume_name, peers)?;
-            mount_cluster(&volume_name)?;
-            setup_ctdb()?;
-            setup_samba(&volume_name)?;
-        }
-        return Ok(());
+
+        // Everyone is in.  Lets see if the volumes exist
+        return setup_volumes();
     } else {
         // Non leader units
         let vol_started = juju::relation_get("started").map_err(|e| e.to_string())?;
         if vol_started.is_some() {
-            mount_cluster(&volume_name)?;
-            // Setup ctdb and samba after the volume comes up on non leader units
+            let volumes = get_volumes()?;
+            for volume in &volumes {
+                if volume_info(&volume.name).is_ok() {
+                    mount_cluster(volume)?;
+                }
+            }
+            // Setup ganesha, ctdb and samba after the volume comes up on non
+            // leader units
+            setup_ganesha(&volumes)?;
             setup_ctdb()?;
-            setup_samba(&volume_name)?;
+            setup_samba(&volumes)?;
         }
 
         return Ok(());
     }
 }
 
-fn create_gluster_volume(volume_name: &str, peers: Vec<Peer>) -> Result<(), String> {
-    match create_volume(&peers, None) {
-        Ok(status) => {
-            match status {
-                Status::Created => {
-                    log!("Create volume succeeded.", Info);
-                    status_set!(Maintenance "Create volume succeeded");
-                    start_gluster_volume(&volume_name)?;
-                    // Poke the other peers to update their status
-                    juju::relation_set("started", "true").map_err(|e| e.to_string())?;
-                    return Ok(());
-                }
-                Status::WaitForMorePeers => {
-                    log!("Waiting for all peers to enter the Peer in Cluster status");
-                    status_set!(Maintenance
-                        "Waiting for all peers to enter the \"Peer in Cluster status\"");
-                    return Ok(());
-                }
-                _ => {
-                    // Status is failed
-                    // What should I return here?
-                    return Ok(());
-                }
+/// Create every configured volume that doesn't exist yet and expand the ones
+/// that do onto any new peers.  Only the leader should call this.
+pub fn setup_volumes() -> Result<(), String> {
+    let volumes = get_volumes()?;
+    for volume in &volumes {
+        if let Err(e) = volume.validate() {
+            log!(format!("Invalid volume config: {}", e), Error);
+            status_set!(Blocked e);
+            return Ok(());
+        }
+    }
+    let peers = peer_list().map_err(|e| e.to_string())?;
+    // Every volume is carved out of the same brick devices
+    let device_mounts = prepare_brick_devices()?;
+    let hosts = gather_hosts(&peers, &device_mounts)?;
+
+    for volume in &volumes {
+        let result = match volume_info(&volume.name) {
+            Ok(info) => {
+                log!(format!("Expanding volume {}", volume.name), Info);
+                status_set!(Maintenance format!("Expanding volume {}", volume.name));
+                expand_gluster_volume(volume, &hosts, info)
+            }
+            Err(gluster::GlusterError::NoVolumesPresent) => {
+                log!(format!("Creating volume {}", volume.name), Info);
+                status_set!(Maintenance format!("Creating volume {}", volume.name));
+                create_gluster_volume(volume, &hosts)
+            }
+            Err(e) => Err(VolumeError::Gluster(format!("volume info failed: {}", e.to_string()))),
+        };
+        set_volume_error(&volume.name, result.as_ref().err())?;
+        if let Err(e) = result {
+            if e.is_transient() {
+                // The next server-relation-changed or config-changed hook
+                // tries again
+                log!(format!("Volume {} isn't ready yet: {}", volume.name, e));
+                status_set!(Maintenance format!("Waiting to retry volume {}: {}", volume.name, e));
+            } else {
+                log!(format!("Setting up volume {} failed: {}", volume.name, e), Error);
+                status_set!(Blocked format!("Setting up volume {} failed: {}", volume.name, e));
             }
+            // Don't let the other volumes hide the status
+            return Ok(());
         }
-        Err(e) => {
-            log!(format!("Create volume failed with output: {}", e), Error);
-            status_set!(Blocked "Create volume failed.  Please check juju debug-log.");
-            return Err(e.to_string());
+        // Creating waits on more peers so the volume may not exist yet
+        if volume_info(&volume.name).is_ok() {
+            // Ensure the volume is mounted
+            mount_cluster(volume)?;
         }
-    };
+    }
+    setup_ganesha(&volumes)?;
+    setup_ctdb()?;
+    setup_samba(&volumes)?;
+    Ok(())
 }
-// Create a new volume if enough peers are available
-fn create_volume(peers: &Vec<Peer>, volume_info: Option<Volume>) -> Result<Status, String> {
-    let cluster_type_config = get_config_value("cluster_type")?;
-    let cluster_type = VolumeType::from_str(&cluster_type_config);
-    let volume_name = get_config_value("volume_name")?;
-    let replicas = match get_config_value("replication_level")?.parse() {
-        Ok(r) => r,
-        Err(e) => {
-            log!(format!("Invalid config value for replicas.  Defaulting to 3. Error was \
-                                {}",
-                         e),
-                 Error);
-            3
-        }
-    };
 
-    // Make sure all peers are in the cluster
-    // spinlock
-    wait_for_peers()?;
+// Set a key on every server relation so the other units run their
+// server-relation-changed hook.  This works outside of relation hooks too.
+fn notify_peers(key: &str) -> Result<(), String> {
+    for relation in juju::relation_ids_by_identifier("server").map_err(|e| e.to_string())? {
+        juju::relation_set_by_id(key, "true", &relation).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
 
-    // Build the brick list
-    let brick_list = match get_brick_list(&peers, volume_info) {
-        Ok(list) => list,
-        Err(e) => {
-            match e {
-                Status::WaitForMorePeers => {
-                    log!("Waiting for more peers", Info);
-                    status_set!(Maintenance "Waiting for more peers");
-                    return Ok(Status::WaitForMorePeers);
-                }
-                Status::InvalidConfig(config_err) => {
-                    return Err(config_err);
-                }
-                _ => {
-                    // Some other error
-                    return Err(format!("Unknown error in create volume: {:?}", e));
-                }
-            }
+fn create_gluster_volume(volume: &VolumeConfig, hosts: &Vec<Host>) -> Result<(), VolumeError> {
+    create_volume(volume, hosts)?;
+    log!("Create volume succeeded.", Info);
+    status_set!(Maintenance "Create volume succeeded");
+    start_gluster_volume(volume).map_err(|e| VolumeError::Gluster(format!("start failed: {}", e)))?;
+    // Poke the other peers to update their status
+    notify_peers("started").map_err(VolumeError::Peer)?;
+    Ok(())
+}
+
+fn expand_gluster_volume(volume: &VolumeConfig,
+                         hosts: &Vec<Host>,
+                         info: Volume)
+                         -> Result<(), VolumeError> {
+    match expand_volume(volume, hosts, Some(info)) {
+        Ok(v) => {
+            log!(format!("Expand volume {} succeeded.  Return code: {}", volume.name, v),
+                 Info);
+            status_set!(Active "Expand volume succeeded.");
+            // Poke the other peers to update their status
+            notify_peers("expanded").map_err(VolumeError::Peer)?;
+            Ok(())
         }
-    };
-    log!(format!("Got brick list: {:?}", brick_list));
-    log!(format!("Creating volume of type {:?} with brick list {:?}",
-                 cluster_type,
-                 brick_list),
-         Info);
+        Err(VolumeError::WaitingForPeers(msg)) => {
+            // The volume is fine as it is until more hosts join
+            log!(msg, Info);
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
 
-    match cluster_type {
+// Run the volume create command that matches the volume type
+fn run_volume_create(volume: &VolumeConfig, brick_list: Vec<Brick>) -> Result<i32, VolumeError> {
+    let volume_name = &volume.name;
+    let replicas = volume.replicas;
+    let result = match volume.gluster_type() {
         VolumeType::Distribute => {
-            let _ = volume_create_distributed(&volume_name, Transport::Tcp, brick_list, true)
-                .map_err(|e| e.to_string());
-            Ok(Status::Created)
+            volume_create_distributed(volume_name, Transport::Tcp, brick_list, true)
         }
-        VolumeType::Stripe => {
-            let _ = volume_create_striped(&volume_name, 3, Transport::Tcp, brick_list, true)
-                .map_err(|e| e.to_string());
-            Ok(Status::Created)
+        VolumeType::Stripe | VolumeType::DistributedAndStripe => {
+            volume_create_striped(volume_name,
+                                  volume.stripe_count,
+                                  Transport::Tcp,
+                                  brick_list,
+                                  true)
         }
-        VolumeType::Replicate => {
-            let _ =
-                volume_create_replicated(&volume_name, replicas, Transport::Tcp, brick_list, true)
-                    .map_err(|e| e.to_string());
-            Ok(Status::Created)
+        VolumeType::Replicate | VolumeType::DistributedAndReplicate => {
+            volume_create_replicated(volume_name, replicas, Transport::Tcp, brick_list, true)
         }
         VolumeType::Arbiter => {
-            let _ =
-                volume_create_arbiter(&volume_name, replicas, 1, Transport::Tcp, brick_list, true)
-                    .map_err(|e| e.to_string());
-            Ok(Status::Created)
-        }
-        VolumeType::StripedAndReplicate => {
-            let _ = volume_create_striped_replicated(&volume_name,
-                                                     3,
-                                                     3,
-                                                     Transport::Tcp,
-                                                     brick_list,
-                                                     true)
-                    .map_err(|e| e.to_string());
-            Ok(Status::Created)
-        }
-        VolumeType::Disperse => {
-            let _ = volume_create_erasure(&volume_name, 3, 1, Transport::Tcp, brick_list, true)
-                .map_err(|e| e.to_string());
-            Ok(Status::Created)
-        }
-        // VolumeType::Tier => {},
-        VolumeType::DistributedAndStripe => {
-            let _ = volume_create_striped(&volume_name, 3, Transport::Tcp, brick_list, true)
-                .map_err(|e| e.to_string());
-            Ok(Status::Created)
-        }
-        VolumeType::DistributedAndReplicate => {
-            let _ = volume_create_replicated(&volume_name, 3, Transport::Tcp, brick_list, true)
-                .map_err(|e| e.to_string());
-            Ok(Status::Created)
+            volume_create_arbiter(volume_name,
+                                  replicas,
+                                  volume.arbiter_count,
+                                  Transport::Tcp,
+                                  brick_list,
+                                  true)
         }
+        VolumeType::StripedAndReplicate |
         VolumeType::DistributedAndStripedAndReplicate => {
-            let _ = volume_create_striped_replicated(&volume_name,
-                                                     3,
-                                                     3,
-                                                     Transport::Tcp,
-                                                     brick_list,
-                                                     true)
-                    .map_err(|e| e.to_string());
-            Ok(Status::Created)
+            volume_create_striped_replicated(volume_name,
+                                             volume.stripe_count,
+                                             replicas,
+                                             Transport::Tcp,
+                                             brick_list,
+                                             true)
         }
-        VolumeType::DistributedAndDisperse => {
-            let _ = volume_create_erasure(
-                &volume_name,
-                brick_list.len()-1, //TODO: This number has to be lower than the brick length
-                1,
-                Transport::Tcp,
-                brick_list,
-                true).map_err(|e| e.to_string());
-            Ok(Status::Created)
+        // VolumeType::Tier => {},
+        VolumeType::Disperse | VolumeType::DistributedAndDisperse => {
+            // Gluster wants the whole set size and how much of it is redundancy
+            volume_create_erasure(volume_name,
+                                  volume.set_size(),
+                                  volume.disperse_redundancy,
+                                  Transport::Tcp,
+                                  brick_list,
+                                  true)
         }
-    }
+    };
+    result.map_err(VolumeError::from)
+}
+
+// Create a new volume if enough peers are available
+fn create_volume(volume: &VolumeConfig, hosts: &Vec<Host>) -> Result<(), VolumeError> {
+    // Make sure all peers are in the cluster
+    // spinlock
+    wait_for_peers().map_err(VolumeError::Peer)?;
+
+    // Build the brick list
+    let brick_list = get_brick_list(volume, hosts, None)?;
+    log!(format!("Got brick list: {:?}", brick_list));
+    log!(format!("Creating volume of type {:?} with brick list {:?}",
+                 volume.gluster_type(),
+                 brick_list),
+         Info);
+
+    // glusterd only runs one transaction at a time across the cluster
+    retry(CREATE_ATTEMPTS,
+          Duration::from_secs(CREATE_RETRY_DELAY),
+          || run_volume_create(volume, brick_list.clone()))?;
+    Ok(())
 }
+
 // Expands the volume by X servers+bricks
 // Adds bricks and then runs a rebalance
-fn expand_volume(peers: Vec<Peer>, volume_info: Option<Volume>) -> Result<i32, String> {
-    let volume_name = get_config_value("volume_name")?;
+fn expand_volume(volume: &VolumeConfig,
+                 hosts: &Vec<Host>,
+                 volume_info: Option<Volume>)
+                 -> Result<i32, VolumeError> {
+    let volume_name = volume.name.clone();
 
     // Are there new peers?
     log!(format!("Checking for new peers to expand the volume named {}",
                  volume_name));
+    if let Some(ref info) = volume_info {
+        let peers: Vec<Peer> = hosts.iter().map(|host| host.peer.clone()).collect();
+        log!(format!("New peers: {:?}", find_new_peers(&peers, info)));
+    }
 
     // Build the brick list
-    let brick_list = match get_brick_list(&peers, volume_info) {
-        Ok(list) => list,
-        Err(e) => {
-            match e {
-                Status::WaitForMorePeers => {
-                    log!("Waiting for more peers", Info);
-                    return Ok(0);
-                }
-                Status::InvalidConfig(config_err) => {
-                    return Err(config_err);
-                }
-                _ => {
-                    // Some other error
-                    return Err(format!("Unknown error in expand volume: {:?}", e));
-                }
-            }
-        }
-    };
+    let brick_list = get_brick_list(volume, hosts, volume_info)?;
 
     // Check to make sure the bricks are formatted and mounted
     // let clean_bricks = try!(check_brick_list(&brick_list).map_err(|e| e.to_string()));
 
     log!(format!("Expanding volume with brick list: {:?}", brick_list),
          Info);
-    match volume_add_brick(&volume_name, brick_list, true) {
-        Ok(o) => Ok(o),
-        Err(e) => Err(e.to_string()),
+    let result = volume_add_brick(&volume_name, brick_list, true)?;
+    // The bricks are in.  A failed rebalance shouldn't fail the expansion
+    if let Err(e) = schedule_rebalance(&volume_name) {
+        log!(format!("Rebalance after expanding {} failed: {}", volume_name, e),
+             Error);
     }
+    Ok(result)
 }
 
-// This function will take into account the replication level and
-// try its hardest to produce a list of bricks that satisfy this:
-// 1. Are not already in the volume
-// 2. Sufficient hosts to satisfy replication level
-// 3. Stripped across the hosts
-// If insufficient hosts exist to satisfy this replication level this will return no new bricks
-// to add
-fn get_brick_list(peers: &Vec<Peer>,
-                  volume: Option<Volume>)
-                  -> Result<Vec<gluster::volume::Brick>, Status> {
-    // Default to 3 replicas if the parsing fails
+// Format and mount any new brick devices.  Returns the mount path of every
+// usable brick device on this unit.
+fn prepare_brick_devices() -> Result<Vec<String>, String> {
     let mut brick_devices: Vec<block::BrickDevice> = Vec::new();
 
-    let replica_config = get_config_value("replication_level").unwrap_or("3".to_string());
-    let replicas = replica_config.parse().unwrap_or(3);
-
     // TODO: Should this fail the hook or just keep going?
     log!("Checking for ephemeral unmount");
-    ephemeral_unmount().map_err(|e| Status::InvalidConfig(e))?;
+    ephemeral_unmount()?;
 
     // Get user configured storage devices
-    let manual_brick_devices = block::get_manual_bricks().map_err(|e| Status::InvalidConfig(e))?;
+    let manual_brick_devices = block::get_manual_bricks()?;
     brick_devices.extend(manual_brick_devices);
 
     // Get the juju storage block devices
-    let juju_config_brick_devices = block::get_juju_bricks().map_err(|e| Status::InvalidConfig(e))?;
+    let juju_config_brick_devices = block::get_juju_bricks()?;
     brick_devices.extend(juju_config_brick_devices);
 
     log!(format!("storage devices: {:?}", brick_devices));
@@ -324,8 +306,7 @@ fn get_brick_list(peers: &Vec<Peer>,
         if !device.initialized {
             log!(format!("Calling initialize_storage for {:?}", device.dev_path));
             // Spawn all format commands in the background
-            format_handles.push(
-                initialize_storage(device.clone()).map_err(|e| Status::FailedToCreate(e))?);
+            format_handles.push(initialize_storage(device.clone())?);
         } else {
             // The device is already initialized, lets add it to our usable paths list
             log!(format!("{:?} is already initialized", device.dev_path));
@@ -342,7 +323,7 @@ fn get_brick_list(peers: &Vec<Peer>,
                         // success
                         // 1. Run any post setup commands if needed
                         finish_initialization(&handle.device.dev_path)
-                            .map_err(|e| Status::FailedToCreate(e.to_string()))?;
+                            .map_err(|e| e.to_string())?;
                         brick_paths.push(handle.device.mount_path.clone());
                     }
                     Err(e) => {
@@ -363,135 +344,249 @@ fn get_brick_list(peers: &Vec<Peer>,
             }
         }
     }
-    log!(format!("Usable brick paths: {:?}", brick_paths));
-
-    if volume.is_none() {
-        log!("Volume is none");
-        // number of bricks % replicas == 0 then we're ok to proceed
-        if peers.len() < replicas {
-            // Not enough peers to replicate across
-            log!("Not enough peers to satisfy the replication level for the Gluster \
-                        volume.  Waiting for more peers to join.");
-            return Err(Status::WaitForMorePeers);
-        } else if peers.len() == replicas {
-            // Case 1: A perfect marriage of peers and number of replicas
-            log!("Number of peers and number of replicas match");
-            return Ok(brick_and_server_cartesian_product(peers, &brick_paths));
-        } else {
-            // Case 2: We have a mismatch of replicas and hosts
-            // Take as many as we can and leave the rest for a later time
-            let count = peers.len() - (peers.len() % replicas);
-            let mut new_peers = peers.clone();
-
-            // Drop these peers off the end of the list
-            new_peers.truncate(count);
-            log!(format!("Too many new peers.  Dropping {} peers off the list", count));
-            return Ok(brick_and_server_cartesian_product(&new_peers, &brick_paths));
+    log!(format!("Usable brick device paths: {:?}", brick_paths));
+    Ok(brick_paths)
+}
+
+// Build the list of new bricks for the volume out of every host's brick
+// devices.  The bricks are ordered so each replica or disperse set spans
+// distinct hosts and availability zones.  If there aren't enough hosts to
+// fill a set this returns WaitingForPeers.
+fn get_brick_list(volume_config: &VolumeConfig,
+                  hosts: &Vec<Host>,
+                  volume: Option<Volume>)
+                  -> Result<Vec<Brick>, VolumeError> {
+    let set_size = volume_config.set_size();
+    let existing_bricks = match volume {
+        Some(volume) => volume.bricks,
+        None => Vec::new(),
+    };
+    // Only offer the bricks that aren't already in the volume
+    let candidates: Vec<Host> = hosts.iter()
+        .map(|host| {
+            let paths: Vec<String> = host.paths
+                .iter()
+                .map(|mount| volume_config.brick_path(mount))
+                .filter(|path| {
+                    !existing_bricks.iter().any(|brick| {
+                        brick.peer.uuid == host.peer.uuid && brick.path == PathBuf::from(path)
+                    })
+                })
+                .collect();
+            Host { paths: paths, ..host.clone() }
+        })
+        .collect();
+    log!(format!("Candidate bricks for {}: {:?}", volume_config.name, candidates));
+
+    let brick_list = place_bricks(&candidates, set_size);
+    if brick_list.is_empty() {
+        return Err(VolumeError::WaitingForPeers(format!("Not enough hosts with free bricks to \
+                                                         fill a set of {} for {}",
+                                                        set_size,
+                                                        volume_config.name)));
+    }
+    validate_placement(&brick_list, set_size, &candidates).map_err(VolumeError::Brick)?;
+    if existing_bricks.is_empty() {
+        let host_count = candidates.iter().filter(|host| !host.paths.is_empty()).count();
+        volume_config.check_bricks(brick_list.len(), host_count).map_err(VolumeError::Config)?;
+    }
+    Ok(brick_list)
+}
+
+// The brick device mount paths this unit has formatted
+fn initialized_brick_devices() -> Result<Vec<String>, String> {
+    let mut brick_devices = block::get_manual_bricks()?;
+    brick_devices.extend(block::get_juju_bricks()?);
+    Ok(brick_devices.into_iter()
+        .filter(|device| device.initialized)
+        .map(|device| device.mount_path)
+        .collect())
+}
+
+/// Share this unit's availability zone and brick devices with the leader so
+/// it can spread the replica sets across hosts and zones.
+pub fn publish_unit_info() -> Result<(), String> {
+    let zone = juju::az_info().unwrap_or(String::new());
+    let devices = serde_yaml::to_string(&initialized_brick_devices()?)
+        .map_err(|e| e.to_string())?;
+    for relation in juju::relation_ids_by_identifier("server").map_err(|e| e.to_string())? {
+        juju::relation_set_by_id("availability-zone", &zone, &relation)
+            .map_err(|e| e.to_string())?;
+        juju::relation_set_by_id("brick-devices", &devices, &relation)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Collect the brick devices and availability zone of every peer.  Units that
+// haven't published their devices are assumed to look like this one.
+fn gather_hosts(peers: &Vec<Peer>, device_mounts: &Vec<String>) -> Result<Vec<Host>, String> {
+    let mut unit_info: HashMap<String, (Option<String>, Option<Vec<String>>)> = HashMap::new();
+    for relation in juju::relation_ids_by_identifier("server").map_err(|e| e.to_string())? {
+        for unit in juju::relation_list_by_id(&relation).map_err(|e| e.to_string())? {
+            let address = match juju::relation_get_by_id("private-address", &relation, &unit)
+                .map_err(|e| e.to_string())? {
+                Some(address) => address.trim().to_string(),
+                None => continue,
+            };
+            let zone = juju::relation_get_by_id("availability-zone", &relation, &unit)
+                .map_err(|e| e.to_string())?;
+            let devices = match juju::relation_get_by_id("brick-devices", &relation, &unit)
+                .map_err(|e| e.to_string())? {
+                Some(devices) => {
+                    Some(serde_yaml::from_str::<Vec<String>>(&devices)
+                        .map_err(|e| e.to_string())?)
+                }
+                None => None,
+            };
+            unit_info.insert(address, (zone, devices));
         }
-    } else {
-        // Existing volume.  Build a differential list.
-        log!("Existing volume.  Building differential brick list");
-        let mut new_peers = find_new_peers(peers, &volume.unwrap());
-
-        if new_peers.len() < replicas {
-            log!("New peers found are less than needed by the replica count");
-            return Err(Status::WaitForMorePeers);
-        } else if new_peers.len() == replicas {
-            log!("New peers and number of replicas match");
-            return Ok(brick_and_server_cartesian_product(&new_peers, &brick_paths));
+    }
+    let my_address = juju::unit_get_private_addr().map_err(|e| e.to_string())?.to_string();
+
+    let mut hosts: Vec<Host> = Vec::new();
+    for peer in peers {
+        let (zone, paths) = if peer.hostname == my_address {
+            (juju::az_info().ok(), device_mounts.clone())
         } else {
-            let count = new_peers.len() - (new_peers.len() % replicas);
-            // Drop these peers off the end of the list
-            log!(format!("Too many new peers.  Dropping {} peers off the list", count));
-            new_peers.truncate(count);
-            return Ok(brick_and_server_cartesian_product(&new_peers, &brick_paths));
-        }
+            match unit_info.get(&peer.hostname) {
+                Some(&(ref zone, ref devices)) => {
+                    (zone.clone(), devices.clone().unwrap_or(device_mounts.clone()))
+                }
+                None => (None, device_mounts.clone()),
+            }
+        };
+        hosts.push(Host {
+            peer: peer.clone(),
+            paths: paths,
+            zone: zone.filter(|zone| !zone.is_empty()),
+        });
     }
+    Ok(hosts)
 }
 // Add all the peers in the gluster cluster to the ctdb cluster
-fn setup_ctdb() -> Result<(), String> {
-    if juju::config_get("virtual_ip_addresses").map_err(|e| e.to_string())?.is_none() {
-        // virtual_ip_addresses isn't set.  Skip setting ctdb up
-        return Ok(());
-    }
-    log!("setting up ctdb");
+pub fn setup_ctdb() -> Result<(), String> {
+    setup_ctdb_without(&[])
+}
+
+/// Leader settings key of the ctdb nodes file every unit writes
+const CTDB_NODES_KEY: &'static str = "ctdb-nodes";
+
+// The addresses of every peer except the departing ones.  The pool lists
+// this unit as localhost.
+fn ctdb_cluster_addresses(departing: &[IpAddr]) -> Result<Vec<IpAddr>, String> {
     let peers = peer_list().map_err(|e| e.to_string())?;
     log!(format!("Got ctdb peer list: {:?}", peers));
     let mut cluster_addresses: Vec<IpAddr> = Vec::new();
     for peer in peers {
-        let address = IpAddr::from_str(&peer.hostname).map_err(|e| e.to_string())?;
-        cluster_addresses.push(address)
+        let mut address = ctdb::resolve_node(&peer.hostname)?;
+        if address.is_loopback() {
+            address = ctdb::resolve_node(&juju::unit_get_private_addr()
+                .map_err(|e| e.to_string())?
+                .to_string())?;
+        }
+        if !departing.contains(&address) && !cluster_addresses.contains(&address) {
+            cluster_addresses.push(address)
+        }
     }
+    cluster_addresses.sort();
+    Ok(cluster_addresses)
+}
 
-    log!("writing /etc/default/ctdb");
-    let mut ctdb_conf = File::create("/etc/default/ctdb").map_err(|e| e.to_string())?;
-    ctdb::render_ctdb_configuration(&mut ctdb_conf).map_err(|e| e.to_string())?;
+// ctdb needs the same nodes file on every node.  The leader merges the
+// cluster into the published file and everyone writes that out as is.
+fn ctdb_nodes(departing: &[IpAddr]) -> Result<Option<String>, String> {
+    let published = juju::leader_get(Some(CTDB_NODES_KEY.to_string()))
+        .map_err(|e| e.to_string())?;
+    if !juju::is_leader().map_err(|e| e.to_string())? {
+        return Ok(published.map(|nodes| format!("{}\n", nodes.trim())));
+    }
+    let published = published.unwrap_or_default();
+    let nodes = ctdb::update_ctdb_nodes(&published, &ctdb_cluster_addresses(departing)?);
+    if nodes.trim() != published.trim() {
+        log!(format!("Publishing ctdb nodes {}", nodes.trim().replace('\n', " ")),
+             Info);
+        let mut settings: HashMap<String, String> = HashMap::new();
+        settings.insert(CTDB_NODES_KEY.to_string(), nodes.clone());
+        juju::leader_set(settings).map_err(|e| e.to_string())?;
+    }
+    Ok(Some(nodes))
+}
 
-    let cluster_networks = get_cluster_networks()?;
+/// Render the ctdb config for every peer except the departing ones and hand
+/// any changes to the running daemon
+pub fn setup_ctdb_without(departing: &[IpAddr]) -> Result<(), String> {
+    if juju::config_get("virtual_ip_addresses").map_err(|e| e.to_string())?.is_none() {
+        // virtual_ip_addresses isn't set.  Skip setting ctdb up
+        return Ok(());
+    }
+    log!("setting up ctdb");
+    let nodes = match ctdb_nodes(departing)? {
+        Some(nodes) => nodes,
+        None => {
+            log!("Waiting for the leader to publish the ctdb nodes");
+            return Ok(());
+        }
+    };
 
-    log!("writing /etc/ctdb/public_addresses");
-    let mut public_addresses =
-        File::create("/etc/ctdb/public_addresses").map_err(|e| e.to_string())?;
-    ctdb::render_ctdb_public_addresses(&mut public_addresses, &cluster_networks)
+    let mut ctdb_conf: Vec<u8> = Vec::new();
+    let samba = if get_config_flag("cifs")? {
+        Some(SambaSecurity::from_str(&get_config_value("cifs_security")?)?)
+    } else {
+        None
+    };
+    ctdb::render_ctdb_configuration(&mut ctdb_conf, &get_nfs_server()?, samba.as_ref())
         .map_err(|e| e.to_string())?;
+    let conf_changed = ctdb::write_if_changed("/etc/default/ctdb", &ctdb_conf)?;
 
-    log!("writing /etc/ctdb/nodes");
-    let mut cluster_nodes = File::create("/etc/ctdb/nodes").map_err(|e| e.to_string())?;
-
-    ctdb::render_ctdb_cluster_nodes(&mut cluster_nodes, &cluster_addresses)
+    let cluster_networks = get_cluster_networks()?;
+    let mut public_addresses: Vec<u8> = Vec::new();
+    ctdb::render_ctdb_public_addresses(&mut public_addresses, &cluster_networks)
         .map_err(|e| e.to_string())?;
+    let ips_changed = ctdb::write_if_changed("/etc/ctdb/public_addresses", &public_addresses)?;
 
-    // Start the ctdb service
-    log!("Starting ctdb");
-    apt::service_start("ctdb")?;
+    let nodes_changed = ctdb::write_if_changed("/etc/ctdb/nodes", nodes.as_bytes())?;
 
+    if !ctdb::ctdb_running() {
+        // Start the ctdb service
+        log!("Starting ctdb");
+        apt::service_start("ctdb")?;
+        return Ok(());
+    }
+    if conf_changed {
+        log!("ctdb configuration changed.  Restarting ctdb", Info);
+        apt::service_restart("ctdb")?;
+        return Ok(());
+    }
+    if nodes_changed {
+        ctdb::reload_nodes()?;
+    }
+    if ips_changed {
+        ctdb::reload_ips()?;
+    }
     Ok(())
 }
 
-fn shrink_volume(peer: Peer, volume_info: Option<Volume>) -> Result<i32, String> {
-    let volume_name = get_config_value("volume_name")?;
-
-    log!(format!("Shrinking volume named  {}", volume_name), Info);
-
-    let peers: Vec<Peer> = vec![peer];
-
-    // Build the brick list
-    let brick_list = match get_brick_list(&peers, volume_info) {
-        Ok(list) => list,
-        Err(e) => {
-            match e {
-                Status::WaitForMorePeers => {
-                    log!("Waiting for more peers", Info);
-                    return Ok(0);
-                }
-                Status::InvalidConfig(config_err) => {
-                    return Err(config_err);
-                }
-                _ => {
-                    // Some other error
-                    return Err(format!("Unknown error in shrink volume: {:?}", e));
-                }
-            }
-        }
-    };
-
-    log!(format!("Shrinking volume with brick list: {:?}", brick_list),
-         Info);
-    match gluster::volume::volume_remove_brick(&volume_name, brick_list, true) {
-        Ok(o) => Ok(o),
-        Err(e) => Err(e.to_string()),
+/// The nfs.disable setting for the configured nfs_server.  gNFS has to be
+/// off for ganesha to bind the NFS ports.
+pub fn nfs_disable_option() -> Result<GlusterOption, String> {
+    match get_nfs_server()? {
+        // Starting in gluster 3.8 NFS is disabled in favor of ganesha.  gnfs
+        // keeps the legacy version for clusters that still want it.
+        NfsServer::Gnfs => Ok(GlusterOption::NfsDisable(Toggle::Off)),
+        NfsServer::Ganesha | NfsServer::Disabled => Ok(GlusterOption::NfsDisable(Toggle::On)),
     }
 }
-fn start_gluster_volume(volume_name: &str) -> Result<(), String> {
+
+fn start_gluster_volume(volume: &VolumeConfig) -> Result<(), String> {
+    let volume_name = &volume.name;
     match gluster::volume::volume_start(&volume_name, false) {
         Ok(_) => {
             log!("Starting volume succeeded.".to_string(), Info);
             status_set!(Active "Starting volume succeeded.");
-            mount_cluster(&volume_name)?;
+            mount_cluster(volume)?;
             let mut settings: Vec<GlusterOption> = Vec::new();
-            // Starting in gluster 3.8 NFS is disabled in favor of ganesha.  I'd like to stick
-            // with the legacy version a bit longer.
-            settings.push(GlusterOption::NfsDisable(Toggle::Off));
+            settings.push(nfs_disable_option()?);
             settings.push(GlusterOption::DiagnosticsLatencyMeasurement(Toggle::On));
             settings.push(GlusterOption::DiagnosticsCountFopHits(Toggle::On));
             settings.push(GlusterOption::DiagnosticsFopSampleInterval(5));
@@ -1,7 +1,218 @@
+extern crate gluster;
 extern crate juju;
 
+use std::collections::{BTreeMap, HashMap};
+
+use gluster::peer::{peer_list, peer_remove, Peer};
+use gluster::volume::{volume_info, Brick, Volume};
+use super::super::remove_brick::{brick_names, drain_bricks, DrainStep};
+use super::super::ctdb;
+use super::super::health::Condition;
+use super::super::volumes::get_volumes;
+use super::server_changed::setup_ctdb_without;
+
 pub fn server_removed() -> Result<(), String> {
-    let private_address = juju::unit_get_private_addr().map_err(|e| e.to_string())?;
-    log!(format!("Removing server: {}", private_address), Info);
-    return Ok(());
+    // In the departed hook this is the address of the unit that is leaving
+    let departing_address = match juju::relation_get("private-address")
+        .map_err(|e| e.to_string())? {
+        Some(address) => address.trim().to_string(),
+        None => {
+            log!("Departing unit has no private-address.  Nothing to remove");
+            return Ok(());
+        }
+    };
+    log!(format!("Removing server: {}", departing_address), Info);
+    let my_address = juju::unit_get_private_addr().map_err(|e| e.to_string())?.to_string();
+    if departing_address == my_address {
+        log!("This unit is departing.  Leaving brick removal to the leader");
+        return Ok(());
+    }
+    // Every remaining unit drops the departing node from ctdb.  The peer is
+    // still in the gluster pool until the leader detaches it.
+    match ctdb::resolve_node(&departing_address) {
+        Ok(address) => {
+            if let Err(e) = setup_ctdb_without(&[address]) {
+                log!(format!("Removing {} from ctdb failed: {}", departing_address, e),
+                     Error);
+            }
+        }
+        Err(e) => log!(format!("Unable to remove {} from ctdb: {}", departing_address, e), Error),
+    }
+    if !juju::is_leader().map_err(|e| e.to_string())? {
+        log!("Not the leader.  Leaving brick removal to the leader");
+        return Ok(());
+    }
+
+    remove_peer(&departing_address)?;
+    Ok(())
+}
+
+/// Leader settings key holding the addresses of departed units whose bricks
+/// are still draining
+const DEPARTING_KEY: &'static str = "departing-peers";
+
+fn departing_peers() -> Result<Vec<String>, String> {
+    Ok(juju::leader_get(Some(DEPARTING_KEY.to_string()))
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|address| address.to_string())
+        .collect())
+}
+
+fn set_departing_peers(peers: &Vec<String>) -> Result<(), String> {
+    let mut settings: HashMap<String, String> = HashMap::new();
+    settings.insert(DEPARTING_KEY.to_string(), peers.join(" "));
+    juju::leader_set(settings).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Leader settings key holding the departed units whose bricks couldn't be
+/// removed, one "address reason" per line
+const FAILED_KEY: &'static str = "failed-departures";
+
+fn failed_departures() -> Result<BTreeMap<String, String>, String> {
+    let mut failures: BTreeMap<String, String> = BTreeMap::new();
+    let value = juju::leader_get(Some(FAILED_KEY.to_string())).map_err(|e| e.to_string())?;
+    for line in value.unwrap_or_default().lines() {
+        let mut parts = line.splitn(2, ' ');
+        if let (Some(address), Some(reason)) = (parts.next(), parts.next()) {
+            failures.insert(address.to_string(), reason.to_string());
+        }
+    }
+    Ok(failures)
+}
+
+// Remember why the removal of a departed unit failed.  None clears it.
+fn set_departure_failed(address: &str, reason: Option<&str>) -> Result<(), String> {
+    let mut failures = failed_departures()?;
+    let changed = match reason {
+        Some(reason) => {
+            // gluster errors span several lines
+            let reason: Vec<&str> = reason.split_whitespace().collect();
+            let reason = reason.join(" ");
+            failures.insert(address.to_string(), reason.clone()) != Some(reason)
+        }
+        None => failures.remove(address).is_some(),
+    };
+    if !changed {
+        return Ok(());
+    }
+    let lines: Vec<String> = failures.iter()
+        .map(|(address, reason)| format!("{} {}", address, reason))
+        .collect();
+    let mut settings: HashMap<String, String> = HashMap::new();
+    settings.insert(FAILED_KEY.to_string(), lines.join("\n"));
+    juju::leader_set(settings).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Drain the bricks of a departed unit out of every volume and then detach it
+/// from the pool.  While the drain runs in the background the address is kept
+/// in leader settings and resume_departures checks on it.
+fn remove_peer(departing_address: &str) -> Result<(), String> {
+    let mut departing = departing_peers()?;
+    let peer = match peer_list()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|peer| peer.hostname == departing_address) {
+        Some(peer) => peer,
+        None => {
+            log!(format!("{} is not a gluster peer.  Nothing to remove", departing_address));
+            if departing.iter().any(|address| address == departing_address) {
+                departing.retain(|address| address != departing_address);
+                set_departing_peers(&departing)?;
+            }
+            return set_departure_failed(departing_address, None);
+        }
+    };
+
+    for volume in get_volumes()? {
+        match volume_info(&volume.name) {
+            Ok(info) => {
+                match shrink_volume(&volume.name, &peer, &info) {
+                    Ok(DrainStep::Done) => {}
+                    Ok(DrainStep::Draining(message)) => {
+                        if !departing.iter().any(|address| address == departing_address) {
+                            departing.push(departing_address.to_string());
+                            set_departing_peers(&departing)?;
+                        }
+                        status_set!(Waiting message);
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        log!(format!("Unable to remove the bricks of {} from {}: {}",
+                                     departing_address,
+                                     volume.name,
+                                     e),
+                             Error);
+                        // update_status reports it and update-status retries it
+                        if departing.iter().any(|address| address == departing_address) {
+                            departing.retain(|address| address != departing_address);
+                            set_departing_peers(&departing)?;
+                        }
+                        return set_departure_failed(departing_address, Some(&e));
+                    }
+                }
+            }
+            Err(gluster::GlusterError::NoVolumesPresent) => {
+                log!(format!("Volume {} doesn't exist.  Nothing to drain", volume.name));
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+    }
+
+    log!(format!("Detaching peer {}", departing_address), Info);
+    peer_remove(&departing_address.to_string(), false).map_err(|e| e.to_string())?;
+    if departing.iter().any(|address| address == departing_address) {
+        departing.retain(|address| address != departing_address);
+        set_departing_peers(&departing)?;
+    }
+    set_departure_failed(departing_address, None)?;
+    status_set!(Active format!("Removed {} from the cluster", departing_address));
+    Ok(())
+}
+
+/// Check on removals whose drain was still running and retry the ones that
+/// failed.  Only the leader does this.
+pub fn resume_departures() -> Result<(), String> {
+    let mut addresses = departing_peers()?;
+    addresses.extend(failed_departures()?.into_iter().map(|(address, _)| address));
+    for address in addresses {
+        log!(format!("Checking on the removal of {}", address), Info);
+        remove_peer(&address)?;
+    }
+    Ok(())
+}
+
+/// Blocked on departed units whose bricks couldn't be removed and waiting
+/// while the bricks of others are still draining
+pub fn departure_conditions() -> Result<Vec<Condition>, String> {
+    let mut conditions: Vec<Condition> = Vec::new();
+    for (address, reason) in failed_departures()? {
+        conditions.push(Condition::blocked(&format!("Unable to remove {}: {}", address, reason)));
+    }
+    let departing = departing_peers()?;
+    if !departing.is_empty() {
+        conditions.push(Condition::waiting(&format!("Draining the bricks of {}",
+                                                    departing.join(", "))));
+    }
+    Ok(conditions)
+}
+
+// Drain all the bricks the peer serves in the volume and then remove them.
+fn shrink_volume(volume_name: &str, peer: &Peer, volume: &Volume) -> Result<DrainStep, String> {
+    let brick_list: Vec<Brick> = volume.bricks
+        .iter()
+        .filter(|brick| brick.peer.uuid == peer.uuid)
+        .cloned()
+        .collect();
+    if brick_list.is_empty() {
+        log!(format!("{} has no bricks in {}", peer.hostname, volume_name));
+        return Ok(DrainStep::Done);
+    }
+    log!(format!("Shrinking volume {} with bricks {}", volume_name, brick_names(&brick_list)),
+         Info);
+
+    drain_bricks(volume_name, volume, &brick_list)
 }
@@ -0,0 +1,15 @@
+extern crate init_daemon;
+
+use self::init_daemon::{detect_daemon, Daemon};
+use super::super::apt::{service_restart, service_start};
+use super::super::package::package_manager;
+
+/// Start glusterd.  Upstart doesn't pick up the config the install hook
+/// wrote unless the job is restarted.
+pub fn start() -> Result<(), String> {
+    let service = package_manager().gluster_service();
+    match detect_daemon()? {
+        Daemon::Systemd => service_start(service),
+        _ => service_restart(service),
+    }
+}
@@ -0,0 +1,6 @@
+use super::super::apt::service_stop;
+use super::super::package::package_manager;
+
+pub fn stop() -> Result<(), String> {
+    service_stop(package_manager().gluster_service())
+}
@@ -0,0 +1,37 @@
+extern crate juju;
+
+use super::super::rebalance::check_pending_rebalance;
+use super::super::snapshot::check_scheduled_snapshots;
+use super::super::upgrade::{process_upgrade, record_version};
+use super::super::volumes::get_volumes;
+use super::server_removed::resume_departures;
+
+/// Periodic work.  Every unit carries on with the rolling upgrade and the
+/// leader handles the rest.  update_status runs after every hook and only
+/// reports on the unit.
+pub fn periodic_tasks() -> Result<(), String> {
+    if let Err(e) = record_version() {
+        log!(format!("Recording the gluster version failed: {}", e), Error);
+    }
+    if let Err(e) = process_upgrade() {
+        log!(format!("Rolling upgrade failed: {}", e), Error);
+    }
+    if !juju::is_leader().map_err(|e| e.to_string())? {
+        return Ok(());
+    }
+    if let Err(e) = resume_departures() {
+        log!(format!("Resuming server removal failed: {}", e), Error);
+    }
+    let volumes = get_volumes()?;
+    for volume in &volumes {
+        if let Err(e) = check_pending_rebalance(&volume.name) {
+            log!(format!("Starting pending rebalance of {} failed: {}", volume.name, e),
+                 Error);
+        }
+    }
+    let names: Vec<String> = volumes.iter().map(|volume| volume.name.clone()).collect();
+    if let Err(e) = check_scheduled_snapshots(&names) {
+        log!(format!("Scheduled snapshots failed: {}", e), Error);
+    }
+    Ok(())
+}
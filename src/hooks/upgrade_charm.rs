@@ -0,0 +1,13 @@
+extern crate juju;
+
+use super::super::upgrade::record_version;
+use super::nrpe_external_master::update_nrpe_checks;
+
+/// The nagios plugin is a copy of the charm binary so it has to be replaced
+/// with the new one.  Older charms didn't tell the peers their version.
+pub fn upgrade_charm() -> Result<(), String> {
+    if let Err(e) = record_version() {
+        log!(format!("Recording the gluster version failed: {}", e), Error);
+    }
+    update_nrpe_checks()
+}
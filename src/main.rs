@@ -1,31 +1,59 @@
 mod actions;
 mod apt;
 mod block;
+mod cron;
 mod ctdb;
+mod ganesha;
+mod georep;
+mod heal;
+mod health;
 mod hooks;
 mod metrics;
+mod nagios;
+mod package;
+mod placement;
+mod rebalance;
+mod remove_brick;
+mod replace_brick;
 mod samba;
+mod snapshot;
 mod updatedb;
 mod upgrade;
+mod volume_detail;
+mod volume_error;
+mod volumes;
+mod yum;
 
-extern crate debian;
 extern crate fstab;
 extern crate gluster;
 extern crate ipnetwork;
-extern crate itertools;
 #[macro_use]
 extern crate juju;
 extern crate resolve;
 extern crate serde_yaml;
 extern crate uuid;
 
-use actions::{disable_volume_quota, enable_volume_quota, list_volume_quotas, set_volume_options};
+use actions::{disable_volume_quota, enable_volume_quota, extend_brick, georep_checkpoint,
+              georep_pause, georep_resume, georep_session_status, list_heal_info, list_split_brain,
+              list_volume_quotas, pause_upgrade, rebalance_volume, replace_brick,
+              resolve_split_brain, resume_upgrade, set_volume_options, snapshot_activate,
+              snapshot_create, snapshot_delete, snapshot_list, snapshot_restore, start_heal,
+              upgrade_plan, virtual_ips};
 use hooks::brick_detached::brick_detached;
 use hooks::config_changed::config_changed;
 use hooks::fuse_relation_joined::fuse_relation_joined;
+use hooks::georep_master::georep_master_changed;
+use hooks::georep_slave::georep_slave_changed;
+use hooks::install::install;
+use hooks::leader_settings_changed::leader_settings_changed;
 use hooks::nfs_relation_joined::nfs_relation_joined;
+use hooks::nrpe_external_master::nrpe_external_master_changed;
 use hooks::server_changed::server_changed;
 use hooks::server_removed::server_removed;
+use hooks::start::start;
+use hooks::stop::stop;
+use hooks::update_status::periodic_tasks;
+use hooks::upgrade_charm::upgrade_charm;
 use metrics::collect_metrics;
 
 use std::collections::BTreeMap;
@@ -41,12 +69,11 @@ use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
 
-use debian::version::Version;
 use gluster::peer::{peer_probe, peer_status, Peer, State};
 use gluster::volume::*;
 use ipnetwork::IpNetwork;
-use itertools::Itertools;
 use juju::{JujuError, unitdata};
+use package::PackageVersion;
 use resolve::address::address_name;
 
 
@@ -126,56 +153,34 @@ mod tests {
         let new_peers = super::find_new_peers(&peers, &volume_info);
         assert_eq!(new_peers, vec![peer2]);
     }
-
     #[test]
-    fn test_cartesian_product() {
-        let peer1 = Peer {
-            uuid: Uuid::new_v4(),
-            hostname: format!("host-{}", Uuid::new_v4()),
-            status: State::PeerInCluster,
-        };
-        let peer2 = Peer {
-            uuid: Uuid::new_v4(),
-            hostname: format!("host-{}", Uuid::new_v4()),
-            status: State::PeerInCluster,
-        };
-        let peers = vec![peer1.clone(), peer2.clone()];
-        let paths = vec!["/mnt/brick1".to_string(), "/mnt/brick2".to_string()];
-        let result = super::brick_and_server_cartesian_product(&peers, &paths);
-        println!("brick_and_server_cartesian_product: {:?}", result);
-        assert_eq!(result,
-                   vec![Brick {
-                            peer: peer1.clone(),
-                            path: PathBuf::from("/mnt/brick1"),
-                        },
-                        Brick {
-                            peer: peer2.clone(),
-                            path: PathBuf::from("/mnt/brick1"),
-                        },
-                        Brick {
-                            peer: peer1.clone(),
-                            path: PathBuf::from("/mnt/brick2"),
-                        },
-                        Brick {
-                            peer: peer2.clone(),
-                            path: PathBuf::from("/mnt/brick2"),
-                        }]);
+    fn test_release_steps() {
+        use super::{release_steps, ReleaseStep};
+        use super::block::{FilesystemType, ThinVolume};
+
+        let thin = ThinVolume::for_device(&PathBuf::from("/dev/sdb")).unwrap();
+        assert_eq!(vec![ReleaseStep::Unmount("/mnt/sdb".to_string()),
+                        ReleaseStep::RemoveFstabEntry("/mnt/sdb".to_string()),
+                        ReleaseStep::RemoveThinVolume(thin.clone()),
+                        ReleaseStep::RemoveDefrag("/mnt/sdb".to_string()),
+                        ReleaseStep::RemoveElevator,
+                        ReleaseStep::ForgetDevice],
+                   release_steps("sdb", &FilesystemType::Xfs, true, Some(thin)));
+        // Already unmounted and no thin volume
+        assert_eq!(vec![ReleaseStep::RemoveFstabEntry("/mnt/sdb".to_string()),
+                        ReleaseStep::RemoveDefrag("/mnt/sdb".to_string()),
+                        ReleaseStep::RemoveElevator,
+                        ReleaseStep::ForgetDevice],
+                   release_steps("sdb", &FilesystemType::Ext4, false, None));
+        // Zfs destroys the pool instead of unmounting
+        assert_eq!(vec![ReleaseStep::DestroyZpool("sdb".to_string()),
+                        ReleaseStep::RemoveDefrag("/mnt/sdb".to_string()),
+                        ReleaseStep::RemoveElevator,
+                        ReleaseStep::ForgetDevice],
+                   release_steps("sdb", &FilesystemType::Zfs, true, None));
     }
 }
 
-// Need more expressive return values so we can wait on peers
-#[derive(Debug)]
-#[allow(dead_code)]
-enum Status {
-    Created,
-    WaitForMorePeers,
-    InvalidConfig(String),
-    FailedToCreate(String),
-    FailedToStart(String),
-}
-
-
-
 fn get_config_value(name: &str) -> Result<String, String> {
     match juju::config_get(&name.to_string()) {
         Ok(v) => Ok(v.unwrap_or_default()),
@@ -185,6 +190,12 @@ fn get_config_value(name: &str) -> Result<String, String> {
     }
 }
 
+// Boolean options come back from config-get as True or False
+fn get_config_flag(name: &str) -> Result<bool, String> {
+    let value = get_config_value(name)?;
+    Ok(value.to_lowercase() == "true")
+}
+
 // Returns None in the case of any error but logs why it happened
 fn get_config_number<T: FromStr<Err = ParseIntError>>(name: &str) -> Option<T> {
     match juju::config_get(&name.to_string()) {
@@ -224,21 +235,25 @@ fn create_sysctl<T: Write>(sysctl: String, f: &mut T) -> Result<usize, String> {
     Ok(bytes_written)
 }
 
-// Return all the virtual ip networks that will be used
+// Return all the virtual ip networks this unit can host.  ctdb spreads the
+// addresses evenly over the units that list them.
 fn get_cluster_networks() -> Result<Vec<ctdb::VirtualIp>, String> {
     let mut cluster_networks: Vec<ctdb::VirtualIp> = Vec::new();
     let config_value = match juju::config_get("virtual_ip_addresses").map_err(|e| e.to_string())? {
         Some(vips) => vips,
         None => return Ok(cluster_networks),
     };
-    let virtual_ips: Vec<&str> = config_value.split(" ").collect();
-    for vip in virtual_ips {
-        if vip.is_empty() {
-            continue;
-        }
-        let network = ctdb::ipnetwork_from_str(vip)?;
-        let interface = ctdb::get_interface_for_address(network)
-            .ok_or(format!("Failed to find interface for network {:?}", network))?;
+    for vip in config_value.split_whitespace() {
+        let (network, interface) = ctdb::parse_vip(vip)?;
+        let interface = match interface.or_else(|| ctdb::get_interface_for_address(network)) {
+            Some(interface) => interface,
+            None => {
+                // Another unit on that network will host it
+                log!(format!("No interface serves {}.  This unit won't host it", network),
+                     Info);
+                continue;
+            }
+        };
         cluster_networks.push(ctdb::VirtualIp {
                                   cidr: network,
                                   interface: interface,
@@ -335,21 +350,6 @@ fn find_new_peers(peers: &Vec<Peer>, volume_info: &Volume) -> Vec<Peer> {
     return new_peers;
 }
 
-fn brick_and_server_cartesian_product(peers: &Vec<Peer>,
-                                      paths: &Vec<String>)
-                                      -> Vec<gluster::volume::Brick> {
-    let mut product: Vec<gluster::volume::Brick> = Vec::new();
-
-    let it = paths.iter().cartesian_product(peers.iter());
-    for (path, host) in it {
-        let brick = gluster::volume::Brick {
-            peer: host.clone(),
-            path: PathBuf::from(path),
-        };
-        product.push(brick);
-    }
-    return product;
-}
 
 fn ephemeral_unmount() -> Result<(), String> {
     match get_config_value("ephemeral_unmount") {
@@ -410,8 +410,13 @@ fn finish_initialization(device_path: &PathBuf) -> Result<(), Error> {
                              device_path.file_name().unwrap().to_string_lossy());
 
     let unit_storage = unitdata::Storage::new(None).map_err(|e| Error::new(ErrorKind::Other, e))?;
+    // lvm-thin bricks mount the thin LV.  udev knows it by its dm-N name.
+    let formatted_path = match block::ThinVolume::for_device(device_path) {
+        Some(ref thin) if thin.exists() => fs::canonicalize(thin.lv_path())?,
+        _ => device_path.clone(),
+    };
     let device_info =
-        block::get_device_info(device_path).map_err(|e| Error::new(ErrorKind::Other, e))?;
+        block::get_device_info(&formatted_path).map_err(|e| Error::new(ErrorKind::Other, e))?;
     log!(format!("device_info: {:?}", device_info), Info);
 
     //Zfs automatically handles mounting the device
@@ -454,6 +459,119 @@ fn finish_initialization(device_path: &PathBuf) -> Result<(), Error> {
     Ok(())
 }
 
+/// One piece of undoing what finish_initialization did for a device
+#[derive(Debug, PartialEq)]
+enum ReleaseStep {
+    /// Zfs mounts the pool itself.  The pool is named after the device.
+    DestroyZpool(String),
+    Unmount(String),
+    RemoveFstabEntry(String),
+    RemoveThinVolume(block::ThinVolume),
+    RemoveDefrag(String),
+    RemoveElevator,
+    ForgetDevice,
+}
+
+// What releasing the device involves.  mounted and thin say what is still
+// there to clean up.
+fn release_steps(device_name: &str,
+                 filesystem_type: &block::FilesystemType,
+                 mounted: bool,
+                 thin: Option<block::ThinVolume>)
+                 -> Vec<ReleaseStep> {
+    let mount_path = format!("/mnt/{}", device_name);
+    let mut steps: Vec<ReleaseStep> = Vec::new();
+    if *filesystem_type == block::FilesystemType::Zfs {
+        steps.push(ReleaseStep::DestroyZpool(device_name.to_string()));
+    } else {
+        if mounted {
+            steps.push(ReleaseStep::Unmount(mount_path.clone()));
+        }
+        steps.push(ReleaseStep::RemoveFstabEntry(mount_path.clone()));
+    }
+    if let Some(thin) = thin {
+        steps.push(ReleaseStep::RemoveThinVolume(thin));
+    }
+    steps.push(ReleaseStep::RemoveDefrag(mount_path));
+    steps.push(ReleaseStep::RemoveElevator);
+    steps.push(ReleaseStep::ForgetDevice);
+    steps
+}
+
+fn run_command(cmd: &mut std::process::Command) -> Result<(), String> {
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    block::process_output(output).map(|_| ())
+}
+
+fn run_release_step(step: &ReleaseStep, device_path: &PathBuf) -> Result<(), String> {
+    match step {
+        &ReleaseStep::DestroyZpool(ref pool) => {
+            log!(format!("Destroying zpool {}", pool), Info);
+            run_command(std::process::Command::new("/sbin/zpool").arg("destroy").arg(pool))
+        }
+        &ReleaseStep::Unmount(ref mount_path) => {
+            // A lazy unmount detaches it now even if something still has
+            // files open on it
+            log!(format!("Unmounting {}", mount_path), Info);
+            run_command(std::process::Command::new("umount").arg("-l").arg(mount_path))
+        }
+        &ReleaseStep::RemoveFstabEntry(ref mount_path) => {
+            let fstab = fstab::FsTab::new(&Path::new("/etc/fstab"));
+            let entries = fstab.get_entries().map_err(|e| e.to_string())?;
+            match entries.iter().find(|entry| entry.mountpoint == Path::new(mount_path)) {
+                Some(entry) => {
+                    log!(format!("Removing {:?} from fstab", entry));
+                    fstab.remove_entry(&entry.fs_spec).map(|_| ()).map_err(|e| e.to_string())
+                }
+                None => Ok(()),
+            }
+        }
+        &ReleaseStep::RemoveThinVolume(ref thin) => {
+            log!(format!("Removing thin volume {:?}", thin.lv_path()), Info);
+            block::remove_thin_volume(device_path, thin)
+        }
+        &ReleaseStep::RemoveDefrag(ref mount_path) => {
+            block::remove_weekly_defrag(mount_path).map(|_| ()).map_err(|e| e.to_string())
+        }
+        &ReleaseStep::RemoveElevator => {
+            block::remove_elevator(device_path).map(|_| ()).map_err(|e| e.to_string())
+        }
+        &ReleaseStep::ForgetDevice => {
+            let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+            unit_storage.unset(&device_path.to_string_lossy()).map_err(|e| e.to_string())
+        }
+    }
+}
+
+// Undo everything finish_initialization did for this device so it can be
+// removed or reused.  Every step is tried even if an earlier one failed so a
+// stuck mount doesn't leave the rest of the device's setup behind.
+fn release_device(device_path: &PathBuf) -> Result<(), String> {
+    let device_name = match device_path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err(format!("Invalid device path {:?}", device_path)),
+    };
+    let mount_path = format!("/mnt/{}", device_name);
+    let filesystem_type = block::FilesystemType::from_str(&get_config_value("filesystem_type")?);
+    let mounted = Path::new(&mount_path).exists() && is_mounted(&mount_path).unwrap_or(true);
+    let thin = block::ThinVolume::for_device(device_path).and_then(|thin| if thin.exists() {
+        Some(thin)
+    } else {
+        None
+    });
+    let mut failures: Vec<String> = Vec::new();
+    for step in release_steps(&device_name, &filesystem_type, mounted, thin) {
+        if let Err(e) = run_release_step(&step, device_path) {
+            log!(format!("{:?} failed: {}", step, e), Error);
+            failures.push(format!("{:?}: {}", step, e));
+        }
+    }
+    if !failures.is_empty() {
+        return Err(failures.join(", "));
+    }
+    Ok(())
+}
+
 // Format and mount block devices to ready them for consumption by Gluster
 // Return an Initialization struct
 fn initialize_storage(device: block::BrickDevice) -> Result<block::AsyncInit, String> {
@@ -461,12 +579,30 @@ fn initialize_storage(device: block::BrickDevice) -> Result<block::AsyncInit, St
 
     //Custom params
     let stripe_width = get_config_number::<u64>("raid_stripe_width");
-    let stripe_size = get_config_number::<u64>("raid_stripe_size");
+    let stripe_size = get_config_number::<u64>("raid_stripe_unit");
     let inode_size = get_config_number::<u64>("inode_size");
 
     let filesystem_type = block::FilesystemType::from_str(&filesystem_config_value);
     let init: block::AsyncInit;
 
+    if let Some(ref thin) = device.thin_volume {
+        if !thin.exists() {
+            match filesystem_type {
+                block::FilesystemType::Btrfs |
+                block::FilesystemType::Zfs => {
+                    return Err(format!("brick_backend lvm-thin does not work with {}",
+                                       filesystem_config_value));
+                }
+                _ => {}
+            }
+            log!(format!("Creating thin volume on {:?}", &device.dev_path), Info);
+            status_set!(Maintenance
+                format!("Creating thin volume on {:?}", &device.dev_path));
+            let chunk_size = block::thin_pool_chunk_size(stripe_size, stripe_width);
+            block::create_thin_volume(&device.dev_path, thin, chunk_size)?;
+        }
+    }
+
     // Format with the default XFS unless told otherwise
     match filesystem_type {
         block::FilesystemType::Xfs => {
@@ -542,10 +678,11 @@ fn initialize_storage(device: block::BrickDevice) -> Result<block::AsyncInit, St
 }
 
 fn resolve_first_vip_to_dns() -> Result<String, String> {
-    let cluster_networks = get_cluster_networks()?;
-    match cluster_networks.first() {
-        Some(cluster_network) => {
-            match cluster_network.cidr {
+    let config_value = get_config_value("virtual_ip_addresses")?;
+    // Every unit hands out the same address even if it can't host it
+    match config_value.split_whitespace().next() {
+        Some(vip) => {
+            match ctdb::parse_vip(vip)?.0 {
                 IpNetwork::V4(ref v4_network) => {
                     // Resolve the ipv4 address back to a dns string
                     Ok(address_name(&::std::net::IpAddr::V4(v4_network.ip())))
@@ -563,29 +700,8 @@ fn resolve_first_vip_to_dns() -> Result<String, String> {
     }
 }
 
-fn get_glusterfs_version() -> Result<Version, String> {
-    let mut cmd = std::process::Command::new("dpkg");
-    cmd.arg("-s");
-    cmd.arg("glusterfs-server");
-    let output = cmd.output().map_err(|e| e.to_string())?;
-    if output.status.success() {
-        let output_str = String::from_utf8_lossy(&output.stdout).into_owned();
-        for line in output_str.lines() {
-            if line.starts_with("Version") {
-                // return the version
-                let parts: Vec<&str> = line.split(" ").collect();
-                if parts.len() == 2 {
-                    let parse_version = Version::parse(&parts[1]).map_err(|e| e.msg)?;
-                    return Ok(parse_version);
-                } else {
-                    return Err(format!("apt-cache Verion string is invalid: {}", line));
-                }
-            }
-        }
-    } else {
-        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
-    }
-    return Err("Unable to find glusterfs-server version".to_string());
+fn get_glusterfs_version() -> Result<PackageVersion, String> {
+    package::package_manager().installed_version("glusterfs-server")
 }
 
 fn is_mounted(directory: &str) -> Result<bool, String> {
@@ -613,22 +729,22 @@ fn is_mounted(directory: &str) -> Result<bool, String> {
     return Ok(false);
 }
 
-// Mount the cluster at /mnt/glusterfs using fuse
-fn mount_cluster(volume_name: &str) -> Result<(), String> {
-    if !Path::new("/mnt/glusterfs").exists() {
-        create_dir("/mnt/glusterfs").map_err(|e| e.to_string())?;
+// Mount the volume at its mount point using fuse
+fn mount_cluster(volume: &volumes::VolumeConfig) -> Result<(), String> {
+    if !Path::new(&volume.mount_point).exists() {
+        create_dir(&volume.mount_point).map_err(|e| e.to_string())?;
     }
-    if !is_mounted("/mnt/glusterfs")? {
+    if !is_mounted(&volume.mount_point)? {
         let mut cmd = std::process::Command::new("mount");
         cmd.arg("-t");
         cmd.arg("glusterfs");
-        cmd.arg(&format!("localhost:/{}", volume_name));
-        cmd.arg("/mnt/glusterfs");
+        cmd.arg(&format!("localhost:/{}", volume.name));
+        cmd.arg(&volume.mount_point);
         let output = cmd.output().map_err(|e| e.to_string())?;
         if output.status.success() {
-            log!("Removing /mnt/glusterfs from updatedb", Info);
-            updatedb::add_to_prunepath(&String::from("/mnt/glusterfs"),
-                                   &Path::new("/etc/updatedb.conf")).map_err(|e| e.to_string())?;
+            log!(format!("Removing {} from updatedb", volume.mount_point), Info);
+            updatedb::add_to_prunepath(&volume.mount_point, &Path::new("/etc/updatedb.conf"))
+                .map_err(|e| e.to_string())?;
             return Ok(());
         } else {
             return Err(String::from_utf8_lossy(&output.stderr).into_owned());
@@ -641,28 +757,156 @@ fn mount_cluster(volume_name: &str) -> Result<(), String> {
 // Update the juju status information
 fn update_status() -> Result<(), String> {
     let version = get_glusterfs_version()?;
-    juju::application_version_set(&format!("{}", version.upstream_version))
+    juju::application_version_set(&format!("{}", version.version))
         .map_err(|e| e.to_string())?;
-    let volume_name = get_config_value("volume_name")?;
-
-    let local_bricks = gluster::get_local_bricks(&volume_name);
-    match local_bricks {
-        Ok(bricks) => {
-            status_set!(Active format!("Unit is ready ({} bricks)", bricks.len()));
-            // Ensure the cluster is mounted
-            mount_cluster(&volume_name)?;
-            Ok(())
+    let volumes = volumes::get_volumes()?;
+    for volume in &volumes {
+        if let Err(e) = volume.validate() {
+            status_set!(Blocked e);
+            return Ok(());
         }
-        Err(gluster::GlusterError::NoVolumesPresent) => {
-            status_set!(Blocked "No bricks found");
-            Ok(())
+    }
+
+    // Checks are added in order of importance.  The worst one sets the
+    // status and the first of those leads the message.
+    let mut conditions: Vec<health::Condition> = Vec::new();
+    let mut brick_count = 0;
+    let mut volumes_found = 0;
+    let mut rebalancing: Vec<String> = Vec::new();
+    let pool = match gluster::peer::peer_list() {
+        Ok(pool) => pool,
+        Err(e) => {
+            conditions.push(health::Condition::blocked(&format!("glusterd is not responding: {}",
+                                                                e.to_string())));
+            Vec::new()
         }
-        _ => Ok(()),
+    };
+    for volume in &volumes {
+        // The status set by the hook that failed is gone by now
+        match volume_error::volume_error(&volume.name) {
+            Ok(Some(e)) => {
+                conditions.push(health::Condition::blocked(&format!("Setting up volume {} \
+                                                                     failed: {}",
+                                                                    volume.name,
+                                                                    e)))
+            }
+            Ok(None) => {}
+            Err(e) => log!(format!("Reading the error of volume {} failed: {}", volume.name, e),
+                           Error),
+        }
+        let info = match volume_info(&volume.name) {
+            Ok(info) => info,
+            Err(gluster::GlusterError::NoVolumesPresent) => {
+                conditions.push(health::Condition::waiting(&format!("Waiting for volume {} to \
+                                                                     be created",
+                                                                    volume.name)));
+                continue;
+            }
+            Err(e) => {
+                conditions.push(health::Condition::blocked(&format!("Volume info of {} \
+                                                                     failed: {}",
+                                                                    volume.name,
+                                                                    e.to_string())));
+                continue;
+            }
+        };
+        match gluster::get_local_bricks(&volume.name) {
+            Ok(bricks) => {
+                brick_count += bricks.len();
+                if info.status == "Started" {
+                    match volume_status(&volume.name) {
+                        Ok(status) => {
+                            conditions.extend(health::volume_conditions(&info, &bricks, &status))
+                        }
+                        Err(e) => {
+                            let message = format!("Volume status of {} failed: {}",
+                                                  volume.name,
+                                                  e.to_string());
+                            conditions.push(health::Condition::blocked(&message))
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                conditions.push(health::Condition::blocked(&format!("Finding local bricks of {} \
+                                                                     failed: {}",
+                                                                    volume.name,
+                                                                    e.to_string())))
+            }
+        }
+        volumes_found += 1;
+        conditions.extend(health::quorum_condition(&info, &pool));
+        if info.status != "Started" {
+            conditions.push(health::Condition::waiting(&format!("Volume {} is {}",
+                                                                volume.name,
+                                                                info.status)));
+            continue;
+        }
+        // Ensure the volume is mounted and the client behind it is alive
+        match mount_cluster(volume) {
+            Ok(_) => conditions.extend(health::mount_condition(&volume.name, &volume.mount_point)),
+            Err(e) => {
+                conditions.push(health::Condition::blocked(&format!("Mounting {} failed: {}",
+                                                                    volume.name,
+                                                                    e)))
+            }
+        }
+        conditions.extend(health::heal_condition(&volume.name));
+        // Rebalance status fails if no rebalance was ever run
+        if let Ok(rebalance) = rebalance::rebalance_status(&volume.name) {
+            if !rebalance.finished() {
+                rebalancing.push(format!("{} {}%", volume.name, rebalance.progress()));
+            }
+        }
+    }
+    if volumes_found > 0 && brick_count == 0 {
+        conditions.push(health::Condition::blocked("No bricks found"));
     }
+    match peer_status() {
+        Ok(peers) => conditions.extend(health::peer_conditions(&peers, &pool)),
+        Err(e) => log!(format!("Peer status failed: {}", e.to_string()), Error),
+    }
+    if let Some(georep) = georep::georep_summary() {
+        conditions.push(health::Condition::active(&georep));
+    }
+    if juju::config_get("virtual_ip_addresses").map_err(|e| e.to_string())?.is_some() {
+        conditions.push(health::ctdb_condition());
+    }
+    match samba::samba_condition() {
+        Ok(condition) => conditions.extend(condition),
+        Err(e) => log!(format!("Reading the samba config failed: {}", e), Error),
+    }
+    match upgrade::upgrade_path_condition() {
+        Ok(condition) => conditions.extend(condition),
+        Err(e) => log!(format!("Reading the upgrade path check failed: {}", e), Error),
+    }
+    match upgrade::upgrade_condition() {
+        Ok(condition) => conditions.extend(condition),
+        Err(e) => log!(format!("Reading the upgrade plan failed: {}", e), Error),
+    }
+    match upgrade::version_condition() {
+        Ok(condition) => conditions.extend(condition),
+        Err(e) => log!(format!("Reading the peer versions failed: {}", e), Error),
+    }
+    match hooks::server_removed::departure_conditions() {
+        Ok(condition) => conditions.extend(condition),
+        Err(e) => log!(format!("Reading the departing peers failed: {}", e), Error),
+    }
+    if !rebalancing.is_empty() {
+        conditions.push(health::Condition::active(&format!("Rebalancing {}",
+                                                           rebalancing.join(", "))));
+    }
+    health::set_status(&conditions,
+                       &format!("Unit is ready ({} bricks)", brick_count));
+    Ok(())
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "nagios" {
+        // nrpe runs the checks outside of any hook so skip the hook registry
+        std::process::exit(nagios::run_check(&args[2..]));
+    }
     if args.len() > 0 {
         // Register our hooks with the Juju library
         let hook_registry: Vec<juju::Hook> =
@@ -671,19 +915,54 @@ fn main() {
                  hook!("config-changed", config_changed),
                  hook!("create-volume-quota", enable_volume_quota),
                  hook!("delete-volume-quota", disable_volume_quota),
+                 hook!("extend-brick", extend_brick),
                  hook!("fuse-relation-joined", fuse_relation_joined),
+                 hook!("georep-checkpoint", georep_checkpoint),
+                 hook!("georep-master-relation-changed", georep_master_changed),
+                 hook!("georep-master-relation-joined", georep_master_changed),
+                 hook!("georep-pause", georep_pause),
+                 hook!("georep-resume", georep_resume),
+                 hook!("georep-slave-relation-changed", georep_slave_changed),
+                 hook!("georep-slave-relation-departed", georep_slave_changed),
+                 hook!("georep-slave-relation-joined", georep_slave_changed),
+                 hook!("georep-status", georep_session_status),
+                 hook!("heal-info", list_heal_info),
+                 hook!("heal-volume", start_heal),
+                 hook!("install", install),
+                 hook!("leader-settings-changed", leader_settings_changed),
                  hook!("list-volume-quotas", list_volume_quotas),
                  hook!("nfs-relation-joined", nfs_relation_joined),
+                 hook!("nrpe-external-master-relation-changed", nrpe_external_master_changed),
+                 hook!("nrpe-external-master-relation-joined", nrpe_external_master_changed),
+                 hook!("pause-upgrade", pause_upgrade),
+                 hook!("rebalance-volume", rebalance_volume),
+                 hook!("replace-brick", replace_brick),
+                 hook!("resolve-split-brain", resolve_split_brain),
+                 hook!("resume-upgrade", resume_upgrade),
                  hook!("server-relation-changed", server_changed),
                  hook!("server-relation-departed", server_removed),
                  hook!("set-volume-options", set_volume_options),
-                 hook!("update-status", update_status)];
+                 hook!("snapshot-activate", snapshot_activate),
+                 hook!("snapshot-create", snapshot_create),
+                 hook!("snapshot-delete", snapshot_delete),
+                 hook!("snapshot-list", snapshot_list),
+                 hook!("snapshot-restore", snapshot_restore),
+                 hook!("split-brain-info", list_split_brain),
+                 hook!("start", start),
+                 hook!("stop", stop),
+                 hook!("upgrade-charm", upgrade_charm),
+                 hook!("upgrade-plan", upgrade_plan),
+                 hook!("virtual-ips", virtual_ips),
+                 hook!("update-status", periodic_tasks)];
 
         let result = juju::process_hooks(hook_registry);
 
-        if result.is_err() {
-            log!(format!("Hook failed with error: {:?}", result.err()), Error);
-        }
         update_status();
+        if let Err(e) = result {
+            log!(format!("Hook failed with error: {:?}", e), Error);
+            // Juju only holds things like a storage detach and retries the
+            // hook if it exits non-zero
+            std::process::exit(1);
+        }
     }
 }
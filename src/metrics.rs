@@ -1,20 +1,448 @@
+extern crate gluster;
 extern crate juju;
 extern crate nix;
 
+use std::collections::BTreeMap;
+use std::fs::{rename, File};
+use std::io::Write;
 use std::path::Path;
+use std::process::Command;
+use self::gluster::peer::peer_list;
 use self::nix::sys::statvfs::vfs::Statvfs;
+use super::get_config_value;
+use super::heal::heal_count;
+use super::rebalance::rebalance_status;
+use super::volumes::get_volumes;
 
-pub fn collect_metrics() -> Result<(), String> {
-    let p = Path::new("/mnt/glusterfs");
+/// One labelled value of a metric
+#[derive(Debug, PartialEq)]
+pub struct Sample {
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+/// A metric family in the Prometheus text format
+#[derive(Debug, PartialEq)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    /// Counters only go up.  Everything else is a gauge.
+    pub counter: bool,
+    pub samples: Vec<Sample>,
+}
+
+impl Metric {
+    fn gauge(name: &'static str, help: &'static str) -> Metric {
+        Metric {
+            name: name,
+            help: help,
+            counter: false,
+            samples: Vec::new(),
+        }
+    }
+
+    fn counter(name: &'static str, help: &'static str) -> Metric {
+        Metric { counter: true, ..Metric::gauge(name, help) }
+    }
+
+    fn add(&mut self, labels: Vec<(&'static str, String)>, value: f64) {
+        self.samples.push(Sample {
+            labels: labels,
+            value: value,
+        });
+    }
+}
+
+// Label values escape backslashes, quotes and newlines
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Write the metrics out in the Prometheus text exposition format
+pub fn render_prometheus<T: Write>(f: &mut T,
+                                   metrics: &Vec<Metric>)
+                                   -> Result<usize, ::std::io::Error> {
+    let mut bytes_written = 0;
+    for metric in metrics {
+        bytes_written += f.write(&format!("# HELP {} {}\n", metric.name, metric.help)
+                .as_bytes())?;
+        let metric_type = if metric.counter { "counter" } else { "gauge" };
+        bytes_written += f.write(&format!("# TYPE {} {}\n", metric.name, metric_type)
+                .as_bytes())?;
+        for sample in &metric.samples {
+            let labels: Vec<String> = sample.labels
+                .iter()
+                .map(|&(key, ref value)| format!("{}=\"{}\"", key, escape_label(value)))
+                .collect();
+            if labels.is_empty() {
+                bytes_written += f.write(&format!("{} {}\n", metric.name, sample.value)
+                        .as_bytes())?;
+            } else {
+                bytes_written += f.write(&format!("{}{{{}}} {}\n",
+                                                  metric.name,
+                                                  labels.join(","),
+                                                  sample.value)
+                        .as_bytes())?;
+            }
+        }
+    }
+    Ok(bytes_written)
+}
+
+/// Space and inode usage of a mounted filesystem
+pub struct FsUsage {
+    pub size: u64,
+    pub used: u64,
+    pub inodes: u64,
+    pub inodes_used: u64,
+}
+
+pub fn fs_usage(mount_point: &str) -> Result<FsUsage, String> {
+    let p = Path::new(mount_point);
     let mount_stats = Statvfs::for_path(p).map_err(|e| e.to_string())?;
     // block size * total blocks
     let total_space = mount_stats.f_blocks * mount_stats.f_bsize;
     let free_space = mount_stats.f_bfree * mount_stats.f_bsize;
-    // capsize only operates on i64 values
-    let used_space = total_space - free_space;
-    let gb_used = used_space / 1024 / 1024 / 1024;
+    Ok(FsUsage {
+        size: total_space,
+        used: total_space - free_space,
+        inodes: mount_stats.f_files,
+        inodes_used: mount_stats.f_files - mount_stats.f_ffree,
+    })
+}
+
+/// Latency and call count of one file operation on a brick from volume
+/// profile.  Latencies are in microseconds.
+#[derive(Debug, PartialEq)]
+pub struct FopStats {
+    pub brick: String,
+    pub fop: String,
+    pub avg_latency: f64,
+    pub min_latency: f64,
+    pub max_latency: f64,
+    pub calls: u64,
+}
+
+// Brick: 10.0.0.1:/mnt/sdb
+// ------------------------
+// Cumulative Stats:
+//  %-latency   Avg-latency   Min-Latency   Max-Latency   No. of calls         Fop
+//  ---------   -----------   -----------   -----------   ------------        ----
+//       1.86      60.50 us      29.00 us      92.00 us              2      STATFS
+pub fn parse_profile_info(output: &str) -> Vec<FopStats> {
+    let mut stats: Vec<FopStats> = Vec::new();
+    let mut brick = String::new();
+    for line in output.lines() {
+        if line.starts_with("Brick: ") {
+            brick = line["Brick: ".len()..].trim().to_string();
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 9 || parts[2] != "us" {
+            continue;
+        }
+        let latency = |s: &str| s.parse::<f64>().ok();
+        if let (Some(avg), Some(min), Some(max), Ok(calls)) =
+            (latency(parts[1]), latency(parts[3]), latency(parts[5]), parts[7].parse::<u64>()) {
+            stats.push(FopStats {
+                brick: brick.clone(),
+                fop: parts[8].to_string(),
+                avg_latency: avg,
+                min_latency: min,
+                max_latency: max,
+                calls: calls,
+            });
+        }
+    }
+    stats
+}
+
+// The charm turns on latency measurement and fop counting for every volume
+// so profile info works without profile start
+fn profile_info(volume: &str) -> Result<Vec<FopStats>, String> {
+    let output = Command::new("gluster").args(&["volume", "profile", volume, "info",
+                                                "cumulative"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(parse_profile_info(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Everything this unit knows about the cluster.  Volume wide numbers are
+/// reported by every unit.
+pub fn gather_metrics() -> Result<Vec<Metric>, String> {
+    let mut volume_size = Metric::gauge("gluster_volume_size_bytes", "Size of the volume");
+    let mut volume_used = Metric::gauge("gluster_volume_used_bytes", "Space used on the volume");
+    let mut volume_inodes = Metric::gauge("gluster_volume_inodes", "Inodes on the volume");
+    let mut volume_inodes_used = Metric::gauge("gluster_volume_inodes_used",
+                                               "Inodes used on the volume");
+    let mut brick_size = Metric::gauge("gluster_brick_size_bytes",
+                                       "Size of the filesystem holding the local brick");
+    let mut brick_used = Metric::gauge("gluster_brick_used_bytes",
+                                       "Space used on the filesystem holding the local brick");
+    let mut brick_inodes = Metric::gauge("gluster_brick_inodes",
+                                         "Inodes on the filesystem holding the local brick");
+    let mut brick_inodes_used =
+        Metric::gauge("gluster_brick_inodes_used",
+                      "Inodes used on the filesystem holding the local brick");
+    let mut heal_pending = Metric::gauge("gluster_heal_pending_entries",
+                                         "Entries waiting to be healed on each brick");
+    let mut rebalance_progress = Metric::gauge("gluster_rebalance_progress_percent",
+                                               "Progress of the last rebalance");
+    let mut fop_avg = Metric::gauge("gluster_fop_latency_avg_microseconds",
+                                    "Average latency of each file operation on each brick");
+    let mut fop_min = Metric::gauge("gluster_fop_latency_min_microseconds",
+                                    "Lowest latency of each file operation on each brick");
+    let mut fop_max = Metric::gauge("gluster_fop_latency_max_microseconds",
+                                    "Highest latency of each file operation on each brick");
+    let mut fop_calls = Metric::counter("gluster_fop_calls_total",
+                                        "Calls of each file operation on each brick");
+    let mut peers = Metric::gauge("gluster_peers", "Peers in the pool by state");
 
-    log!(format!("Collecting metric gb-used {}", gb_used), Info);
-    juju::add_metric("gb-used", &format!("{}", gb_used)).map_err(|e| e.to_string())?;
+    for volume in get_volumes()? {
+        let name = || vec![("volume", volume.name.clone())];
+        match fs_usage(&volume.mount_point) {
+            Ok(usage) => {
+                volume_size.add(name(), usage.size as f64);
+                volume_used.add(name(), usage.used as f64);
+                volume_inodes.add(name(), usage.inodes as f64);
+                volume_inodes_used.add(name(), usage.inodes_used as f64);
+            }
+            Err(e) => {
+                log!(format!("Unable to stat {}: {}.  Skipping", volume.mount_point, e));
+            }
+        }
+        let bricks = match gluster::get_local_bricks(&volume.name) {
+            Ok(bricks) => bricks,
+            // The volume isn't created yet
+            Err(_) => continue,
+        };
+        for brick in bricks {
+            let path = brick.path.to_string_lossy().into_owned();
+            let labels = || vec![("volume", volume.name.clone()), ("brick", path.clone())];
+            if let Ok(usage) = fs_usage(&path) {
+                brick_size.add(labels(), usage.size as f64);
+                brick_used.add(labels(), usage.used as f64);
+                brick_inodes.add(labels(), usage.inodes as f64);
+                brick_inodes_used.add(labels(), usage.inodes_used as f64);
+            }
+        }
+        match heal_count(&volume.name) {
+            Ok(counts) => {
+                // Offline bricks can't be counted
+                for count in counts {
+                    if let Some(entries) = count.entries {
+                        heal_pending.add(vec![("volume", volume.name.clone()),
+                                              ("brick", count.brick.clone())],
+                                         entries as f64);
+                    }
+                }
+            }
+            Err(e) => log!(format!("Heal count of {} failed: {}", volume.name, e)),
+        }
+        // Rebalance status fails if no rebalance was ever run
+        if let Ok(status) = rebalance_status(&volume.name) {
+            rebalance_progress.add(name(), status.progress() as f64);
+        }
+        match profile_info(&volume.name) {
+            Ok(stats) => {
+                for stat in stats {
+                    let labels = || {
+                        vec![("volume", volume.name.clone()),
+                             ("brick", stat.brick.clone()),
+                             ("fop", stat.fop.clone())]
+                    };
+                    fop_avg.add(labels(), stat.avg_latency);
+                    fop_min.add(labels(), stat.min_latency);
+                    fop_max.add(labels(), stat.max_latency);
+                    fop_calls.add(labels(), stat.calls as f64);
+                }
+            }
+            Err(e) => log!(format!("Profile info of {} failed: {}", volume.name, e)),
+        }
+    }
+
+    let mut states: BTreeMap<String, u64> = BTreeMap::new();
+    for peer in peer_list().map_err(|e| e.to_string())? {
+        *states.entry(peer.status.to_string()).or_insert(0) += 1;
+    }
+    for (state, count) in states {
+        peers.add(vec![("state", state)], count as f64);
+    }
+
+    Ok(vec![volume_size,
+            volume_used,
+            volume_inodes,
+            volume_inodes_used,
+            brick_size,
+            brick_used,
+            brick_inodes,
+            brick_inodes_used,
+            heal_pending,
+            rebalance_progress,
+            fop_avg,
+            fop_min,
+            fop_max,
+            fop_calls,
+            peers])
+}
+
+/// Write the metrics where the node exporter textfile collector picks them
+/// up.  The file is replaced in one go so a scrape never sees half of it.
+pub fn write_prometheus_textfile(metrics: &Vec<Metric>) -> Result<(), String> {
+    let textfile_dir = get_config_value("prometheus_textfile_dir")?;
+    if textfile_dir.is_empty() {
+        return Ok(());
+    }
+    if !Path::new(&textfile_dir).exists() {
+        log!(format!("{} doesn't exist.  Is the node exporter installed?", textfile_dir));
+        return Ok(());
+    }
+    let path = Path::new(&textfile_dir).join("gluster.prom");
+    let tmp_path = Path::new(&textfile_dir).join("gluster.prom.tmp");
+    {
+        let mut f = File::create(&tmp_path).map_err(|e| e.to_string())?;
+        render_prometheus(&mut f, metrics).map_err(|e| e.to_string())?;
+    }
+    rename(&tmp_path, &path).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// The juju crate can't label metrics so call add-metric directly
+fn add_volume_metric(key: &str, value: &str, volume: &str) -> Result<(), String> {
+    let output = Command::new("add-metric").arg("--labels")
+        .arg(format!("volume={}", volume))
+        .arg(format!("{}={}", key, value))
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
     Ok(())
 }
+
+// Sum of the samples of a metric that belong to the volume
+fn volume_total(metric: &Metric, volume: &str) -> Option<f64> {
+    let values: Vec<f64> = metric.samples
+        .iter()
+        .filter(|sample| {
+            sample.labels.iter().any(|&(key, ref value)| key == "volume" && value == volume)
+        })
+        .map(|sample| sample.value)
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().fold(0.0, |acc, value| acc + value))
+}
+
+fn find_metric<'a>(metrics: &'a Vec<Metric>, name: &str) -> Option<&'a Metric> {
+    metrics.iter().find(|metric| metric.name == name)
+}
+
+pub fn collect_metrics() -> Result<(), String> {
+    let metrics = gather_metrics()?;
+    if let Err(e) = write_prometheus_textfile(&metrics) {
+        log!(format!("Writing the prometheus textfile failed: {}", e), Error);
+    }
+
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    // Juju metric name, prometheus metric and the unit to report it in
+    let juju_metrics = [("gb-used", "gluster_volume_used_bytes", GB),
+                        ("gb-total", "gluster_volume_size_bytes", GB),
+                        ("inodes-used", "gluster_volume_inodes_used", 1.0),
+                        ("heal-pending", "gluster_heal_pending_entries", 1.0),
+                        ("rebalance-progress", "gluster_rebalance_progress_percent", 1.0)];
+    for volume in get_volumes()? {
+        for &(key, name, unit) in juju_metrics.iter() {
+            let total = find_metric(&metrics, name).and_then(|metric| volume_total(metric,
+                                                                                   &volume.name));
+            if let Some(total) = total {
+                let value = (total / unit) as u64;
+                log!(format!("Collecting metric {} {} for volume {}", key, value, volume.name),
+                     Info);
+                // One metric failing shouldn't lose the rest
+                if let Err(e) = add_volume_metric(key, &format!("{}", value), &volume.name) {
+                    log!(format!("Adding metric {} for volume {} failed: {}", key, volume.name, e),
+                         Error);
+                }
+            }
+        }
+    }
+
+    if let Some(peers) = find_metric(&metrics, "gluster_peers") {
+        let connected = peers.samples
+            .iter()
+            .filter(|sample| sample.labels.iter().any(|&(_, ref state)| state == "Connected"))
+            .fold(0.0, |acc, sample| acc + sample.value);
+        juju::add_metric("peers-connected", &format!("{}", connected))
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_profile_info() {
+    let output = r#"Brick: 10.0.0.1:/mnt/sdb
+------------------------
+Cumulative Stats:
+   Block Size:                  4b+                 8b+
+ No. of Reads:                    0                   0
+No. of Writes:                    1                   3
+ %-latency   Avg-latency   Min-Latency   Max-Latency   No. of calls         Fop
+ ---------   -----------   -----------   -----------   ------------        ----
+      0.00       0.00 us       0.00 us       0.00 us              2     RELEASE
+      1.86      60.50 us      29.00 us      92.00 us              2      STATFS
+     98.14    1066.00 us     105.00 us    3911.00 us              6      LOOKUP
+
+    Duration: 1234 seconds
+   Data Read: 0 bytes
+Data Written: 17 bytes
+
+Brick: 10.0.0.2:/mnt/sdb
+------------------------
+Cumulative Stats:
+ %-latency   Avg-latency   Min-Latency   Max-Latency   No. of calls         Fop
+ ---------   -----------   -----------   -----------   ------------        ----
+    100.00      45.00 us      45.00 us      45.00 us              1      STATFS
+"#;
+    let stats = parse_profile_info(output);
+    assert_eq!(stats.len(), 4);
+    assert_eq!(stats[2],
+               FopStats {
+                   brick: "10.0.0.1:/mnt/sdb".to_string(),
+                   fop: "LOOKUP".to_string(),
+                   avg_latency: 1066.0,
+                   min_latency: 105.0,
+                   max_latency: 3911.0,
+                   calls: 6,
+               });
+    assert_eq!(stats[3].brick, "10.0.0.2:/mnt/sdb");
+}
+
+#[test]
+fn test_render_prometheus() {
+    let mut used = Metric::gauge("gluster_volume_used_bytes", "Space used on the volume");
+    used.add(vec![("volume", "test".to_string())], 1536.0);
+    let mut calls = Metric::counter("gluster_fop_calls_total", "Calls of each file operation");
+    calls.add(vec![("volume", "test".to_string()),
+                   ("brick", "10.0.0.1:/mnt/\"sdb\"".to_string())],
+              6.0);
+    let mut peers = Metric::gauge("gluster_peers", "Peers in the pool by state");
+    peers.add(Vec::new(), 0.5);
+    let mut buff: Vec<u8> = Vec::new();
+    render_prometheus(&mut buff, &vec![used, calls, peers]).unwrap();
+    let expected = r#"# HELP gluster_volume_used_bytes Space used on the volume
+# TYPE gluster_volume_used_bytes gauge
+gluster_volume_used_bytes{volume="test"} 1536
+# HELP gluster_fop_calls_total Calls of each file operation
+# TYPE gluster_fop_calls_total counter
+gluster_fop_calls_total{volume="test",brick="10.0.0.1:/mnt/\"sdb\""} 6
+# HELP gluster_peers Peers in the pool by state
+# TYPE gluster_peers gauge
+gluster_peers 0.5
+"#;
+    assert_eq!(expected, String::from_utf8_lossy(&buff));
+}
@@ -0,0 +1,594 @@
+extern crate gluster;
+extern crate juju;
+
+use std::collections::BTreeMap;
+use std::fs::{canonicalize, File};
+use std::io::Read;
+use std::process::Command;
+
+use self::gluster::GlusterError;
+use self::gluster::volume::{quota_list, volume_info, volume_list, volume_quotas_enabled,
+                            volume_status};
+use super::georep::{georep_status, session_status, sessions};
+use super::heal::split_brain_info;
+use super::metrics::fs_usage;
+
+/// Where the nrpe-external-master hook installs the charm binary
+pub const PLUGIN_PATH: &'static str = "/usr/local/lib/nagios/plugins/check_gluster";
+
+const ONE_GB_BYTES: f64 = 1073741824.0;
+
+/// Nagios plugin return codes.  They don't sort by severity so use worst to
+/// combine them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NagiosStatus {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl NagiosStatus {
+    pub fn to_string(&self) -> String {
+        match *self {
+            NagiosStatus::Ok => "OK".to_string(),
+            NagiosStatus::Warning => "WARNING".to_string(),
+            NagiosStatus::Critical => "CRITICAL".to_string(),
+            NagiosStatus::Unknown => "UNKNOWN".to_string(),
+        }
+    }
+
+    // A full disk matters more than a mount that couldn't be read
+    fn severity(&self) -> u8 {
+        match *self {
+            NagiosStatus::Ok => 0,
+            NagiosStatus::Warning => 1,
+            NagiosStatus::Unknown => 2,
+            NagiosStatus::Critical => 3,
+        }
+    }
+
+    /// The more severe of the two
+    pub fn worst(self, other: NagiosStatus) -> NagiosStatus {
+        if other.severity() > self.severity() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// What a check prints for nagios and how it exits
+#[derive(Debug, PartialEq)]
+pub struct CheckResult {
+    pub status: NagiosStatus,
+    pub message: String,
+    pub perfdata: Vec<String>,
+}
+
+impl CheckResult {
+    fn new(status: NagiosStatus, message: &str) -> CheckResult {
+        CheckResult {
+            status: status,
+            message: message.to_string(),
+            perfdata: Vec::new(),
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        if self.perfdata.is_empty() {
+            format!("{}: {}", self.status.to_string(), self.message)
+        } else {
+            format!("{}: {} | {}",
+                    self.status.to_string(),
+                    self.message,
+                    self.perfdata.join(" "))
+        }
+    }
+}
+
+/// Compare a percentage against the warning and critical thresholds
+pub fn threshold_status(used_percent: f64, warning: u8, critical: u8) -> NagiosStatus {
+    if used_percent >= critical as f64 {
+        NagiosStatus::Critical
+    } else if used_percent >= warning as f64 {
+        NagiosStatus::Warning
+    } else {
+        NagiosStatus::Ok
+    }
+}
+
+fn percent(used: f64, total: f64) -> f64 {
+    if total == 0.0 {
+        return 0.0;
+    }
+    100.0 * used / total
+}
+
+fn gluster_error(e: GlusterError) -> CheckResult {
+    match e {
+        GlusterError::NoVolumesPresent => {
+            CheckResult::new(NagiosStatus::Critical, "Volume not found")
+        }
+        _ => {
+            CheckResult::new(NagiosStatus::Unknown,
+                             &format!("glusterd failed: {}", e.to_string()))
+        }
+    }
+}
+
+/// Whether the volume is started and how many of its bricks are up
+pub fn check_volume_info(volume: &str) -> CheckResult {
+    let info = match volume_info(volume) {
+        Ok(info) => info,
+        Err(e) => return gluster_error(e),
+    };
+    if info.status != "Started" {
+        return CheckResult::new(NagiosStatus::Critical,
+                                &format!("Volume {} is {}", volume, info.status));
+    }
+    let bricks = match volume_status(volume) {
+        Ok(bricks) => bricks,
+        Err(e) => return gluster_error(e),
+    };
+    let down: Vec<String> = bricks.iter()
+        .filter(|brick| !brick.online)
+        .map(|brick| format!("{}:{}", brick.brick.peer.hostname, brick.brick.path.display()))
+        .collect();
+    if down.is_empty() {
+        CheckResult::new(NagiosStatus::Ok,
+                         &format!("Volume {} type {} - All bricks are up",
+                                  volume,
+                                  info.vol_type.to_string()))
+    } else if down.len() == bricks.len() {
+        CheckResult::new(NagiosStatus::Critical,
+                         &format!("Volume {} - All bricks are down", volume))
+    } else {
+        CheckResult::new(NagiosStatus::Warning,
+                         &format!("Volume {} - Bricks down: {}", volume, down.join(", ")))
+    }
+}
+
+/// Quota directories over their hard limit are critical.  Gluster warns at
+/// 80% of the limit by default so that's the warning threshold here too.
+pub fn check_volume_quota(volume: &str) -> CheckResult {
+    match volume_quotas_enabled(volume) {
+        Ok(true) => {}
+        Ok(false) => return CheckResult::new(NagiosStatus::Ok, "Quota not enabled"),
+        Err(e) => return gluster_error(e),
+    }
+    let quotas = match quota_list(volume) {
+        Ok(quotas) => quotas,
+        Err(e) => return gluster_error(e),
+    };
+    let mut hard: Vec<String> = Vec::new();
+    let mut soft: Vec<String> = Vec::new();
+    for quota in quotas {
+        let used_percent = percent(quota.used as f64, quota.limit as f64);
+        match threshold_status(used_percent, 80, 100) {
+            NagiosStatus::Critical => hard.push(quota.path.display().to_string()),
+            NagiosStatus::Warning => soft.push(quota.path.display().to_string()),
+            _ => {}
+        }
+    }
+    if !hard.is_empty() {
+        CheckResult::new(NagiosStatus::Critical,
+                         &format!("Hard limit exceeded on path(s): {}", hard.join(", ")))
+    } else if !soft.is_empty() {
+        CheckResult::new(NagiosStatus::Warning,
+                         &format!("Soft limit exceeded on path(s): {}", soft.join(", ")))
+    } else {
+        CheckResult::new(NagiosStatus::Ok, "Quota usage is within limits")
+    }
+}
+
+/// Files in split-brain need someone to pick the good copy
+pub fn check_volume_split_brain(volume: &str) -> CheckResult {
+    let info = match volume_info(volume) {
+        Ok(info) => info,
+        Err(e) => return gluster_error(e),
+    };
+    if !info.vol_type.to_string().contains("Replicate") {
+        return CheckResult::new(NagiosStatus::Ok,
+                                &format!("Volume {} is not of replicate type", volume));
+    }
+    let bricks = match split_brain_info(volume) {
+        Ok(bricks) => bricks,
+        Err(e) => return CheckResult::new(NagiosStatus::Unknown, &e),
+    };
+    let entries: usize = bricks.iter().map(|brick| brick.entries.len()).sum();
+    let mut result = if entries > 0 {
+        CheckResult::new(NagiosStatus::Critical,
+                         &format!("{} entries found in split-brain state", entries))
+    } else {
+        CheckResult::new(NagiosStatus::Ok, "No split-brain entries found")
+    };
+    result.perfdata.push(format!("split-brain_entries={}", entries));
+    result
+}
+
+/// Map the geo-replication session states of a volume to a nagios status.
+/// A faulty session is critical and one that isn't syncing is a warning.
+pub fn georep_sessions_status(statuses: &Vec<(String, String)>) -> CheckResult {
+    if statuses.is_empty() {
+        return CheckResult::new(NagiosStatus::Ok, "No active geo-replication sessions");
+    }
+    let mut status = NagiosStatus::Ok;
+    let mut messages: Vec<String> = Vec::new();
+    for &(ref slave, ref session) in statuses {
+        let session_status = match session.as_ref() {
+            "Active" | "Passive" | "Changelog Crawl" | "History Crawl" | "Hybrid Crawl" => {
+                NagiosStatus::Ok
+            }
+            "Faulty" => NagiosStatus::Critical,
+            _ => NagiosStatus::Warning,
+        };
+        status = status.worst(session_status);
+        messages.push(format!("{} is {}", slave, session));
+    }
+    CheckResult::new(status,
+                     &format!("Geo-replication session(s): {}", messages.join(", ")))
+}
+
+pub fn check_volume_georep(volume: &str) -> CheckResult {
+    let workers = match georep_status() {
+        Ok(workers) => workers,
+        Err(e) => return CheckResult::new(NagiosStatus::Unknown, &e),
+    };
+    let statuses: Vec<(String, String)> = sessions(&workers)
+        .into_iter()
+        .filter(|&(ref master_volume, _)| master_volume == volume)
+        .map(|(master_volume, slave)| {
+            let status = session_status(&workers, &master_volume, &slave);
+            (slave, status)
+        })
+        .collect();
+    georep_sessions_status(&statuses)
+}
+
+/// Server quorum protects against split-brain when peers drop out.  Without
+/// it on any volume there's nothing to report on.
+pub fn check_quorum_status() -> CheckResult {
+    let volumes = match volume_list() {
+        Some(volumes) => volumes,
+        None => return CheckResult::new(NagiosStatus::Unknown, "No volumes found"),
+    };
+    for volume in volumes {
+        let info = match volume_info(&volume) {
+            Ok(info) => info,
+            Err(e) => return gluster_error(e),
+        };
+        if info.options.get("cluster.server-quorum-type").map(|t| t.as_ref()) == Some("server") {
+            return CheckResult::new(NagiosStatus::Ok, "Server quorum is enabled");
+        }
+    }
+    CheckResult::new(NagiosStatus::Unknown, "Server quorum is not enabled on any volume")
+}
+
+/// Space used on the volume through its client mount point
+pub fn check_vol_utilization(volume: &str,
+                             mount_point: &str,
+                             warning: u8,
+                             critical: u8)
+                             -> CheckResult {
+    let info = match volume_info(volume) {
+        Ok(info) => info,
+        Err(e) => return gluster_error(e),
+    };
+    if info.status != "Started" {
+        return CheckResult::new(NagiosStatus::Unknown,
+                                &format!("Volume {} is {}", volume, info.status));
+    }
+    let usage = match fs_usage(mount_point) {
+        Ok(usage) => usage,
+        Err(e) => {
+            return CheckResult::new(NagiosStatus::Unknown,
+                                    &format!("Unable to stat {}: {}", mount_point, e))
+        }
+    };
+    let used_percent = percent(usage.used as f64, usage.size as f64);
+    let mut result = CheckResult::new(threshold_status(used_percent, warning, critical),
+                                      &format!("Utilization:{:.0}%", used_percent));
+    result.perfdata.push(format!("utilization={:.0}%;{};{}", used_percent, warning, critical));
+    result.perfdata.push(format!("total={}", usage.size / 1024));
+    result.perfdata.push(format!("used={}", usage.used / 1024));
+    result.perfdata.push(format!("free={}", (usage.size - usage.used) / 1024));
+    result
+}
+
+/// A logical volume from lvs.  Sizes are in GiB.
+#[derive(Debug, PartialEq)]
+pub struct LogicalVolume {
+    pub path: String,
+    pub volume_group: String,
+    pub name: String,
+    /// The thin pool a thin volume lives in
+    pub pool: Option<String>,
+    pub size: f64,
+    /// Only set on thin pools
+    pub metadata_size: Option<f64>,
+    pub data_percent: Option<f64>,
+    pub metadata_percent: Option<f64>,
+}
+
+const LVS_FIELDS: &'static str = "lv_path,vg_name,lv_name,pool_lv,lv_size,lv_metadata_size,\
+                                  data_percent,metadata_percent";
+
+// /dev/gluster_sdb/brick|gluster_sdb|brick|brick_pool|9.00||12.50|
+//  |gluster_sdb|brick_pool||9.50|0.01|11.84|1.25
+pub fn parse_lvs(output: &str) -> Vec<LogicalVolume> {
+    let optional = |field: &str| -> Option<String> {
+        let field = field.trim();
+        if field.is_empty() {
+            None
+        } else {
+            Some(field.to_string())
+        }
+    };
+    let number = |field: &str| optional(field).and_then(|f| f.parse::<f64>().ok());
+    output.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('|').collect();
+            if fields.len() < 8 {
+                return None;
+            }
+            Some(LogicalVolume {
+                path: fields[0].trim().to_string(),
+                volume_group: fields[1].trim().to_string(),
+                name: fields[2].trim().to_string(),
+                pool: optional(fields[3]),
+                size: number(fields[4]).unwrap_or(0.0),
+                metadata_size: number(fields[5]),
+                data_percent: number(fields[6]),
+                metadata_percent: number(fields[7]),
+            })
+        })
+        .collect()
+}
+
+fn logical_volumes() -> Vec<LogicalVolume> {
+    let mut cmd = Command::new("lvs");
+    cmd.args(&["--noheadings", "--nosuffix", "--units", "g", "--separator", "|", "-o",
+                LVS_FIELDS]);
+    match cmd.output() {
+        Ok(ref output) if output.status.success() => {
+            parse_lvs(&String::from_utf8_lossy(&output.stdout))
+        }
+        // No lvm tools or volumes means no thin pools to check
+        _ => Vec::new(),
+    }
+}
+
+/// The thin pool backing the device if it's a thin volume
+fn thin_pool_for_device<'a>(device: &str,
+                            volumes: &'a Vec<LogicalVolume>)
+                            -> Option<&'a LogicalVolume> {
+    let device = canonicalize(device).ok()?;
+    let thin = volumes.iter()
+        .find(|lv| !lv.path.is_empty() && canonicalize(&lv.path).ok().as_ref() == Some(&device))?;
+    let pool = thin.pool.as_ref()?;
+    volumes.iter().find(|lv| lv.volume_group == thin.volume_group && &lv.name == pool)
+}
+
+// /dev/sdb /mnt/sdb xfs rw,noatime,inode64 0 0
+/// The (device, mount point) of every block device mount
+pub fn parse_proc_mounts(contents: &str) -> Vec<(String, String)> {
+    contents.lines()
+        .filter(|line| line.starts_with('/'))
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 2 {
+                return None;
+            }
+            Some((parts[0].to_string(), parts[1].to_string()))
+        })
+        .collect()
+}
+
+/// Space, inode and thin pool usage of the mounted filesystems.  include
+/// limits the check to those mount points and exclude skips them.
+pub fn check_mounts(warning: u8,
+                    critical: u8,
+                    include: &Vec<String>,
+                    exclude: &Vec<String>)
+                    -> CheckResult {
+    let mut contents = String::new();
+    if let Err(e) = File::open("/proc/mounts").and_then(|mut f| f.read_to_string(&mut contents)) {
+        return CheckResult::new(NagiosStatus::Unknown,
+                                &format!("Unable to read /proc/mounts: {}", e));
+    }
+    let volumes = logical_volumes();
+    let mut status = NagiosStatus::Ok;
+    let mut messages: Vec<String> = Vec::new();
+    let mut perfdata: Vec<String> = Vec::new();
+    for (device, mount_point) in parse_proc_mounts(&contents) {
+        if (!include.is_empty() && !include.contains(&mount_point)) ||
+           exclude.contains(&mount_point) {
+            continue;
+        }
+        let usage = match fs_usage(&mount_point) {
+            Ok(usage) => usage,
+            Err(e) => {
+                status = status.worst(NagiosStatus::Unknown);
+                messages.push(format!("{}: {}", mount_point, e));
+                continue;
+            }
+        };
+        // name, used, total, used percent
+        let mut checks = vec![("space",
+                               usage.used as f64 / ONE_GB_BYTES,
+                               usage.size as f64 / ONE_GB_BYTES,
+                               percent(usage.used as f64, usage.size as f64)),
+                              ("inode",
+                               usage.inodes_used as f64,
+                               usage.inodes as f64,
+                               percent(usage.inodes_used as f64, usage.inodes as f64))];
+        if let Some(pool) = thin_pool_for_device(&device, &volumes) {
+            let data_percent = pool.data_percent.unwrap_or(0.0);
+            let metadata_percent = pool.metadata_percent.unwrap_or(0.0);
+            let metadata_size = pool.metadata_size.unwrap_or(0.0);
+            checks.push(("thinpool",
+                         pool.size * data_percent / 100.0,
+                         pool.size,
+                         data_percent));
+            checks.push(("thinpool-metadata",
+                         metadata_size * metadata_percent / 100.0,
+                         metadata_size,
+                         metadata_percent));
+        }
+        let mut exceeded: Vec<String> = Vec::new();
+        for (name, used, total, used_percent) in checks {
+            let check_status = threshold_status(used_percent, warning, critical);
+            if check_status != NagiosStatus::Ok {
+                status = status.worst(check_status);
+                exceeded.push(format!("{} used {:.3} / {:.3}", name, used, total));
+            }
+            let label = if name == "space" {
+                mount_point.clone()
+            } else {
+                format!("{}.{}", mount_point, name)
+            };
+            perfdata.push(format!("{}={:.3}%;{};{};0;{:.3}",
+                                  label,
+                                  used_percent,
+                                  warning,
+                                  critical,
+                                  total));
+        }
+        if !exceeded.is_empty() {
+            messages.push(format!("mount point {} {{{}}}", mount_point, exceeded.join(", ")));
+        }
+    }
+    let mut result = if messages.is_empty() {
+        CheckResult::new(status, "All mount points are within their thresholds")
+    } else {
+        CheckResult::new(status, &messages.join(", "))
+    };
+    result.perfdata = perfdata;
+    result
+}
+
+/// Collect -x value pairs.  Flags can repeat.
+fn parse_options(args: &[String]) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let mut options: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        if !flag.starts_with('-') {
+            return Err(format!("Unexpected argument {}", flag));
+        }
+        let value = iter.next().ok_or(format!("{} needs a value", flag))?;
+        options.entry(flag.clone()).or_insert_with(Vec::new).push(value.clone());
+    }
+    Ok(options)
+}
+
+fn required<'a>(options: &'a BTreeMap<String, Vec<String>>, flag: &str) -> Result<&'a str, String> {
+    match options.get(flag).and_then(|values| values.last()) {
+        Some(value) => Ok(value),
+        None => Err(format!("{} is required", flag)),
+    }
+}
+
+/// Read the -w and -c percentages.  Critical has to be above warning.
+fn thresholds(options: &BTreeMap<String, Vec<String>>) -> Result<(u8, u8), String> {
+    let warning = required(options, "-w")?.parse::<u8>().map_err(|e| e.to_string())?;
+    let critical = required(options, "-c")?.parse::<u8>().map_err(|e| e.to_string())?;
+    if critical <= warning {
+        return Err("critical threshold must be greater than the warning threshold".to_string());
+    }
+    Ok((warning, critical))
+}
+
+fn run(args: &[String]) -> Result<CheckResult, String> {
+    let check = args.first().ok_or("No check given")?;
+    let options = parse_options(&args[1..])?;
+    match check.as_ref() {
+        "volume-status" => {
+            let volume = required(&options, "-v")?;
+            match required(&options, "-t")? {
+                "info" => Ok(check_volume_info(volume)),
+                "quota" => Ok(check_volume_quota(volume)),
+                "self-heal" => Ok(check_volume_split_brain(volume)),
+                "geo-rep" => Ok(check_volume_georep(volume)),
+                t => Err(format!("Unknown volume-status type {}", t)),
+            }
+        }
+        "quorum-status" => Ok(check_quorum_status()),
+        "vol-utilization" => {
+            let (warning, critical) = thresholds(&options)?;
+            Ok(check_vol_utilization(required(&options, "-v")?,
+                                     required(&options, "-m")?,
+                                     warning,
+                                     critical))
+        }
+        "mounts" => {
+            let (warning, critical) = thresholds(&options)?;
+            let include = options.get("-i").cloned().unwrap_or(Vec::new());
+            let exclude = options.get("-e").cloned().unwrap_or(Vec::new());
+            Ok(check_mounts(warning, critical, &include, &exclude))
+        }
+        _ => Err(format!("Unknown check {}", check)),
+    }
+}
+
+/// Run a check from the command line, print the result and return the
+/// nagios exit code.  Usage:
+///   nagios volume-status -v <volume> -t info|quota|self-heal|geo-rep
+///   nagios quorum-status
+///   nagios vol-utilization -v <volume> -m <mount point> -w <percent> -c <percent>
+///   nagios mounts -w <percent> -c <percent> [-i <mount point>]... [-e <mount point>]...
+pub fn run_check(args: &[String]) -> i32 {
+    let result = match run(args) {
+        Ok(result) => result,
+        Err(e) => CheckResult::new(NagiosStatus::Unknown, &e),
+    };
+    println!("{}", result.to_string());
+    result.status as i32
+}
+
+#[test]
+fn test_parse_lvs() {
+    let output = "  /dev/gluster_sdb/brick|gluster_sdb|brick|brick_pool|9.00||12.50|\n  \
+                  |gluster_sdb|brick_pool||9.50|0.01|11.84|1.25\n";
+    let volumes = parse_lvs(output);
+    assert_eq!(2, volumes.len());
+    assert_eq!(Some("brick_pool".to_string()), volumes[0].pool);
+    assert_eq!(None, volumes[0].metadata_size);
+    assert_eq!("brick_pool", volumes[1].name);
+    assert_eq!(Some(0.01), volumes[1].metadata_size);
+    assert_eq!(Some(1.25), volumes[1].metadata_percent);
+
+    let mounts = parse_proc_mounts("sysfs /sys sysfs rw 0 0\n\
+                                    /dev/sdb /mnt/sdb xfs rw,noatime,inode64 0 0\n\
+                                    10.0.0.1:/test /mnt/glusterfs fuse.glusterfs rw 0 0\n");
+    assert_eq!(vec![("/dev/sdb".to_string(), "/mnt/sdb".to_string())], mounts);
+}
+
+#[test]
+fn test_check_status() {
+    assert_eq!(NagiosStatus::Ok, threshold_status(79.9, 80, 90));
+    assert_eq!(NagiosStatus::Warning, threshold_status(80.0, 80, 90));
+    assert_eq!(NagiosStatus::Critical, threshold_status(95.0, 80, 90));
+    // An unreadable mount doesn't hide a full one
+    assert_eq!(NagiosStatus::Critical,
+               NagiosStatus::Critical.worst(NagiosStatus::Unknown));
+    assert_eq!(NagiosStatus::Critical,
+               NagiosStatus::Unknown.worst(NagiosStatus::Critical));
+    assert_eq!(NagiosStatus::Unknown, NagiosStatus::Warning.worst(NagiosStatus::Unknown));
+    assert_eq!(NagiosStatus::Warning, NagiosStatus::Ok.worst(NagiosStatus::Warning));
+
+    let statuses = vec![("geo@10.0.0.9::test".to_string(), "Active".to_string()),
+                        ("geo@10.0.0.10::test".to_string(), "Stopped".to_string())];
+    let result = georep_sessions_status(&statuses);
+    assert_eq!(NagiosStatus::Warning, result.status);
+    assert_eq!("WARNING: Geo-replication session(s): geo@10.0.0.9::test is Active, \
+                geo@10.0.0.10::test is Stopped",
+               result.to_string());
+
+    let args: Vec<String> = vec!["vol-utilization", "-v", "test", "-m", "/mnt/glusterfs", "-w",
+                                 "90", "-c", "80"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    assert_eq!(NagiosStatus::Unknown as i32, run_check(&args));
+}
@@ -0,0 +1,244 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::Path;
+
+use super::apt;
+use super::yum;
+
+/// A package version as epoch:version-release.  Debian calls the last part
+/// the revision but it compares the same way.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackageVersion {
+    pub epoch: u32,
+    pub version: String,
+    pub release: String,
+}
+
+impl PackageVersion {
+    pub fn parse(s: &str) -> Result<PackageVersion, String> {
+        let s = s.trim();
+        let (epoch, rest) = match s.find(':') {
+            Some(i) => {
+                // rpm prints a missing epoch as (none)
+                let epoch = match &s[..i] {
+                    "(none)" | "" => 0,
+                    epoch => epoch.parse::<u32>().map_err(|e| format!("{}: {}", s, e))?,
+                };
+                (epoch, &s[i + 1..])
+            }
+            None => (0, s),
+        };
+        let (version, release) = match rest.rfind('-') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, ""),
+        };
+        if !version.chars().next().map(|c| c.is_digit(10)).unwrap_or(false) {
+            return Err(format!("Invalid package version: {}", s));
+        }
+        Ok(PackageVersion {
+            epoch: epoch,
+            version: version.to_string(),
+            release: release.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for PackageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.epoch > 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+        write!(f, "{}", self.version)?;
+        if !self.release.is_empty() {
+            write!(f, "-{}", self.release)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for PackageVersion {
+    fn cmp(&self, other: &PackageVersion) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| vercmp(&self.version, &other.version))
+            .then_with(|| vercmp(&self.release, &other.release))
+    }
+}
+
+impl PartialOrd for PackageVersion {
+    fn partial_cmp(&self, other: &PackageVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compare two version strings the way rpm does.  They are split into runs
+/// of digits and letters, digits compare as numbers and beat letters, and a
+/// ~ sorts before anything.  dpkg agrees on every version gluster has
+/// shipped.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.as_bytes();
+    let mut b = b.as_bytes();
+    loop {
+        let separator = |c: &u8| !c.is_ascii_alphanumeric() && *c != b'~';
+        while a.first().map(&separator).unwrap_or(false) {
+            a = &a[1..];
+        }
+        while b.first().map(&separator).unwrap_or(false) {
+            b = &b[1..];
+        }
+        match (a.first(), b.first()) {
+            (Some(&b'~'), Some(&b'~')) => {
+                a = &a[1..];
+                b = &b[1..];
+                continue;
+            }
+            (Some(&b'~'), _) => return Ordering::Less,
+            (_, Some(&b'~')) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            _ => {}
+        }
+        let numeric = a[0].is_ascii_digit();
+        let segment = |s: &[u8]| -> usize {
+            s.iter()
+                .take_while(|c| if numeric {
+                    c.is_ascii_digit()
+                } else {
+                    c.is_ascii_alphabetic()
+                })
+                .count()
+        };
+        let (a_len, b_len) = (segment(a), segment(b));
+        if b_len == 0 {
+            // A number is newer than letters
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let (a_seg, b_seg) = (&a[..a_len], &b[..b_len]);
+        let order = if numeric {
+            let trim = |s: &[u8]| -> usize { s.iter().take_while(|c| **c == b'0').count() };
+            let (a_num, b_num) = (&a_seg[trim(a_seg)..], &b_seg[trim(b_seg)..]);
+            a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num))
+        } else {
+            a_seg.cmp(b_seg)
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+        a = &a[a_len..];
+        b = &b[b_len..];
+    }
+}
+
+/// The package manager of the distribution the unit runs on
+#[derive(Debug, Eq, PartialEq)]
+pub enum PackageManager {
+    /// Debian and Ubuntu
+    Apt,
+    /// CentOS and RHEL 7
+    Yum,
+    /// Fedora and RHEL 8
+    Dnf,
+}
+
+/// Red Hat style distributions all ship /etc/redhat-release
+pub fn package_manager() -> PackageManager {
+    if !Path::new("/etc/redhat-release").exists() {
+        PackageManager::Apt
+    } else if Path::new("/usr/bin/dnf").exists() {
+        PackageManager::Dnf
+    } else {
+        PackageManager::Yum
+    }
+}
+
+impl PackageManager {
+    fn yum_command(&self) -> &'static str {
+        match self {
+            &PackageManager::Dnf => "dnf",
+            _ => "yum",
+        }
+    }
+
+    /// Whether the source config option is one this package manager can use
+    pub fn check_source(&self, source: &str) -> Result<(), String> {
+        match self {
+            &PackageManager::Apt => Ok(()),
+            _ => yum::check_source(source),
+        }
+    }
+
+    /// Add the source config option as a package repository
+    pub fn add_source(&self, source: &str) -> Result<(), String> {
+        match self {
+            &PackageManager::Apt => apt::add_source(source),
+            _ => yum::add_source(self.yum_command(), source),
+        }
+    }
+
+    /// Refresh the package lists
+    pub fn update(&self) -> Result<(), String> {
+        match self {
+            &PackageManager::Apt => apt::apt_update(),
+            _ => yum::update(self.yum_command()),
+        }
+    }
+
+    /// Install packages by their Debian names.  yum and dnf install the
+    /// matching rpm.
+    pub fn install(&self, packages: Vec<&str>) -> Result<(), String> {
+        match self {
+            &PackageManager::Apt => apt::apt_install(packages),
+            _ => yum::install(self.yum_command(), packages),
+        }
+    }
+
+    pub fn installed_version(&self, package: &str) -> Result<PackageVersion, String> {
+        match self {
+            &PackageManager::Apt => apt::installed_version(package),
+            _ => yum::installed_version(yum::rpm_name(package)),
+        }
+    }
+
+    /// The version an install would pick right now
+    pub fn candidate_version(&self, package: &str) -> Result<PackageVersion, String> {
+        match self {
+            &PackageManager::Apt => apt::get_candidate_package_version(package),
+            _ => yum::candidate_version(self.yum_command(), yum::rpm_name(package)),
+        }
+    }
+
+    /// The service running glusterd
+    pub fn gluster_service(&self) -> &'static str {
+        match self {
+            &PackageManager::Apt => "glusterfs-server",
+            _ => "glusterd",
+        }
+    }
+}
+
+#[test]
+fn test_package_versions() {
+    let version = |v: &str| PackageVersion::parse(v).unwrap();
+    assert_eq!(PackageVersion {
+                   epoch: 0,
+                   version: "3.12.2".to_string(),
+                   release: "18.el7".to_string(),
+               },
+               version("(none):3.12.2-18.el7"));
+    assert_eq!("1:3.10.5-ubuntu1~xenial1", version("1:3.10.5-ubuntu1~xenial1").to_string());
+    assert!(PackageVersion::parse("(none)").is_err());
+
+    assert!(version("3.10.5-1") > version("3.8.15-1"));
+    assert!(version("3.12.15-1.el7") > version("3.12.2-18.el7"));
+    assert!(version("1:3.8.0-1") > version("3.12.1-1"));
+    assert!(version("3.12.1-ubuntu1~xenial2") > version("3.12.1-ubuntu1~xenial1"));
+    assert!(version("4.0.0~rc1-1") < version("4.0.0-1"));
+    assert_eq!(Ordering::Equal, vercmp("3.012", "3.12"));
+    assert_eq!(Ordering::Greater, vercmp("1.el7.1", "1.el7"));
+    assert_eq!(Ordering::Greater, vercmp("3.12.1", "3.12a"));
+}
@@ -0,0 +1,231 @@
+extern crate gluster;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use self::gluster::peer::Peer;
+use self::gluster::volume::Brick;
+
+/// A server and the brick paths it can offer a volume
+#[derive(Debug, Clone, PartialEq)]
+pub struct Host {
+    pub peer: Peer,
+    pub paths: Vec<String>,
+    /// The Juju availability zone of the unit if the cloud has them
+    pub zone: Option<String>,
+}
+
+impl Host {
+    /// Hosts without a zone are their own failure domain
+    fn failure_domain(&self) -> String {
+        match self.zone {
+            Some(ref zone) => zone.clone(),
+            None => format!("host:{}", self.peer.uuid),
+        }
+    }
+}
+
+// How many distinct failure domains every set has to span.  When there are
+// fewer domains than set members some domains have to hold more than one.
+fn required_domains(hosts: &Vec<Host>, set_size: usize) -> usize {
+    let domains: HashSet<String> = hosts.iter()
+        .filter(|host| !host.paths.is_empty())
+        .map(|host| host.failure_domain())
+        .collect();
+    ::std::cmp::min(domains.len(), set_size)
+}
+
+/// Order the bricks so gluster groups them into replica or disperse sets that
+/// each span set_size distinct hosts and as many failure domains as possible.
+/// Gluster builds sets out of consecutive bricks.  Bricks that can't be
+/// placed in a complete set are left out and can be used once more hosts
+/// join.
+pub fn place_bricks(hosts: &Vec<Host>, set_size: usize) -> Vec<Brick> {
+    let mut bricks: Vec<Brick> = Vec::new();
+    if set_size == 0 {
+        return bricks;
+    }
+    let required = required_domains(hosts, set_size);
+    // How many paths each host has handed out so far
+    let mut used: Vec<usize> = vec![0; hosts.len()];
+    loop {
+        let mut set: Vec<usize> = Vec::new();
+        let mut set_domains: HashSet<String> = HashSet::new();
+        while set.len() < set_size {
+            // Prefer a domain the set doesn't have yet, then the domain and
+            // host with the most bricks left so the big ones don't end up
+            // with bricks nobody can pair with.  Ties keep the host order.
+            let candidate = (0..hosts.len())
+                .filter(|&i| !set.contains(&i) && used[i] < hosts[i].paths.len())
+                .max_by_key(|&i| {
+                    let domain = hosts[i].failure_domain();
+                    let domain_left: usize = (0..hosts.len())
+                        .filter(|&j| hosts[j].failure_domain() == domain)
+                        .map(|j| hosts[j].paths.len() - used[j])
+                        .sum();
+                    (!set_domains.contains(&domain),
+                     domain_left,
+                     hosts[i].paths.len() - used[i],
+                     hosts.len() - i)
+                });
+            match candidate {
+                Some(i) => {
+                    set_domains.insert(hosts[i].failure_domain());
+                    set.push(i);
+                }
+                None => break,
+            }
+        }
+        if set.len() < set_size || set_domains.len() < required {
+            break;
+        }
+        for i in set {
+            bricks.push(Brick {
+                peer: hosts[i].peer.clone(),
+                path: PathBuf::from(&hosts[i].paths[used[i]]),
+            });
+            used[i] += 1;
+        }
+    }
+    let left_over: usize = (0..hosts.len()).map(|i| hosts[i].paths.len() - used[i]).sum();
+    if left_over > 0 {
+        log!(format!("{} bricks could not be placed in a complete set of {}",
+                     left_over,
+                     set_size));
+    }
+    bricks
+}
+
+/// Check that the bricks form complete sets of set_size and that no set has
+/// two bricks on the same host or fewer failure domains than the hosts allow.
+pub fn validate_placement(bricks: &Vec<Brick>,
+                          set_size: usize,
+                          hosts: &Vec<Host>)
+                          -> Result<(), String> {
+    if set_size == 0 || bricks.len() % set_size != 0 {
+        return Err(format!("{} bricks can not be split into sets of {}",
+                           bricks.len(),
+                           set_size));
+    }
+    let mut seen: HashSet<String> = HashSet::new();
+    for brick in bricks {
+        if !seen.insert(brick.to_string()) {
+            return Err(format!("Brick {} is listed more than once", brick.to_string()));
+        }
+    }
+    let required = required_domains(hosts, set_size);
+    for set in bricks.chunks(set_size) {
+        let set_hosts: HashSet<String> =
+            set.iter().map(|brick| brick.peer.uuid.to_string()).collect();
+        if set_hosts.len() != set.len() {
+            return Err(format!("Set {:?} has more than one brick on the same host",
+                               set.iter().map(|brick| brick.to_string()).collect::<Vec<String>>()));
+        }
+        let set_domains: HashSet<String> = set.iter()
+            .map(|brick| {
+                match hosts.iter().find(|host| host.peer.uuid == brick.peer.uuid) {
+                    Some(host) => host.failure_domain(),
+                    None => format!("host:{}", brick.peer.uuid),
+                }
+            })
+            .collect();
+        if set_domains.len() < required {
+            return Err(format!("Set {:?} only spans {} of the {} required failure domains",
+                               set.iter().map(|brick| brick.to_string()).collect::<Vec<String>>(),
+                               set_domains.len(),
+                               required));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_host(name: &str, paths: Vec<&str>, zone: Option<&str>) -> Host {
+    use self::gluster::peer::State;
+    use super::uuid::Uuid;
+    Host {
+        peer: Peer {
+            uuid: Uuid::new_v4(),
+            hostname: name.to_string(),
+            status: State::PeerInCluster,
+        },
+        paths: paths.iter().map(|p| p.to_string()).collect(),
+        zone: zone.map(|z| z.to_string()),
+    }
+}
+
+#[test]
+fn test_place_bricks_two_hosts() {
+    let hosts = vec![test_host("host-1", vec!["/mnt/brick1", "/mnt/brick2"], None),
+                     test_host("host-2", vec!["/mnt/brick1", "/mnt/brick2"], None)];
+    let result = place_bricks(&hosts, 2);
+    println!("place_bricks: {:?}", result);
+    assert_eq!(result,
+               vec![Brick {
+                        peer: hosts[0].peer.clone(),
+                        path: PathBuf::from("/mnt/brick1"),
+                    },
+                    Brick {
+                        peer: hosts[1].peer.clone(),
+                        path: PathBuf::from("/mnt/brick1"),
+                    },
+                    Brick {
+                        peer: hosts[0].peer.clone(),
+                        path: PathBuf::from("/mnt/brick2"),
+                    },
+                    Brick {
+                        peer: hosts[1].peer.clone(),
+                        path: PathBuf::from("/mnt/brick2"),
+                    }]);
+    assert!(validate_placement(&result, 2, &hosts).is_ok());
+}
+
+#[test]
+fn test_place_bricks_uneven_hosts() {
+    // 4 hosts with replica 3 and unequal brick counts.  The old cartesian
+    // product put two replicas of the second set on host-1.
+    let hosts = vec![test_host("host-1", vec!["/mnt/a", "/mnt/b", "/mnt/c"], None),
+                     test_host("host-2", vec!["/mnt/a"], None),
+                     test_host("host-3", vec!["/mnt/a", "/mnt/b"], None),
+                     test_host("host-4", vec!["/mnt/a"], None)];
+    let result = place_bricks(&hosts, 3);
+    println!("place_bricks: {:?}", result);
+    // 7 bricks make 2 sets of 3 with one left over
+    assert_eq!(result.len(), 6);
+    assert!(validate_placement(&result, 3, &hosts).is_ok());
+
+    // Fewer hosts than replicas can't form a set
+    assert!(place_bricks(&hosts[0..2].to_vec(), 3).is_empty());
+}
+
+#[test]
+fn test_place_bricks_zones() {
+    let hosts = vec![test_host("host-1", vec!["/mnt/a", "/mnt/b"], Some("az1")),
+                     test_host("host-2", vec!["/mnt/a", "/mnt/b"], Some("az1")),
+                     test_host("host-3", vec!["/mnt/a", "/mnt/b"], Some("az2")),
+                     test_host("host-4", vec!["/mnt/a", "/mnt/b"], Some("az2"))];
+    let result = place_bricks(&hosts, 2);
+    println!("place_bricks: {:?}", result);
+    assert_eq!(result.len(), 8);
+    assert!(validate_placement(&result, 2, &hosts).is_ok());
+    // Both replicas in one zone is rejected
+    let same_zone = vec![Brick {
+                             peer: hosts[0].peer.clone(),
+                             path: PathBuf::from("/mnt/a"),
+                         },
+                         Brick {
+                             peer: hosts[1].peer.clone(),
+                             path: PathBuf::from("/mnt/a"),
+                         }];
+    assert!(validate_placement(&same_zone, 2, &hosts).is_err());
+    // Two bricks of a set on one host is rejected
+    let same_host = vec![Brick {
+                             peer: hosts[0].peer.clone(),
+                             path: PathBuf::from("/mnt/a"),
+                         },
+                         Brick {
+                             peer: hosts[0].peer.clone(),
+                             path: PathBuf::from("/mnt/b"),
+                         }];
+    assert!(validate_placement(&same_host, 2, &hosts).is_err());
+}
@@ -0,0 +1,417 @@
+extern crate chrono;
+extern crate gluster;
+extern crate juju;
+
+use std::collections::HashMap;
+use std::process::Command;
+
+use self::chrono::{Local, TimeZone};
+use self::juju::unitdata;
+use super::cron::Schedule;
+use super::get_config_value;
+
+/// The kind of rebalance to run against a volume
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RebalanceType {
+    /// Only fix the directory layout so new files land on the new bricks
+    FixLayout,
+    /// Fix the layout and migrate the existing data
+    Full,
+}
+
+impl RebalanceType {
+    pub fn from_str(s: &str) -> Result<RebalanceType, String> {
+        match s {
+            "fix-layout" => Ok(RebalanceType::FixLayout),
+            "full" => Ok(RebalanceType::Full),
+            _ => Err(format!("Unknown rebalance type {}", s)),
+        }
+    }
+}
+
+/// What to do after bricks are added to or removed from the volume
+#[derive(Debug, Eq, PartialEq)]
+pub enum RebalancePolicy {
+    None,
+    FixLayout,
+    Full,
+}
+
+impl RebalancePolicy {
+    pub fn from_str(s: &str) -> Result<RebalancePolicy, String> {
+        match s {
+            "none" => Ok(RebalancePolicy::None),
+            "fix-layout" => Ok(RebalancePolicy::FixLayout),
+            "full" => Ok(RebalancePolicy::Full),
+            _ => Err(format!("Unknown rebalance policy {}", s)),
+        }
+    }
+}
+
+/// The rebalance progress of a single node as reported by
+/// gluster volume rebalance <vol> status
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalanceNode {
+    pub node: String,
+    pub rebalanced_files: u64,
+    /// Bytes moved so far
+    pub size: u64,
+    pub scanned: u64,
+    pub failures: u64,
+    pub skipped: u64,
+    pub status: String,
+    /// Run time in seconds
+    pub run_time: u64,
+}
+
+impl RebalanceNode {
+    /// Returns true once this node is no longer moving data
+    pub fn finished(&self) -> bool {
+        !(self.status.contains("in progress") || self.status == "not started")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalanceStatus {
+    pub nodes: Vec<RebalanceNode>,
+    /// Newer gluster versions print an estimate of the time left in seconds
+    pub estimated_time_left: Option<u64>,
+}
+
+impl RebalanceStatus {
+    /// True when no node has a rebalance in progress
+    pub fn finished(&self) -> bool {
+        self.nodes.iter().all(|node| node.finished())
+    }
+
+    /// Total bytes moved across all nodes
+    pub fn bytes_moved(&self) -> u64 {
+        self.nodes.iter().fold(0, |acc, node| acc + node.size)
+    }
+
+    /// Total failures across all nodes
+    pub fn failures(&self) -> u64 {
+        self.nodes.iter().fold(0, |acc, node| acc + node.failures)
+    }
+
+    /// The longest running node is how long the rebalance has been going
+    pub fn elapsed(&self) -> u64 {
+        self.nodes.iter().map(|node| node.run_time).max().unwrap_or(0)
+    }
+
+    /// Rough percent complete.  Uses gluster's time estimate if one was
+    /// given otherwise falls back to the fraction of nodes that are done.
+    pub fn progress(&self) -> u8 {
+        if self.finished() {
+            return 100;
+        }
+        match self.estimated_time_left {
+            Some(left) => {
+                let elapsed = self.elapsed();
+                if elapsed + left == 0 {
+                    return 0;
+                }
+                ((elapsed * 100) / (elapsed + left)) as u8
+            }
+            None => {
+                if self.nodes.is_empty() {
+                    return 0;
+                }
+                let done = self.nodes.iter().filter(|node| node.finished()).count();
+                ((done * 100) / self.nodes.len()) as u8
+            }
+        }
+    }
+}
+
+fn run_rebalance(volume: &str, args: Vec<&str>) -> Result<String, String> {
+    let mut cmd = Command::new("gluster");
+    cmd.arg("--mode=script");
+    cmd.arg("volume");
+    cmd.arg("rebalance");
+    cmd.arg(volume);
+    cmd.args(&args);
+    log!(format!("rebalance cmd: {:?}", cmd), Debug);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Start a rebalance on the volume.  This returns as soon as gluster accepts
+/// the request.  Use rebalance_status to poll for progress.
+pub fn rebalance_start(volume: &str,
+                       rebalance_type: &RebalanceType,
+                       force: bool)
+                       -> Result<(), String> {
+    let mut args: Vec<&str> = Vec::new();
+    match *rebalance_type {
+        RebalanceType::FixLayout => {
+            args.push("fix-layout");
+            args.push("start");
+        }
+        RebalanceType::Full => {
+            args.push("start");
+            if force {
+                args.push("force");
+            }
+        }
+    }
+    run_rebalance(volume, args)?;
+    Ok(())
+}
+
+/// Stop a running rebalance on the volume
+pub fn rebalance_stop(volume: &str) -> Result<(), String> {
+    run_rebalance(volume, vec!["stop"])?;
+    Ok(())
+}
+
+/// Query the rebalance status of every node in the volume
+pub fn rebalance_status(volume: &str) -> Result<RebalanceStatus, String> {
+    let output = run_rebalance(volume, vec!["status"])?;
+    parse_rebalance_status(&output)
+}
+
+/// Apply the rebalance_policy config after the volume has been expanded or
+/// shrunk.  A full rebalance is deferred to the rebalance_window if one is set.
+pub fn schedule_rebalance(volume: &str) -> Result<(), String> {
+    let policy = RebalancePolicy::from_str(&get_config_value("rebalance_policy")?)?;
+    match policy {
+        RebalancePolicy::None => {
+            log!(format!("rebalance_policy is none.  Not rebalancing {}", volume));
+            Ok(())
+        }
+        RebalancePolicy::FixLayout => {
+            log!(format!("Starting fix-layout rebalance of {}", volume), Info);
+            rebalance_start(volume, &RebalanceType::FixLayout, false)
+        }
+        RebalancePolicy::Full => {
+            let window = get_config_value("rebalance_window")?;
+            if window.trim().is_empty() {
+                log!(format!("Starting full rebalance of {}", volume), Info);
+                return rebalance_start(volume, &RebalanceType::Full, false);
+            }
+            // Make sure the window is valid before we wait on it
+            Schedule::from_str(&window)?;
+            // Fix the layout now so new files land on the new bricks while
+            // the data migration waits for the window
+            rebalance_start(volume, &RebalanceType::FixLayout, false)?;
+            set_pending_rebalance(volume, true)?;
+            log!(format!("Deferring full rebalance of {} to maintenance window {}",
+                         volume,
+                         window),
+                 Info);
+            Ok(())
+        }
+    }
+}
+
+// Kept in leader settings so a new leader picks the pending rebalance up
+fn pending_rebalance_key(volume: &str) -> String {
+    format!("pending-rebalance-{}", volume)
+}
+
+// Setting a leader key to the empty string removes it
+fn set_pending_rebalance(volume: &str, pending: bool) -> Result<(), String> {
+    let mut settings: HashMap<String, String> = HashMap::new();
+    settings.insert(pending_rebalance_key(volume),
+                    if pending { "true" } else { "" }.to_string());
+    juju::leader_set(settings).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// When check_pending_rebalance last looked at the window for the volume
+fn last_check_key(volume: &str) -> String {
+    format!("rebalance.last_check.{}", volume)
+}
+
+/// Start a deferred full rebalance of the volume if the rebalance_window
+/// opened since the last check.  This is called from update-status so a
+/// window narrower than its interval still fires.
+pub fn check_pending_rebalance(volume: &str) -> Result<(), String> {
+    let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+    let now = Local::now();
+    let key = last_check_key(volume);
+    let last_check = unit_storage.get::<i64>(&key).map_err(|e| e.to_string())?;
+    unit_storage.set(&key, now.timestamp()).map_err(|e| e.to_string())?;
+
+    let pending = juju::leader_get(Some(pending_rebalance_key(volume)))
+        .map_err(|e| e.to_string())?;
+    if pending.as_ref().map(|p| p.as_ref()) != Some("true") {
+        return Ok(());
+    }
+    let window = get_config_value("rebalance_window")?;
+    if !window.trim().is_empty() {
+        let schedule = Schedule::from_str(&window)?;
+        let open = match last_check {
+            Some(last_check) => schedule.due_between(&Local.timestamp(last_check, 0), &now),
+            // A new leader has nothing to count from
+            None => schedule.matches(&now),
+        };
+        if !open {
+            log!(format!("Outside of rebalance window {}. Rebalance of {} still pending",
+                         window,
+                         volume));
+            return Ok(());
+        }
+    }
+    log!(format!("Starting deferred full rebalance of {}", volume), Info);
+    // If this fails the rebalance stays pending and is retried next time
+    rebalance_start(volume, &RebalanceType::Full, false)?;
+    set_pending_rebalance(volume, false)?;
+    Ok(())
+}
+
+// Gluster prints run time either as h:m:s or as fractional seconds
+// depending on the version
+fn parse_run_time(s: &str) -> Option<u64> {
+    if s.contains(':') {
+        let mut seconds: u64 = 0;
+        for part in s.split(':') {
+            let value = match part.parse::<u64>() {
+                Ok(v) => v,
+                Err(_) => return None,
+            };
+            seconds = seconds * 60 + value;
+        }
+        Some(seconds)
+    } else {
+        match s.parse::<f64>() {
+            Ok(v) => Some(v as u64),
+            Err(_) => None,
+        }
+    }
+}
+
+fn parse_size(s: &str) -> Option<u64> {
+    gluster::translate_to_bytes::<f64>(s).map(|bytes| bytes as u64)
+}
+
+fn parse_rebalance_line(line: &str) -> Option<RebalanceNode> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() < 3 {
+        return None;
+    }
+    let run_time = match parse_run_time(parts[parts.len() - 1]) {
+        Some(t) => t,
+        None => return None,
+    };
+    // Full rebalance output:
+    // Node Rebalanced-files size scanned failures skipped status run-time
+    if parts.len() >= 8 {
+        let rebalanced_files = parts[1].parse::<u64>();
+        let size = parse_size(parts[2]);
+        let scanned = parts[3].parse::<u64>();
+        let failures = parts[4].parse::<u64>();
+        let skipped = parts[5].parse::<u64>();
+        if let (Ok(rebalanced_files), Some(size), Ok(scanned), Ok(failures), Ok(skipped)) =
+            (rebalanced_files, size, scanned, failures, skipped) {
+            return Some(RebalanceNode {
+                node: parts[0].to_string(),
+                rebalanced_files: rebalanced_files,
+                size: size,
+                scanned: scanned,
+                failures: failures,
+                skipped: skipped,
+                status: parts[6..parts.len() - 1].join(" "),
+                run_time: run_time,
+            });
+        }
+    }
+    // Fix layout output only has:
+    // Node status run-time
+    let status = parts[1..parts.len() - 1].join(" ");
+    if status.starts_with("----") {
+        return None;
+    }
+    Some(RebalanceNode {
+        node: parts[0].to_string(),
+        rebalanced_files: 0,
+        size: 0,
+        scanned: 0,
+        failures: 0,
+        skipped: 0,
+        status: status,
+        run_time: run_time,
+    })
+}
+
+pub fn parse_rebalance_status(output: &str) -> Result<RebalanceStatus, String> {
+    let mut nodes: Vec<RebalanceNode> = Vec::new();
+    let mut estimated_time_left: Option<u64> = None;
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("Node") ||
+           trimmed.starts_with("volume rebalance") {
+            continue;
+        }
+        if trimmed.starts_with("Estimated time left") {
+            // Estimated time left for rebalance to complete :        0:02:14
+            if let Some(time_str) = trimmed.splitn(2, ':').nth(1) {
+                estimated_time_left = parse_run_time(time_str.trim());
+            }
+            continue;
+        }
+        if let Some(node) = parse_rebalance_line(trimmed) {
+            nodes.push(node);
+        }
+    }
+    if nodes.is_empty() {
+        return Err(format!("Unable to parse rebalance status from: {}", output));
+    }
+    Ok(RebalanceStatus {
+        nodes: nodes,
+        estimated_time_left: estimated_time_left,
+    })
+}
+
+#[test]
+fn test_parse_rebalance_status() {
+    let output = r#"                                    Node Rebalanced-files          size       scanned      failures       skipped               status  run time in h:m:s
+                               ---------      -----------   -----------   -----------   -----------   -----------         ------------     --------------
+                               localhost               12        1.5MB            30             0             0          in progress        0:01:10
+                            172.31.26.65                0        0Bytes             3             1             0            completed        0:00:04
+Estimated time left for rebalance to complete :        0:02:20
+volume rebalance: test: success
+"#;
+    let result = parse_rebalance_status(output).unwrap();
+    println!("test_parse_rebalance_status: {:?}", result);
+    assert_eq!(result.nodes.len(), 2);
+    assert_eq!(result.nodes[0],
+               RebalanceNode {
+                   node: "localhost".to_string(),
+                   rebalanced_files: 12,
+                   size: 1572864,
+                   scanned: 30,
+                   failures: 0,
+                   skipped: 0,
+                   status: "in progress".to_string(),
+                   run_time: 70,
+               });
+    assert_eq!(result.nodes[1].status, "completed".to_string());
+    assert_eq!(result.estimated_time_left, Some(140));
+    assert!(!result.finished());
+    assert_eq!(result.bytes_moved(), 1572864);
+    assert_eq!(result.failures(), 1);
+    assert_eq!(result.elapsed(), 70);
+    assert_eq!(result.progress(), 33);
+}
+
+#[test]
+fn test_parse_fix_layout_status() {
+    let output = r#"                                    Node                                    status           run time in h:m:s
+                               ---------                               -----------         ------------
+                               localhost                    fix-layout completed        0:00:01
+                            172.31.26.65                    fix-layout completed        0:00:02
+volume rebalance: test: success
+"#;
+    let result = parse_rebalance_status(output).unwrap();
+    println!("test_parse_fix_layout_status: {:?}", result);
+    assert_eq!(result.nodes.len(), 2);
+    assert_eq!(result.nodes[0].status, "fix-layout completed".to_string());
+    assert_eq!(result.nodes[1].run_time, 2);
+    assert!(result.finished());
+    assert_eq!(result.progress(), 100);
+}
@@ -0,0 +1,250 @@
+extern crate gluster;
+
+use std::process::Command;
+
+use self::gluster::volume::{Brick, Volume, VolumeType};
+use super::rebalance::{parse_rebalance_status, RebalanceStatus};
+use super::volume_detail::{BrickDetail, subvolume_size, volume_status_detail};
+
+fn run_remove_brick(volume: &str,
+                    bricks: &Vec<Brick>,
+                    operation: &str)
+                    -> Result<String, String> {
+    let mut cmd = Command::new("gluster");
+    cmd.arg("--mode=script");
+    cmd.arg("volume");
+    cmd.arg("remove-brick");
+    cmd.arg(volume);
+    for brick in bricks {
+        cmd.arg(brick.to_string());
+    }
+    cmd.arg(operation);
+    log!(format!("remove-brick cmd: {:?}", cmd), Debug);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Start migrating the data off of the bricks.  The bricks stay in the volume
+/// until remove_brick_commit is called.
+pub fn remove_brick_start(volume: &str, bricks: &Vec<Brick>) -> Result<(), String> {
+    run_remove_brick(volume, bricks, "start")?;
+    Ok(())
+}
+
+/// Query the data migration progress of a remove-brick.  The output format is
+/// identical to a rebalance status.
+pub fn remove_brick_status(volume: &str,
+                           bricks: &Vec<Brick>)
+                           -> Result<RebalanceStatus, String> {
+    let output = run_remove_brick(volume, bricks, "status")?;
+    parse_rebalance_status(&output)
+}
+
+/// Remove the bricks from the volume once the migration has finished
+pub fn remove_brick_commit(volume: &str, bricks: &Vec<Brick>) -> Result<(), String> {
+    run_remove_brick(volume, bricks, "commit")?;
+    Ok(())
+}
+
+/// Abort a remove-brick.  The bricks stay in the volume.
+pub fn remove_brick_stop(volume: &str, bricks: &Vec<Brick>) -> Result<(), String> {
+    run_remove_brick(volume, bricks, "stop")?;
+    Ok(())
+}
+
+/// Gluster removes whole replica or disperse sets after migrating their data.
+/// Bricks are grouped into sets in the order they appear in the volume so
+/// every set has to be either fully removed or fully kept.  Taking a single
+/// brick out of a set would lower the replica count, or leave an arbiter
+/// with one data copy, without anyone asking for it.
+pub fn check_remove_brick_geometry(volume_bricks: &Vec<Brick>,
+                                   subvolume_size: usize,
+                                   removing: &Vec<Brick>)
+                                   -> Result<(), String> {
+    if subvolume_size == 0 || volume_bricks.len() % subvolume_size != 0 {
+        return Err(format!("Volume has {} bricks which is not a multiple of the set size {}",
+                           volume_bricks.len(),
+                           subvolume_size));
+    }
+    let mut sets_remaining = 0;
+    for set in volume_bricks.chunks(subvolume_size) {
+        let removed = set.iter().filter(|brick| removing.contains(brick)).count();
+        if removed != 0 && removed != set.len() {
+            return Err(format!("Removing {} of the {} bricks in set {} would break the \
+                                replica set.  Replace the bricks instead",
+                               removed,
+                               set.len(),
+                               brick_names(&set.to_vec())));
+        }
+        if removed == 0 {
+            sets_remaining += 1;
+        }
+    }
+    if sets_remaining == 0 {
+        return Err("Removing these bricks would leave the volume with no bricks".to_string());
+    }
+    Ok(())
+}
+
+/// Make sure the data on the bricks being removed fits on the bricks that
+/// remain.  10% of the remaining free space is held back as headroom to match
+/// gluster's default cluster.min-free-disk.
+pub fn check_remove_brick_space(details: &Vec<BrickDetail>,
+                                removing: &Vec<Brick>)
+                                -> Result<(), String> {
+    let removing_names: Vec<String> = removing.iter().map(|brick| brick.to_string()).collect();
+    let mut needed: u64 = 0;
+    let mut available: u64 = 0;
+    for detail in details {
+        if removing_names.contains(&detail.brick) {
+            needed += detail.used();
+        } else {
+            available += detail.free;
+        }
+    }
+    let usable = available - available / 10;
+    if needed > usable {
+        return Err(format!("Not enough free space to drain bricks. {} bytes need to move but \
+                            only {} bytes are free on the remaining bricks",
+                           needed,
+                           usable));
+    }
+    Ok(())
+}
+
+/// Where a drain got to before drain_bricks returned
+#[derive(Debug, Eq, PartialEq)]
+pub enum DrainStep {
+    Done,
+    /// Still migrating in the background.  Calling drain_bricks again checks
+    /// on the running migration.
+    Draining(String),
+}
+
+/// host:/path of every brick, separated by spaces, for status messages
+pub fn brick_names(bricks: &Vec<Brick>) -> String {
+    let names: Vec<String> = bricks.iter().map(|brick| brick.to_string()).collect();
+    names.join(" ")
+}
+
+pub fn is_replicated(volume: &Volume) -> bool {
+    match volume.vol_type {
+        VolumeType::Arbiter |
+        VolumeType::Replicate |
+        VolumeType::StripedAndReplicate |
+        VolumeType::DistributedAndReplicate |
+        VolumeType::DistributedAndStripedAndReplicate => true,
+        _ => false,
+    }
+}
+
+/// Remove the bricks from the volume without losing data.  Whole sets have
+/// their data migrated and are only committed out of the volume after every
+/// file moved.  If the volume geometry or free space doesn't allow it this returns an
+/// error before anything is touched.  The migration runs in the background so
+/// this starts it or checks on it once and returns Draining until it is done.
+pub fn drain_bricks(volume_name: &str,
+                    volume: &Volume,
+                    brick_list: &Vec<Brick>)
+                    -> Result<DrainStep, String> {
+    let set_size = subvolume_size(volume_name)?;
+    check_remove_brick_geometry(&volume.bricks, set_size, brick_list)?;
+
+    // Start the migration unless an earlier hook already did
+    if remove_brick_status(volume_name, brick_list).is_err() {
+        let details = volume_status_detail(volume_name)?;
+        check_remove_brick_space(&details, brick_list)?;
+        status_set!(Maintenance format!("Draining bricks {}", brick_names(brick_list)));
+        remove_brick_start(volume_name, brick_list)?;
+    }
+    let status = remove_brick_status(volume_name, brick_list)?;
+    if !status.finished() {
+        let message = format!("Draining bricks {}: {}%",
+                              brick_names(brick_list),
+                              status.progress());
+        log!(message.clone(), Info);
+        return Ok(DrainStep::Draining(message));
+    }
+    let failed = status.nodes.iter().any(|node| node.status.contains("failed"));
+    if failed || status.failures() > 0 {
+        // Put the bricks back into service so the volume isn't left half drained
+        if let Err(e) = remove_brick_stop(volume_name, brick_list) {
+            log!(format!("Stopping remove-brick failed: {}", e), Error);
+        }
+        return Err(format!("Draining failed with {} failures.  The bricks were not \
+                            removed.  Check the rebalance logs",
+                           status.failures()));
+    }
+    log!(format!("Drained {} bytes from {}",
+                 status.bytes_moved(),
+                 brick_names(brick_list)),
+         Info);
+    remove_brick_commit(volume_name, brick_list)?;
+    Ok(DrainStep::Done)
+}
+
+#[cfg(test)]
+fn test_brick(host: &str, path: &str) -> Brick {
+    use self::gluster::peer::{Peer, State};
+    use super::uuid::Uuid;
+    Brick {
+        peer: Peer {
+            uuid: Uuid::new_v4(),
+            hostname: host.to_string(),
+            status: State::PeerInCluster,
+        },
+        path: ::std::path::PathBuf::from(path),
+    }
+}
+
+#[test]
+fn test_check_remove_brick_geometry() {
+    let bricks = vec![test_brick("10.0.0.1", "/mnt/xvdb"),
+                      test_brick("10.0.0.2", "/mnt/xvdb"),
+                      test_brick("10.0.0.3", "/mnt/xvdb"),
+                      test_brick("10.0.0.4", "/mnt/xvdb")];
+    // Removing a whole replica 2 set is fine
+    assert!(check_remove_brick_geometry(&bricks,
+                                        2,
+                                        &vec![bricks[2].clone(), bricks[3].clone()])
+        .is_ok());
+    // A host holding one brick of every set would lower the replica count
+    assert_eq!(Err("Removing 1 of the 2 bricks in set 10.0.0.1:/mnt/xvdb 10.0.0.2:/mnt/xvdb \
+                    would break the replica set.  Replace the bricks instead"
+                       .to_string()),
+               check_remove_brick_geometry(&bricks,
+                                           2,
+                                           &vec![bricks[1].clone(), bricks[3].clone()]));
+    assert!(check_remove_brick_geometry(&bricks, 2, &vec![bricks[1].clone()]).is_err());
+    // Removing everything isn't allowed
+    assert!(check_remove_brick_geometry(&bricks, 4, &bricks).is_err());
+    // Distribute volumes can drop any brick
+    assert!(check_remove_brick_geometry(&bricks, 1, &vec![bricks[1].clone()]).is_ok());
+}
+
+#[test]
+fn test_check_remove_brick_space() {
+    let gb = 1024 * 1024 * 1024;
+    let detail = |brick: &str, free: u64, total: u64| {
+        BrickDetail {
+            brick: brick.to_string(),
+            online: true,
+            device: "/dev/xvdb".to_string(),
+            free: free,
+            total: total,
+            inode_count: 0,
+            free_inodes: 0,
+        }
+    };
+    let removing = vec![test_brick("10.0.0.2", "/mnt/xvdb")];
+    let details = vec![detail("10.0.0.1:/mnt/xvdb", 5 * gb, 10 * gb),
+                       detail("10.0.0.2:/mnt/xvdb", 6 * gb, 10 * gb)];
+    assert!(check_remove_brick_space(&details, &removing).is_ok());
+
+    let details = vec![detail("10.0.0.1:/mnt/xvdb", 4 * gb, 10 * gb),
+                       detail("10.0.0.2:/mnt/xvdb", 2 * gb, 10 * gb)];
+    assert!(check_remove_brick_space(&details, &removing).is_err());
+}
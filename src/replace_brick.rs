@@ -0,0 +1,26 @@
+extern crate gluster;
+
+use std::process::Command;
+
+use self::gluster::volume::Brick;
+
+/// Swap a brick in the volume for a new empty one.  Gluster only supports
+/// commit force for replace-brick so the data has to be healed back onto the
+/// new brick afterwards from the rest of its replica set.
+pub fn replace_brick_commit_force(volume: &str, old: &Brick, new: &Brick) -> Result<(), String> {
+    let mut cmd = Command::new("gluster");
+    cmd.arg("--mode=script");
+    cmd.arg("volume");
+    cmd.arg("replace-brick");
+    cmd.arg(volume);
+    cmd.arg(old.to_string());
+    cmd.arg(new.to_string());
+    cmd.arg("commit");
+    cmd.arg("force");
+    log!(format!("replace-brick cmd: {:?}", cmd), Debug);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(())
+}
@@ -1,30 +1,196 @@
+extern crate serde_yaml;
+
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::Path;
+use std::str::FromStr;
 
-use super::apt::{apt_install, service_start};
+use super::apt::service_start;
+use super::{get_config_flag, get_config_value};
+use super::health::Condition;
 use super::juju;
+use super::package::package_manager;
+use super::volumes::VolumeConfig;
+
+/// How samba authenticates clients
+#[derive(Debug, Eq, PartialEq)]
+pub enum SambaSecurity {
+    /// Local samba users.  Shares without valid users allow guests.
+    User,
+    /// Active Directory domain members through winbind
+    Ads,
+}
+
+impl FromStr for SambaSecurity {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "user" => Ok(SambaSecurity::User),
+            "ads" => Ok(SambaSecurity::Ads),
+            _ => Err(format!("Unknown cifs_security {}.  Use user or ads", s)),
+        }
+    }
+}
+
+/// The share options from the cifs_* config options
+#[derive(Debug)]
+pub struct SambaConfig {
+    pub security: SambaSecurity,
+    pub workgroup: String,
+    /// The kerberos realm.  Only used with ads security
+    pub realm: Option<String>,
+    /// Users and @groups allowed to connect.  Empty allows everyone.
+    pub valid_users: Vec<String>,
+    pub read_only: bool,
+    /// Serve the volumes with libgfapi instead of going through the fuse mount
+    pub vfs_glusterfs: bool,
+    /// Set when ctdb clusters samba across the units
+    pub clustering: bool,
+    /// Added to the [global] section as is
+    pub extra_parameters: BTreeMap<String, String>,
+}
+
+/// cifs_valid_users is a comma separated list because AD group names have
+/// spaces in them.  smb.conf separates the users with spaces so names with
+/// spaces are quoted after the @, + or & that marks a group.
+pub fn parse_valid_users(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|user| user.trim())
+        .filter(|user| !user.is_empty())
+        .map(|user| {
+            if !user.contains(char::is_whitespace) || user.contains('"') {
+                return user.to_string();
+            }
+            let name = user.trim_left_matches(|c| c == '@' || c == '+' || c == '&');
+            format!("{}\"{}\"", &user[..user.len() - name.len()], name)
+        })
+        .collect()
+}
+
+impl SambaConfig {
+    pub fn from_config() -> Result<SambaConfig, String> {
+        let workgroup = get_config_value("cifs_workgroup")?;
+        let realm = get_config_value("cifs_realm")?;
+        let extra_parameters = get_config_value("cifs_extra_parameters")?;
+        let vips = juju::config_get("virtual_ip_addresses").map_err(|e| e.to_string())?;
+        Ok(SambaConfig {
+            security: SambaSecurity::from_str(&get_config_value("cifs_security")?)?,
+            workgroup: if workgroup.is_empty() {
+                "WORKGROUP".to_string()
+            } else {
+                workgroup
+            },
+            realm: if realm.is_empty() { None } else { Some(realm) },
+            valid_users: parse_valid_users(&get_config_value("cifs_valid_users")?),
+            read_only: get_config_flag("cifs_read_only")?,
+            vfs_glusterfs: get_config_flag("cifs_vfs_glusterfs")?,
+            clustering: vips.is_some(),
+            extra_parameters: if extra_parameters.trim().is_empty() {
+                BTreeMap::new()
+            } else {
+                serde_yaml::from_str(&extra_parameters)
+                    .map_err(|e| format!("Invalid cifs_extra_parameters: {}", e))?
+            },
+        })
+    }
 
-/// Write the samba configuration file out to disk
+    pub fn validate(&self) -> Result<(), String> {
+        if self.security == SambaSecurity::Ads && self.realm.is_none() {
+            return Err("cifs_security ads needs cifs_realm to be set".to_string());
+        }
+        Ok(())
+    }
+
+    fn guest_ok(&self) -> bool {
+        self.security == SambaSecurity::User && self.valid_users.is_empty()
+    }
+}
+
+/// Write the samba configuration file out to disk.  Every volume is shared
+/// under its own name.
 pub fn render_samba_configuration<T: Write>(f: &mut T,
-                                            volume_name: &str)
+                                            volumes: &Vec<VolumeConfig>,
+                                            config: &SambaConfig)
                                             -> Result<usize, ::std::io::Error> {
     let mut bytes_written = 0;
-    bytes_written += f.write(&format!("[{}]\n", volume_name).as_bytes())?;
-    bytes_written += f.write(b"path = /mnt/glusterfs\n")?;
-    bytes_written += f.write(b"read only = no\n")?;
-    bytes_written += f.write(b"guest ok = yes\n")?;
-    bytes_written += f.write(b"kernel share modes = no\n")?;
-    bytes_written += f.write(b"kernel oplocks = no\n")?;
-    bytes_written += f.write(b"map archive = no\n")?;
-    bytes_written += f.write(b"map hidden = no\n")?;
-    bytes_written += f.write(b"map read only = no\n")?;
-    bytes_written += f.write(b"map system = no\n")?;
-    bytes_written += f.write(b"store dos attributes = yes\n")?;
+    bytes_written += f.write(b"[global]\n")?;
+    bytes_written += f.write(&format!("workgroup = {}\n", config.workgroup).as_bytes())?;
+    match config.security {
+        SambaSecurity::User => {
+            bytes_written += f.write(b"security = user\n")?;
+            if config.guest_ok() {
+                bytes_written += f.write(b"map to guest = Bad User\n")?;
+            }
+        }
+        SambaSecurity::Ads => {
+            bytes_written += f.write(b"security = ads\n")?;
+            if let Some(ref realm) = config.realm {
+                bytes_written += f.write(&format!("realm = {}\n", realm).as_bytes())?;
+            }
+            // Every unit has to map domain users to the same ids so the
+            // ownership on the volume agrees across the cluster
+            bytes_written += f.write(b"idmap config * : backend = tdb\n")?;
+            bytes_written += f.write(b"idmap config * : range = 3000-7999\n")?;
+            bytes_written += f.write(&format!("idmap config {} : backend = rid\n",
+                                              config.workgroup)
+                                          .as_bytes())?;
+            bytes_written += f.write(&format!("idmap config {} : range = 10000-999999\n",
+                                              config.workgroup)
+                                          .as_bytes())?;
+            bytes_written += f.write(b"winbind use default domain = yes\n")?;
+        }
+    }
+    if config.clustering {
+        // ctdb shares the samba databases between the units
+        bytes_written += f.write(b"clustering = yes\n")?;
+    }
+    for (key, value) in &config.extra_parameters {
+        bytes_written += f.write(&format!("{} = {}\n", key, value).as_bytes())?;
+    }
+    for volume in volumes {
+        bytes_written += f.write(&format!("\n[{}]\n", volume.name).as_bytes())?;
+        if config.vfs_glusterfs {
+            // The path is inside the volume when samba talks libgfapi
+            bytes_written += f.write(b"path = /\n")?;
+            bytes_written += f.write(b"vfs objects = glusterfs\n")?;
+            bytes_written += f.write(&format!("glusterfs:volume = {}\n", volume.name).as_bytes())?;
+            bytes_written += f.write(b"glusterfs:volfile_server = localhost\n")?;
+            bytes_written += f.write(&format!("glusterfs:logfile = \
+                                               /var/log/samba/glusterfs-{}.%M.log\n",
+                                              volume.name)
+                                          .as_bytes())?;
+        } else {
+            bytes_written += f.write(&format!("path = {}\n", volume.mount_point).as_bytes())?;
+        }
+        if config.read_only {
+            bytes_written += f.write(b"read only = yes\n")?;
+        } else {
+            bytes_written += f.write(b"read only = no\n")?;
+        }
+        if config.guest_ok() {
+            bytes_written += f.write(b"guest ok = yes\n")?;
+        } else {
+            bytes_written += f.write(b"guest ok = no\n")?;
+        }
+        if !config.valid_users.is_empty() {
+            bytes_written += f.write(&format!("valid users = {}\n", config.valid_users.join(" "))
+                    .as_bytes())?;
+        }
+        bytes_written += f.write(b"kernel share modes = no\n")?;
+        bytes_written += f.write(b"kernel oplocks = no\n")?;
+        bytes_written += f.write(b"map archive = no\n")?;
+        bytes_written += f.write(b"map hidden = no\n")?;
+        bytes_written += f.write(b"map read only = no\n")?;
+        bytes_written += f.write(b"map system = no\n")?;
+        bytes_written += f.write(b"store dos attributes = yes\n")?;
+    }
     Ok(bytes_written)
 }
 
-fn samba_config_changed(volume_name: &str) -> Result<bool, ::std::io::Error> {
+fn samba_config_changed(volumes: &Vec<VolumeConfig>,
+                        config: &SambaConfig)
+                        -> Result<bool, ::std::io::Error> {
     if Path::new("/etc/samba/smb.conf").exists() {
         // Lets check if the smb.conf matches what we're going to write.  If so then
         // it was already setup and there's nothing to do
@@ -32,7 +198,7 @@ fn samba_config_changed(volume_name: &str) -> Result<bool, ::std::io::Error> {
         let mut existing_config: Vec<u8> = Vec::new();
         f.read_to_end(&mut existing_config)?;
         let mut new_config: Vec<u8> = Vec::new();
-        let _ = render_samba_configuration(&mut new_config, volume_name)?;
+        let _ = render_samba_configuration(&mut new_config, volumes, config)?;
         if new_config == existing_config {
             // configs are identical
             return Ok(false);
@@ -44,28 +210,132 @@ fn samba_config_changed(volume_name: &str) -> Result<bool, ::std::io::Error> {
     return Ok(true);
 }
 
-pub fn setup_samba(volume_name: &str) -> Result<(), String> {
-    let cifs_config = juju::config_get("cifs").map_err(|e| e.to_string())?;
-    if cifs_config.is_none() {
+/// Blocked while cifs is enabled with options samba can't use.  setup_samba
+/// skips the setup then.
+pub fn samba_condition() -> Result<Option<Condition>, String> {
+    if !get_config_flag("cifs")? {
+        return Ok(None);
+    }
+    let invalid = SambaConfig::from_config().and_then(|config| config.validate()).err();
+    Ok(invalid.map(|e| Condition::blocked(&format!("Invalid samba config: {}", e))))
+}
+
+pub fn setup_samba(volumes: &Vec<VolumeConfig>) -> Result<(), String> {
+    if !get_config_flag("cifs")? {
         // Samba isn't enabled
         log!("Samba option is not enabled");
         return Ok(());
     }
-    if !samba_config_changed(volume_name).map_err(|e| e.to_string())? {
+    let config = SambaConfig::from_config()?;
+    if let Err(e) = config.validate() {
+        // update_status reports it through samba_condition
+        log!(format!("Invalid samba config: {}", e), Error);
+        return Ok(());
+    }
+    if !samba_config_changed(volumes, &config).map_err(|e| e.to_string())? {
         log!("Samba is already setup.  Not reinstalling");
         return Ok(());
     }
 
     status_set!(Maintenance "Installing Samba");
-    apt_install(vec!["samba"])?;
+    let mut packages = vec!["samba"];
+    if config.vfs_glusterfs {
+        packages.push("samba-vfs-modules");
+    }
+    if config.security == SambaSecurity::Ads {
+        packages.extend(vec!["winbind", "libnss-winbind", "krb5-user"]);
+    }
+    package_manager().install(packages)?;
     status_set!(Maintenance "Configuring Samba");
     log!("Setting up Samba");
     let mut samba_conf = File::create("/etc/samba/smb.conf").map_err(|e| e.to_string())?;
-    let bytes_written =
-        render_samba_configuration(&mut samba_conf, volume_name).map_err(|e| e.to_string())?;
+    let bytes_written = render_samba_configuration(&mut samba_conf, volumes, &config)
+        .map_err(|e| e.to_string())?;
     log!(format!("Wrote {} bytes to /etc/samba/smb.conf", bytes_written));
+    if config.clustering {
+        // CTDB_MANAGES_SAMBA has ctdb start smbd and winbind on healthy nodes
+        log!("Leaving the samba services to ctdb");
+        return Ok(());
+    }
     log!("Starting Samba service");
     status_set!(Maintenance "Starting Samba");
     service_start("smbd")?;
+    if config.security == SambaSecurity::Ads {
+        // Joining needs domain admin credentials so that's left to the
+        // operator
+        log!("Join each unit to the domain with: net ads join -U <admin>",
+             Info);
+        service_start("winbind")?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+fn test_volumes() -> Vec<VolumeConfig> {
+    let volume = |name: &str, mount_point: &str| {
+        VolumeConfig {
+            name: name.to_string(),
+            volume_type: "Replicate".to_string(),
+            replicas: 3,
+            arbiter_count: 1,
+            stripe_count: 3,
+            disperse_data: 2,
+            disperse_redundancy: 1,
+            brick_dir: Some(name.to_string()),
+            mount_point: mount_point.to_string(),
+        }
+    };
+    vec![volume("home", "/mnt/glusterfs"), volume("scratch", "/mnt/glusterfs-scratch")]
+}
+
+#[test]
+fn test_render_samba_user_configuration() {
+    let config = SambaConfig {
+        security: SambaSecurity::User,
+        workgroup: "WORKGROUP".to_string(),
+        realm: None,
+        valid_users: Vec::new(),
+        read_only: false,
+        vfs_glusterfs: false,
+        clustering: false,
+        extra_parameters: BTreeMap::new(),
+    };
+    let mut buff: Vec<u8> = Vec::new();
+    render_samba_configuration(&mut buff, &test_volumes(), &config).unwrap();
+    assert_eq!(include_str!("testdata/smb_user.conf"),
+               String::from_utf8_lossy(&buff));
+}
+
+#[test]
+fn test_render_samba_ads_configuration() {
+    let mut extra_parameters = BTreeMap::new();
+    extra_parameters.insert("server min protocol".to_string(), "SMB2".to_string());
+    let config = SambaConfig {
+        security: SambaSecurity::Ads,
+        workgroup: "EXAMPLE".to_string(),
+        realm: Some("EXAMPLE.COM".to_string()),
+        valid_users: parse_valid_users("@EXAMPLE\\Domain Users, alice"),
+        read_only: true,
+        vfs_glusterfs: true,
+        clustering: true,
+        extra_parameters: extra_parameters,
+    };
+    assert!(config.validate().is_ok());
+    let mut buff: Vec<u8> = Vec::new();
+    render_samba_configuration(&mut buff, &test_volumes(), &config).unwrap();
+    assert_eq!(include_str!("testdata/smb_ads.conf"),
+               String::from_utf8_lossy(&buff));
+
+    let config = SambaConfig { realm: None, ..config };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_parse_valid_users() {
+    let users = vec!["@\"EXAMPLE\\Domain Users\"".to_string(), "alice".to_string()];
+    assert_eq!(users, parse_valid_users("@EXAMPLE\\Domain Users, alice"));
+    // Already quoted names are left alone
+    assert_eq!(users, parse_valid_users("@\"EXAMPLE\\Domain Users\",alice,"));
+    assert_eq!(vec!["\"Jane Doe\"", "+staff"], parse_valid_users(" Jane Doe , +staff"));
+    assert!(parse_valid_users("").is_empty());
+}
@@ -0,0 +1,269 @@
+extern crate chrono;
+extern crate gluster;
+extern crate juju;
+
+use std::process::Command;
+
+use self::chrono::{Local, NaiveDateTime, TimeZone};
+use self::gluster::volume::{volume_info, volume_start, volume_status, volume_stop};
+use self::juju::unitdata;
+use super::cron::Schedule;
+use super::get_config_value;
+
+/// Scheduled snapshots are named with this prefix.  Only they are pruned.
+pub const SCHEDULED_PREFIX: &'static str = "scheduled";
+
+/// A gluster snapshot as snapshot info describes it
+#[derive(Debug, PartialEq)]
+pub struct Snapshot {
+    /// Gluster appends the creation time in GMT to the requested name
+    pub name: String,
+    /// The volume the snapshot was taken of
+    pub volume: String,
+    /// When the snapshot was taken in GMT
+    pub created: Option<NaiveDateTime>,
+    /// The charm records the volume state at snapshot time here
+    pub description: String,
+    /// Started once the snapshot is activated and can be mounted
+    pub status: String,
+}
+
+fn run_snapshot(args: Vec<&str>) -> Result<String, String> {
+    let mut cmd = Command::new("gluster");
+    cmd.arg("--mode=script");
+    cmd.arg("snapshot");
+    cmd.args(&args);
+    log!(format!("snapshot cmd: {:?}", cmd), Debug);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        if stderr.contains("thin") {
+            return Err(format!("{}.  Snapshots need bricks on thin provisioned LVM",
+                               stderr.trim()));
+        }
+        return Err(stderr);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Describe the volume so a snapshot records what it was taken of
+pub fn volume_state(volume: &str) -> Result<String, String> {
+    let info = volume_info(volume).map_err(|e| e.to_string())?;
+    let online = match volume_status(volume) {
+        Ok(bricks) => bricks.iter().filter(|brick| brick.online).count().to_string(),
+        Err(_) => "unknown".to_string(),
+    };
+    Ok(format!("{} with {} of {} bricks online",
+               info.status,
+               online,
+               info.bricks.len()))
+}
+
+/// Snapshot the volume.  Returns the snapshot name gluster picked.
+pub fn create_snapshot(volume: &str, name: &str) -> Result<String, String> {
+    let description = volume_state(volume)?;
+    let output = run_snapshot(vec!["create", name, volume, "description", &description])?;
+    // snapshot create: success: Snap scheduled_GMT-2017.06.01-02.00.00 created successfully
+    match output.split_whitespace().skip_while(|word| *word != "Snap").nth(1) {
+        Some(created) => Ok(created.to_string()),
+        None => Err(format!("Unable to find the snapshot name in {}", output.trim())),
+    }
+}
+
+pub fn delete_snapshot(name: &str) -> Result<(), String> {
+    run_snapshot(vec!["delete", name])?;
+    Ok(())
+}
+
+/// Start the snapshot's volume so it can be mounted read only
+pub fn activate_snapshot(name: &str) -> Result<(), String> {
+    run_snapshot(vec!["activate", name])?;
+    Ok(())
+}
+
+/// Roll the volume back to the snapshot.  Gluster needs the volume stopped
+/// for this so clients lose access until it's started again.  The snapshot is
+/// gone afterwards.
+pub fn restore_snapshot(volume: &str, name: &str) -> Result<(), String> {
+    volume_stop(volume, false).map_err(|e| e.to_string())?;
+    let result = run_snapshot(vec!["restore", name]);
+    // Bring the volume back even if the restore failed
+    volume_start(volume, false).map_err(|e| e.to_string())?;
+    result?;
+    Ok(())
+}
+
+/// Every snapshot of the volume
+pub fn list_snapshots(volume: &str) -> Result<Vec<Snapshot>, String> {
+    let output = run_snapshot(vec!["info", "volume", volume])?;
+    Ok(parse_snapshot_info(&output))
+}
+
+/// The scheduled snapshots to delete so only retention of them are kept.
+/// The oldest go first.
+pub fn snapshots_to_prune(snapshots: &Vec<Snapshot>, retention: usize) -> Vec<String> {
+    let mut scheduled: Vec<&Snapshot> = snapshots.iter()
+        .filter(|snapshot| snapshot.name.starts_with(SCHEDULED_PREFIX))
+        .collect();
+    if scheduled.len() <= retention {
+        return Vec::new();
+    }
+    scheduled.sort_by_key(|snapshot| snapshot.created);
+    let prune = scheduled.len() - retention;
+    scheduled.iter().take(prune).map(|snapshot| snapshot.name.clone()).collect()
+}
+
+const LAST_CHECK_KEY: &'static str = "snapshot.last_check";
+
+/// Take and prune the scheduled snapshots of every volume if the
+/// snapshot_schedule came due since the last check.  Only the leader should
+/// call this.
+pub fn check_scheduled_snapshots(volumes: &Vec<String>) -> Result<(), String> {
+    let schedule = get_config_value("snapshot_schedule")?;
+    if schedule.trim().is_empty() {
+        return Ok(());
+    }
+    let schedule = Schedule::from_str(&schedule)?;
+    let retention = get_config_value("snapshot_retention")?.parse::<usize>().unwrap_or(7);
+
+    let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+    let now = Local::now();
+    let last_check = unit_storage.get::<i64>(LAST_CHECK_KEY).map_err(|e| e.to_string())?;
+    unit_storage.set(LAST_CHECK_KEY, now.timestamp()).map_err(|e| e.to_string())?;
+    let due = match last_check {
+        Some(last_check) => schedule.due_between(&Local.timestamp(last_check, 0), &now),
+        // Start counting from the first check
+        None => false,
+    };
+    if !due {
+        return Ok(());
+    }
+    for volume in volumes {
+        match create_snapshot(volume, SCHEDULED_PREFIX) {
+            Ok(name) => log!(format!("Took scheduled snapshot {} of {}", name, volume), Info),
+            Err(e) => {
+                log!(format!("Scheduled snapshot of {} failed: {}", volume, e), Error);
+                continue;
+            }
+        }
+        let snapshots = match list_snapshots(volume) {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                log!(format!("Listing the snapshots of {} failed: {}", volume, e), Error);
+                continue;
+            }
+        };
+        for name in snapshots_to_prune(&snapshots, retention) {
+            log!(format!("Deleting scheduled snapshot {} of {}", name, volume), Info);
+            if let Err(e) = delete_snapshot(&name) {
+                log!(format!("Deleting snapshot {} failed: {}", name, e), Error);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Snapshot                  : scheduled_GMT-2017.06.01-02.00.00
+// Snap UUID                 : 6b1d5c8e-3f2a-4d3b-9d7e-0a8c1f3e2b11
+// Description               : Started with 6 of 6 bricks online
+// Created                   : 2017-06-01 02:00:00
+// Snap Volumes:
+//
+//     Snap Volume Name          : 1f4f5f6c3b9a4d7e8c2b1a0d9e8f7c6b
+//     Origin Volume name        : test
+//     Snaps taken for test      : 2
+//     Snaps available for test  : 254
+//     Status                    : Stopped
+pub fn parse_snapshot_info(output: &str) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+    for line in output.lines() {
+        let mut parts = line.splitn(2, ':');
+        let key = parts.next().unwrap_or_default().trim();
+        let value = match parts.next() {
+            Some(value) => value.trim(),
+            None => continue,
+        };
+        if key == "Snapshot" {
+            snapshots.push(Snapshot {
+                name: value.to_string(),
+                volume: String::new(),
+                created: None,
+                description: String::new(),
+                status: String::new(),
+            });
+            continue;
+        }
+        let snapshot = match snapshots.last_mut() {
+            Some(snapshot) => snapshot,
+            None => continue,
+        };
+        match key {
+            "Description" | "Snap Description" => snapshot.description = value.to_string(),
+            "Created" => {
+                snapshot.created = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").ok()
+            }
+            "Origin Volume name" => snapshot.volume = value.to_string(),
+            "Status" => snapshot.status = value.to_string(),
+            _ => {}
+        }
+    }
+    snapshots
+}
+
+#[test]
+fn test_parse_snapshot_info() {
+    let output = r#"Snapshot                  : scheduled_GMT-2017.06.02-02.00.00
+Snap UUID                 : 6b1d5c8e-3f2a-4d3b-9d7e-0a8c1f3e2b11
+Description               : Started with 6 of 6 bricks online
+Created                   : 2017-06-02 02:00:00
+Snap Volumes:
+
+	Snap Volume Name          : 1f4f5f6c3b9a4d7e8c2b1a0d9e8f7c6b
+	Origin Volume name        : test
+	Snaps taken for test      : 3
+	Snaps available for test  : 253
+	Status                    : Stopped
+
+Snapshot                  : before-upgrade_GMT-2017.05.30-12.15.00
+Snap UUID                 : 0d4c1b6e-7a2f-4c3e-8b1d-2e9f0a7c6b54
+Created                   : 2017-05-30 12:15:00
+Snap Volumes:
+
+	Snap Volume Name          : 7c6b5a4d3e2f1a0b9c8d7e6f5a4b3c2d
+	Origin Volume name        : test
+	Snaps taken for test      : 3
+	Snaps available for test  : 253
+	Status                    : Started
+
+Snapshot                  : scheduled_GMT-2017.06.01-02.00.00
+Snap UUID                 : 9a8b7c6d-5e4f-4a3b-2c1d-0e9f8a7b6c5d
+Description               : Started with 5 of 6 bricks online
+Created                   : 2017-06-01 02:00:00
+Snap Volumes:
+
+	Snap Volume Name          : 2a3b4c5d6e7f8a9b0c1d2e3f4a5b6c7d
+	Origin Volume name        : test
+	Snaps taken for test      : 3
+	Snaps available for test  : 253
+	Status                    : Stopped
+"#;
+    let snapshots = parse_snapshot_info(output);
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!(snapshots[0],
+               Snapshot {
+                   name: "scheduled_GMT-2017.06.02-02.00.00".to_string(),
+                   volume: "test".to_string(),
+                   created: NaiveDateTime::parse_from_str("2017-06-02 02:00:00",
+                                                          "%Y-%m-%d %H:%M:%S")
+                       .ok(),
+                   description: "Started with 6 of 6 bricks online".to_string(),
+                   status: "Stopped".to_string(),
+               });
+    assert_eq!(snapshots[1].status, "Started");
+    assert_eq!(snapshots[1].description, "");
+
+    // Only the scheduled snapshots are pruned, oldest first
+    assert_eq!(snapshots_to_prune(&snapshots, 1),
+               vec!["scheduled_GMT-2017.06.01-02.00.00".to_string()]);
+    assert!(snapshots_to_prune(&snapshots, 2).is_empty());
+}
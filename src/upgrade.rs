@@ -1,233 +1,1209 @@
 extern crate chrono;
 extern crate gluster;
-extern crate init_daemon;
 extern crate juju;
-extern crate rand;
-extern crate rustc_serialize;
-extern crate uuid;
 
-use std::fs::{create_dir, File, OpenOptions};
-use std::io::{BufRead, BufReader, Read, Write};
-use std::path::Path;
+#[cfg(test)]
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::str::FromStr;
+use std::process::Command;
 use std::thread;
+use std::time::Duration;
 
-use self::chrono::*;
-use self::gluster::peer::Peer;
-use self::gluster::volume::volume_info;
-use self::rand::distributions::{IndependentSample, Range};
-use self::rustc_serialize::json;
-use self::uuid::Uuid;
+use self::chrono::Local;
+use self::gluster::{BrickStatus, get_local_bricks, GlusterError};
+use self::gluster::peer::{peer_list, Peer, State};
+use self::gluster::volume::{volume_status, Brick};
+use self::juju::unitdata;
 
 use super::apt;
-use super::debian::version::Version;
+use super::{get_config_number, get_config_value};
 use super::get_glusterfs_version;
+use super::heal::{heal_count, pending_heal_entries, HealCount};
+use super::health::{same_brick, Condition};
+use super::package::{package_manager, PackageVersion};
+use super::volumes::get_volumes;
 
-fn get_local_uuid() -> Result<Uuid, String> {
-    // File looks like this:
-    // UUID=30602134-698f-4e53-8503-163e175aea85
-    // operating-version=30800
-    let f = File::open("/var/lib/glusterd/glusterd.info").map_err(|e| e.to_string())?;
-    let mut reader = BufReader::new(f);
-
-    let mut line = String::new();
-    reader.read_line(&mut line).map_err(|e| e.to_string())?;
-    if line.contains("UUID") {
-        let parts: Vec<&str> = line.split("=").collect();
-        let uuid = Uuid::parse_str(parts[1].trim()).map_err(|e| e.to_string())?;
-        return Ok(uuid);
-    }
-    Err("Unable to find UUID".to_string())
-}
-
-// Edge cases:
-// 1. Previous node dies on upgrade, can we retry?
-pub fn roll_cluster(new_version: &Version) -> Result<(), String> {
-    // This is tricky to get right so here's what we're going to do.
-    // :param new_version: str of the version to upgrade to
-    // There's 2 possible cases: Either I'm first in line or not.
-    // If I'm not first in line I'll wait a random time between 5-30 seconds
-    // and test to see if the previous peer is upgraded yet.
-    //
-    log!(format!("roll_cluster called with {}", new_version));
-    let volume_name = juju::config_get(&"volume_name".to_string()).map_err(|e| e.to_string())?;
-    let my_uuid = get_local_uuid()?;
-
-    // volume_name always has a default
-    let volume_bricks = volume_info(&volume_name.unwrap()).map_err(|e| e.to_string())?.bricks;
-    let mut peer_list: Vec<Peer> = volume_bricks.iter().map(|x| x.peer.clone()).collect();
-    log!(format!("peer_list: {:?}", peer_list));
-
-    // Sort by UUID
-    peer_list.sort();
-    // We find our position by UUID
-    let position = match peer_list.iter().position(|x| x.uuid == my_uuid) {
-        Some(p) => p,
-        None => {
-            log!(format!("Unable to determine upgrade position from: {:?}", peer_list),
-                 Error);
-            return Err("Unable to determine upgrade position".to_string());
+/// How often and how long to look for the local bricks after the restart
+const BRICK_START_INTERVAL: u64 = 5;
+const BRICK_START_RETRIES: u32 = 24;
+
+/// Where the upgrade gets the time from so the tests can move it along
+pub trait Clock {
+    /// Seconds since the epoch
+    fn now(&self) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        Local::now().timestamp()
+    }
+}
+
+/// What a unit reported about the upgrade on the server relation
+#[derive(Debug, PartialEq)]
+pub struct UnitProgress {
+    /// The version the timestamps below are for
+    pub version: String,
+    /// When the unit last asked for its turn.  Only set by units coming back
+    /// after they timed out.
+    pub requested: Option<i64>,
+    /// When the unit finished upgrading
+    pub done: Option<i64>,
+}
+
+/// The rolling upgrade as the leader keeps it in leader settings.  Units
+/// upgrade one at a time in a fixed order.  The unit holding the lock that
+/// doesn't finish within the timeout is skipped and can queue up again once
+/// it comes back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpgradePlan {
+    pub version: String,
+    /// Every unit in upgrade order
+    pub order: Vec<String>,
+    /// The unit allowed to upgrade right now
+    pub current: Option<String>,
+    /// When current was handed the lock
+    pub granted: Option<i64>,
+    pub done: Vec<String>,
+    /// Units that held the lock too long and when they were skipped
+    pub timed_out: BTreeMap<String, i64>,
+    /// No new unit gets the lock while paused.  The one holding it finishes.
+    pub paused: bool,
+}
+
+/// The number after the slash in gluster/10
+fn unit_number(unit: &str) -> u64 {
+    unit.rsplit('/').next().and_then(|n| n.parse::<u64>().ok()).unwrap_or(0)
+}
+
+/// Sort units by their unit number so gluster/10 comes after gluster/2
+pub fn unit_order(units: &Vec<String>) -> Vec<String> {
+    let mut order = units.clone();
+    order.sort_by_key(|unit| unit_number(unit));
+    order.dedup();
+    order
+}
+
+impl UpgradePlan {
+    pub fn new(version: &str, units: &Vec<String>) -> UpgradePlan {
+        UpgradePlan {
+            version: version.to_string(),
+            order: unit_order(units),
+            current: None,
+            granted: None,
+            done: Vec::new(),
+            timed_out: BTreeMap::new(),
+            paused: false,
         }
-    };
-    log!(format!("upgrade position: {}", position));
-    if position == 0 {
-        // I'm first!  Roll
-        // First set a key to inform others I'm about to roll
-        lock_and_roll(&my_uuid, new_version)?;
-    } else {
-        // Check if the previous node has finished
-        juju::status_set(juju::Status {
-                             status_type: juju::StatusType::Waiting,
-                             message: format!("Waiting on {:?} to finish upgrading",
-                                              peer_list[position - 1]),
-                         }).map_err(|e| e.to_string())?;
-        wait_on_previous_node(&peer_list[position - 1], new_version)?;
-        lock_and_roll(&my_uuid, new_version)?;
     }
+
+    fn finished(&self, progress: &BTreeMap<String, UnitProgress>, unit: &str) -> bool {
+        match progress.get(unit) {
+            Some(p) => p.version == self.version && p.done.is_some(),
+            None => false,
+        }
+    }
+
+    // A skipped unit is back once it reports anything newer than the skip
+    fn returned(&self, progress: &BTreeMap<String, UnitProgress>, unit: &str, at: i64) -> bool {
+        match progress.get(unit) {
+            Some(p) => {
+                p.version == self.version &&
+                (p.requested.map_or(false, |r| r > at) || p.done.map_or(false, |d| d > at))
+            }
+            None => false,
+        }
+    }
+
+    /// Move the lock along.  Returns true when the plan changed and needs to
+    /// be saved.
+    pub fn advance(&mut self,
+                   progress: &BTreeMap<String, UnitProgress>,
+                   clock: &Clock,
+                   timeout: i64)
+                   -> bool {
+        let now = clock.now();
+        let mut changed = false;
+        let returned: Vec<String> = self.timed_out
+            .iter()
+            .filter(|&(unit, at)| self.returned(progress, unit, *at))
+            .map(|(unit, _)| unit.clone())
+            .collect();
+        for unit in returned {
+            log!(format!("{} is back.  Queueing it for the upgrade to {} again",
+                         unit,
+                         self.version));
+            self.timed_out.remove(&unit);
+            changed = true;
+        }
+        if let Some(current) = self.current.clone() {
+            if self.finished(progress, &current) {
+                log!(format!("{} finished upgrading to {}", current, self.version));
+                self.done.push(current);
+            } else if now - self.granted.unwrap_or(now) > timeout {
+                log!(format!("{} didn't finish upgrading to {} within {} seconds.  Moving on",
+                             current,
+                             self.version,
+                             timeout),
+                     Error);
+                self.timed_out.insert(current, now);
+            } else {
+                return changed;
+            }
+            self.current = None;
+            self.granted = None;
+            changed = true;
+        }
+        if self.paused {
+            return changed;
+        }
+        let next = self.order
+            .iter()
+            .find(|unit| !self.done.contains(unit) && !self.timed_out.contains_key(*unit))
+            .cloned();
+        if let Some(next) = next {
+            log!(format!("Handing the upgrade lock to {}", next));
+            self.current = Some(next);
+            self.granted = Some(now);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Forget units that left the server relation.  A unit removed while it
+    /// held the lock or after it timed out would hold up the plan forever.
+    /// Returns true when the plan changed.
+    pub fn drop_departed(&mut self, units: &Vec<String>) -> bool {
+        let departed: Vec<String> = self.order
+            .iter()
+            .filter(|unit| !units.contains(unit))
+            .cloned()
+            .collect();
+        for unit in &departed {
+            log!(format!("{} left the cluster.  Dropping it from the upgrade to {}",
+                         unit,
+                         self.version));
+            self.order.retain(|u| u != unit);
+            self.timed_out.remove(unit);
+            if self.current.as_ref() == Some(unit) {
+                self.current = None;
+                self.granted = None;
+            }
+        }
+        !departed.is_empty()
+    }
+
+    /// Every unit upgraded
+    pub fn complete(&self) -> bool {
+        self.order.iter().all(|unit| self.done.contains(unit))
+    }
+
+    pub fn to_settings(&self) -> HashMap<String, String> {
+        let mut settings: HashMap<String, String> = HashMap::new();
+        settings.insert("upgrade-version".to_string(), self.version.clone());
+        settings.insert("upgrade-order".to_string(), self.order.join(" "));
+        settings.insert("upgrade-current".to_string(),
+                        self.current.clone().unwrap_or(String::new()));
+        settings.insert("upgrade-granted".to_string(),
+                        self.granted.map(|g| g.to_string()).unwrap_or(String::new()));
+        settings.insert("upgrade-done".to_string(), self.done.join(" "));
+        let timed_out: Vec<String> = self.timed_out
+            .iter()
+            .map(|(unit, at)| format!("{}={}", unit, at))
+            .collect();
+        settings.insert("upgrade-timed-out".to_string(), timed_out.join(" "));
+        settings.insert("upgrade-paused".to_string(),
+                        if self.paused { "true" } else { "" }.to_string());
+        settings
+    }
+
+    /// None when no upgrade was ever started
+    pub fn from_settings(settings: &HashMap<String, String>)
+                         -> Result<Option<UpgradePlan>, String> {
+        let get = |key: &str| settings.get(key).map(|v| v.trim().to_string()).unwrap_or_default();
+        let list = |key: &str| -> Vec<String> {
+            get(key).split_whitespace().map(|unit| unit.to_string()).collect()
+        };
+        let version = get("upgrade-version");
+        if version.is_empty() {
+            return Ok(None);
+        }
+        let current = get("upgrade-current");
+        let granted = get("upgrade-granted");
+        let mut timed_out: BTreeMap<String, i64> = BTreeMap::new();
+        for entry in list("upgrade-timed-out") {
+            let parts: Vec<&str> = entry.splitn(2, '=').collect();
+            if parts.len() != 2 {
+                return Err(format!("Invalid upgrade-timed-out entry {}", entry));
+            }
+            timed_out.insert(parts[0].to_string(),
+                             parts[1].parse::<i64>().map_err(|e| e.to_string())?);
+        }
+        Ok(Some(UpgradePlan {
+            version: version,
+            order: list("upgrade-order"),
+            current: if current.is_empty() { None } else { Some(current) },
+            granted: if granted.is_empty() {
+                None
+            } else {
+                Some(granted.parse::<i64>().map_err(|e| e.to_string())?)
+            },
+            done: list("upgrade-done"),
+            timed_out: timed_out,
+            paused: get("upgrade-paused") == "true",
+        }))
+    }
+
+    /// How far along the upgrade is for the unit status
+    pub fn condition(&self) -> Option<Condition> {
+        if self.complete() {
+            return None;
+        }
+        let mut message = format!("Upgrading to {}: {}/{} done",
+                                  self.version,
+                                  self.done.len(),
+                                  self.order.len());
+        if let Some(ref current) = self.current {
+            message = format!("{}, {} upgrading", message, current);
+        }
+        if !self.timed_out.is_empty() {
+            let skipped: Vec<String> = self.timed_out.keys().cloned().collect();
+            message = format!("{}, {} timed out", message, skipped.join(" "));
+        }
+        if self.paused {
+            message = format!("{}, paused", message);
+        }
+        Some(Condition::active(&message))
+    }
+}
+
+const PLAN_KEYS: [&'static str; 7] = ["upgrade-version",
+                                      "upgrade-order",
+                                      "upgrade-current",
+                                      "upgrade-granted",
+                                      "upgrade-done",
+                                      "upgrade-timed-out",
+                                      "upgrade-paused"];
+
+fn load_plan() -> Result<Option<UpgradePlan>, String> {
+    let mut settings: HashMap<String, String> = HashMap::new();
+    for key in PLAN_KEYS.iter() {
+        if let Some(value) = juju::leader_get(Some(key.to_string())).map_err(|e| e.to_string())? {
+            settings.insert(key.to_string(), value);
+        }
+    }
+    UpgradePlan::from_settings(&settings)
+}
+
+fn save_plan(plan: &UpgradePlan) -> Result<(), String> {
+    juju::leader_set(plan.to_settings()).map_err(|e| e.to_string())?;
     Ok(())
 }
 
-pub fn upgrade_peer(new_version: &Version) -> Result<(), String> {
-    let current_version = get_glusterfs_version().map_err(|e| e.to_string())?;
-    juju::status_set(juju::Status {
-                         status_type: juju::StatusType::Maintenance,
-                         message: "Upgrading peer".to_string(),
-                     }).map_err(|e| e.to_string())?;
-    log!(format!("Current ceph version is {}", current_version));
-    log!(format!("Upgrading to: {}", new_version));
+fn local_unit() -> Result<String, String> {
+    env::var("JUJU_UNIT_NAME").map_err(|e| format!("JUJU_UNIT_NAME: {}", e))
+}
+
+// gluster/1 as the relation helpers want it
+fn unit_relation(unit: &str) -> Result<juju::Relation, String> {
+    let parts: Vec<&str> = unit.splitn(2, '/').collect();
+    if parts.len() != 2 {
+        return Err(format!("Invalid unit name {}", unit));
+    }
+    Ok(juju::Relation {
+        name: parts[0].to_string(),
+        id: parts[1].parse::<usize>().map_err(|e| e.to_string())?,
+    })
+}
+
+/// This unit and its peers
+fn cluster_units() -> Result<Vec<String>, String> {
+    let mut units = vec![local_unit()?];
+    for relation in juju::relation_ids_by_identifier("server").map_err(|e| e.to_string())? {
+        for unit in juju::relation_list_by_id(&relation).map_err(|e| e.to_string())? {
+            units.push(format!("{}/{}", unit.name, unit.id));
+        }
+    }
+    Ok(units)
+}
 
-    apt::service_stop("glusterfs-server")?;
-    apt::apt_install(vec!["glusterfs-server", "glusterfs-common", "glusterfs-client"])?;
-    apt::service_start("glusterfs-server")?;
-    super::update_status()?;
-    return Ok(());
+/// What every unit, this one included, reported on the server relation
+fn read_progress() -> Result<BTreeMap<String, UnitProgress>, String> {
+    let mut progress: BTreeMap<String, UnitProgress> = BTreeMap::new();
+    for relation in juju::relation_ids_by_identifier("server").map_err(|e| e.to_string())? {
+        for unit in cluster_units()? {
+            let get = |key: &str| -> Result<Option<String>, String> {
+                juju::relation_get_by_id(key, &relation, &unit_relation(&unit)?)
+                    .map_err(|e| e.to_string())
+            };
+            let version = match get("upgrade-version")? {
+                Some(version) => version,
+                None => continue,
+            };
+            let timestamp = |value: Option<String>| value.and_then(|v| v.parse::<i64>().ok());
+            progress.insert(unit.clone(),
+                            UnitProgress {
+                                version: version,
+                                requested: timestamp(get("upgrade-requested")?),
+                                done: timestamp(get("upgrade-done")?),
+                            });
+        }
+    }
+    Ok(progress)
 }
 
-fn lock_and_roll(my_uuid: &Uuid, version: &Version) -> Result<(), String> {
-    let start_timestamp = Local::now();
+fn report_progress(version: &str, key: &str, timestamp: i64) -> Result<(), String> {
+    for relation in juju::relation_ids_by_identifier("server").map_err(|e| e.to_string())? {
+        juju::relation_set_by_id("upgrade-version", version, &relation)
+            .map_err(|e| e.to_string())?;
+        juju::relation_set_by_id(key, &timestamp.to_string(), &relation)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
 
-    log!(format!("gluster_key_set {}_{}_start {}",
-                 my_uuid,
-                 version,
-                 start_timestamp));
-    gluster_key_set(&format!("{}_{}_start", &my_uuid, version), start_timestamp)?;
-    log!("Rolling");
+fn upgrade_timeout() -> i64 {
+    get_config_number::<i64>("upgrade_timeout").unwrap_or(30) * 60
+}
 
-    // This should be quick
-    upgrade_peer(&version)?;
-    log!("Done");
+/// Whether a source change starts the rolling upgrade by itself
+#[derive(Debug, Eq, PartialEq)]
+pub enum UpgradeMode {
+    Auto,
+    /// Wait for the resume-upgrade action
+    Manual,
+}
 
-    let stop_timestamp = Local::now();
-    // Set a key to inform others I am finished
-    log!(format!("gluster_key_set {}_{}_done {}",
-                 my_uuid,
-                 version,
-                 stop_timestamp));
-    gluster_key_set(&format!("{}_{}_done", &my_uuid, version), stop_timestamp)?;
+impl FromStr for UpgradeMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "auto" => Ok(UpgradeMode::Auto),
+            "manual" => Ok(UpgradeMode::Manual),
+            _ => Err(format!("Unknown upgrade_mode {}.  Use auto or manual", s)),
+        }
+    }
+}
 
-    return Ok(());
+pub fn get_upgrade_mode() -> Result<UpgradeMode, String> {
+    UpgradeMode::from_str(&get_config_value("upgrade_mode")?)
 }
 
+/// The releases each gluster release can be upgraded to in one go.  Gluster
+/// only tests upgrades from the releases it still maintained when the new one
+/// came out so older clusters have to upgrade in several steps.  From 5 on
+/// the upgrade guide of each release lists the ones it can be upgraded from.
+const UPGRADE_PATHS: [((u64, u64), &'static [(u64, u64)]); 11] =
+    [((3, 7), &[(3, 8)]),
+     ((3, 8), &[(3, 9), (3, 10)]),
+     ((3, 9), &[(3, 10)]),
+     ((3, 10), &[(3, 11), (3, 12)]),
+     ((3, 11), &[(3, 12)]),
+     ((3, 12), &[(3, 13), (4, 0), (4, 1), (5, 0), (6, 0)]),
+     ((3, 13), &[(4, 0), (4, 1)]),
+     ((4, 0), &[(4, 1)]),
+     ((4, 1), &[(5, 0), (6, 0), (7, 0)]),
+     ((5, 0), &[(6, 0), (7, 0)]),
+     ((6, 0), &[(7, 0)])];
 
+/// The newest release UPGRADE_PATHS knows the upgrade paths to
+const NEWEST_KNOWN_RELEASE: (u64, u64) = (7, 0);
 
-fn gluster_key_get(key: &str) -> Option<DateTime<Local>> {
-    let mut f = match File::open(&format!("/mnt/glusterfs/.upgrade/{}", key)) {
-        Ok(f) => f,
-        Err(_) => {
-            return None;
+/// Unit data keys for the upgrade path check, the installed version and
+/// what the local upgrade waits on
+const BLOCKED_KEY: &'static str = "upgrade.blocked";
+const SOURCE_KEY: &'static str = "upgrade.source";
+const VERSION_KEY: &'static str = "upgrade.version";
+const WAITING_KEY: &'static str = "upgrade.waiting";
+
+/// The major and minor number of a gluster version.  3.12.1-1 is 3.12.  From
+/// 5 on the major number alone names the release and the minor number counts
+/// updates so 5.13-1 is 5.0.
+pub fn release(version: &PackageVersion) -> Option<(u64, u64)> {
+    let mut numbers = version.version.split('.').map(|n| n.parse::<u64>().ok());
+    match (numbers.next(), numbers.next()) {
+        (Some(Some(major)), Some(Some(_))) if major >= 5 => Some((major, 0)),
+        (Some(Some(major)), Some(Some(minor))) => Some((major, minor)),
+        _ => None,
+    }
+}
+
+// 3.12 or 5
+fn release_name(release: (u64, u64)) -> String {
+    if release.0 >= 5 {
+        release.0.to_string()
+    } else {
+        format!("{}.{}", release.0, release.1)
+    }
+}
+
+/// Whether gluster supports upgrading from one version to the other.
+/// Updates within a release are always fine.
+pub fn check_upgrade_path(from: &PackageVersion, to: &PackageVersion) -> Result<(), String> {
+    if to < from {
+        return Err(format!("Downgrading gluster from {} to {} is not supported", from, to));
+    }
+    let (from_release, to_release) = match (release(from), release(to)) {
+        (Some(from_release), Some(to_release)) => (from_release, to_release),
+        _ => return Err(format!("Unknown gluster release in {} or {}", from, to)),
+    };
+    if from_release == to_release {
+        return Ok(());
+    }
+    if to_release > NEWEST_KNOWN_RELEASE {
+        return Err(format!("Gluster {} is an unknown release.  The charm knows the upgrade \
+                            paths up to {}",
+                           release_name(to_release),
+                           release_name(NEWEST_KNOWN_RELEASE)));
+    }
+    let supported = UPGRADE_PATHS.iter()
+        .any(|&(source, targets)| source == from_release && targets.contains(&to_release));
+    if !supported {
+        return Err(format!("Upgrading gluster from {} to {} is not supported.  Upgrade \
+                            through the releases in between",
+                           release_name(from_release),
+                           release_name(to_release)));
+    }
+    Ok(())
+}
+
+/// Remember why the source can't be upgraded to so the unit stays Blocked
+/// until source changes again.  None clears it.
+pub fn set_upgrade_blocked(reason: Option<&str>) -> Result<(), String> {
+    let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+    match reason {
+        Some(reason) => unit_storage.set(BLOCKED_KEY, reason).map_err(|e| e.to_string())?,
+        None => unit_storage.unset(BLOCKED_KEY).map_err(|e| e.to_string())?,
+    }
+    Ok(())
+}
+
+/// Blocked while the source offers a version gluster can't upgrade to
+pub fn upgrade_path_condition() -> Result<Option<Condition>, String> {
+    let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+    let reason = unit_storage.get::<String>(BLOCKED_KEY).map_err(|e| e.to_string())?;
+    Ok(reason.map(|reason| Condition::blocked(&reason)))
+}
+
+/// Keep track of the source and version this unit runs and tell the peers
+/// about the version when it changes.  Run from update-status, config-changed
+/// and upgrade-charm.
+pub fn record_version() -> Result<(), String> {
+    let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+    let source = juju::config_get("source").map_err(|e| e.to_string())?.unwrap_or_default();
+    unit_storage.set(SOURCE_KEY, source).map_err(|e| e.to_string())?;
+    let version = get_glusterfs_version()?.to_string();
+    let recorded = unit_storage.get::<String>(VERSION_KEY).map_err(|e| e.to_string())?;
+    if recorded.as_ref() == Some(&version) {
+        return Ok(());
+    }
+    log!(format!("Running gluster {}", version), Info);
+    for relation in juju::relation_ids_by_identifier("server").map_err(|e| e.to_string())? {
+        juju::relation_set_by_id("gluster-version", &version, &relation)
+            .map_err(|e| e.to_string())?;
+    }
+    unit_storage.set(VERSION_KEY, version).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The units on each version when they don't all agree
+pub fn mixed_versions(versions: &BTreeMap<String, String>) -> Option<String> {
+    let mut by_version: BTreeMap<&String, Vec<&str>> = BTreeMap::new();
+    for (unit, version) in versions {
+        by_version.entry(version).or_insert_with(Vec::new).push(unit);
+    }
+    if by_version.len() < 2 {
+        return None;
+    }
+    let groups: Vec<String> = by_version.iter()
+        .map(|(version, units)| format!("{} on {}", version, units.join(" ")))
+        .collect();
+    Some(format!("Mixed versions: {}", groups.join(", ")))
+}
+
+/// Shows which units run which version while the cluster is mixed
+pub fn version_condition() -> Result<Option<Condition>, String> {
+    let mut versions: BTreeMap<String, String> = BTreeMap::new();
+    for relation in juju::relation_ids_by_identifier("server").map_err(|e| e.to_string())? {
+        for unit in cluster_units()? {
+            if let Some(version) = juju::relation_get_by_id("gluster-version",
+                                                            &relation,
+                                                            &unit_relation(&unit)?)
+                .map_err(|e| e.to_string())? {
+                versions.insert(unit, version);
+            }
         }
+    }
+    Ok(mixed_versions(&versions).map(|message| Condition::active(&message)))
+}
+
+/// Start a rolling upgrade to version after source changed.  Only the
+/// leader writes the plan.  The other units pick their turn up from
+/// leader-settings-changed.
+pub fn start_upgrade(version: &PackageVersion) -> Result<(), String> {
+    if !juju::is_leader().map_err(|e| e.to_string())? {
+        log!("Waiting on the leader to schedule the upgrade");
+        return Ok(());
+    }
+    if get_upgrade_mode()? == UpgradeMode::Manual {
+        log!(format!("upgrade_mode is manual.  Run resume-upgrade to upgrade to {}",
+                     version),
+             Info);
+        return Ok(());
+    }
+    schedule_upgrade(version)
+}
+
+fn schedule_upgrade(version: &PackageVersion) -> Result<(), String> {
+    if let Some(plan) = load_plan()? {
+        if plan.version == version.to_string() {
+            log!(format!("Upgrade to {} is already scheduled", version));
+            return Ok(());
+        }
+    }
+    let mut plan = UpgradePlan::new(&version.to_string(), &cluster_units()?);
+    log!(format!("Upgrade order for {}: {}", version, plan.order.join(", ")));
+    plan.advance(&read_progress()?, &SystemClock, upgrade_timeout());
+    save_plan(&plan)
+}
+
+/// Stop handing out the upgrade lock.  Returns the plan that was paused.
+pub fn pause_upgrade() -> Result<UpgradePlan, String> {
+    let mut plan = match load_plan()? {
+        Some(ref plan) if plan.complete() => return Err("The upgrade is complete".to_string()),
+        Some(plan) => plan,
+        None => return Err("No upgrade is in progress".to_string()),
     };
-    let mut s = String::new();
-    match f.read_to_string(&mut s) {
-        Ok(bytes) => {
-            log!(format!("gluster_key_get read {} bytes", bytes));
-        }
-        Err(e) => {
-            log!(format!("gluster_key_get failed to read file \
-                                /mnt/glusterfs/.upgraded/{}. Error: {}",
-                         key,
-                         e),
-                 Error);
-            return None;
+    plan.paused = true;
+    save_plan(&plan)?;
+    Ok(plan)
+}
+
+/// Carry on with a paused upgrade or start the one a source change staged
+/// in manual mode.  Returns the plan that is now running.
+pub fn resume_upgrade() -> Result<UpgradePlan, String> {
+    if let Some(mut plan) = load_plan()? {
+        if !plan.complete() {
+            plan.paused = false;
+            plan.drop_departed(&cluster_units()?);
+            plan.advance(&read_progress()?, &SystemClock, upgrade_timeout());
+            save_plan(&plan)?;
+            return Ok(plan);
         }
+    }
+    let current = get_glusterfs_version()?;
+    let candidate = package_manager().candidate_version("glusterfs-server")?;
+    if candidate <= current {
+        return Err(format!("The newest version available is {} which isn't newer than {}",
+                           candidate,
+                           current));
+    }
+    check_upgrade_path(&current, &candidate)?;
+    schedule_upgrade(&candidate)?;
+    load_plan()?.ok_or("The upgrade plan wasn't saved".to_string())
+}
+
+/// What an upgrade would do right now without starting it
+pub struct UpgradeReport {
+    pub current_version: PackageVersion,
+    pub candidate_version: PackageVersion,
+    pub mode: UpgradeMode,
+    /// The running plan's order or the order a new one would get
+    pub order: Vec<String>,
+    pub plan: Option<UpgradePlan>,
+    /// Reasons the next unit couldn't upgrade
+    pub blockers: Vec<String>,
+}
+
+pub fn upgrade_report() -> Result<UpgradeReport, String> {
+    let current_version = get_glusterfs_version()?;
+    let candidate_version = package_manager().candidate_version("glusterfs-server")?;
+    let mode = get_upgrade_mode()?;
+    let plan = load_plan()?.and_then(|plan| if plan.complete() { None } else { Some(plan) });
+    let mut blockers: Vec<String> = Vec::new();
+    match plan {
+        Some(ref plan) => {
+            if plan.paused {
+                blockers.push("the upgrade is paused.  Run resume-upgrade".to_string());
+            }
+        }
+        None => {
+            if candidate_version <= current_version {
+                blockers.push(format!("No version newer than {} is available", current_version));
+            } else if let Err(reason) = check_upgrade_path(&current_version,
+                                                           &candidate_version) {
+                blockers.push(reason);
+            } else if mode == UpgradeMode::Manual {
+                blockers.push("upgrade_mode is manual.  Run resume-upgrade".to_string());
+            }
+        }
+    }
+    if let Err(reason) = cluster_state().and_then(|state| preflight_checks(&state)) {
+        blockers.push(reason);
+    }
+    Ok(UpgradeReport {
+        current_version: current_version,
+        candidate_version: candidate_version,
+        mode: mode,
+        order: match plan {
+            Some(ref plan) => plan.order.clone(),
+            None => unit_order(&cluster_units()?),
+        },
+        plan: plan,
+        blockers: blockers,
+    })
+}
+
+/// Run from update-status, leader-settings-changed and
+/// server-relation-changed.  The leader moves the lock along and the unit
+/// holding it upgrades.
+pub fn process_upgrade() -> Result<(), String> {
+    set_upgrade_waiting(None)?;
+    let mut plan = match load_plan()? {
+        Some(plan) => plan,
+        None => return Ok(()),
     };
-    let decoded: DateTime<Local> = match json::decode(&s) {
-        Ok(d) => d,
-        Err(e) => {
-            log!(format!("Failed to decode json file in gluster_key_get(): {}", e),
-                 Error);
-            return None;
+    let leader = juju::is_leader().map_err(|e| e.to_string())?;
+    if leader && plan.drop_departed(&cluster_units()?) {
+        save_plan(&plan)?;
+    }
+    if plan.complete() {
+        if leader {
+            bump_op_version()?;
         }
+        return Ok(());
+    }
+    let unit = local_unit()?;
+    let clock = SystemClock;
+    if leader && plan.advance(&read_progress()?, &clock, upgrade_timeout()) {
+        save_plan(&plan)?;
+    }
+    if plan.current.as_ref() == Some(&unit) {
+        let progress = read_progress()?;
+        if !plan.finished(&progress, &unit) {
+            let version = PackageVersion::parse(&plan.version)?;
+            if let UpgradeStep::Waiting(reason) = upgrade_peer(&version)? {
+                let reason = format!("Upgrade to {} waiting: {}", version, reason);
+                log!(reason);
+                return set_upgrade_waiting(Some(&reason));
+            }
+            report_progress(&plan.version, "upgrade-done", clock.now())?;
+            if leader && plan.advance(&read_progress()?, &clock, upgrade_timeout()) {
+                save_plan(&plan)?;
+            }
+        }
+    } else if let Some(at) = plan.timed_out.get(&unit).cloned() {
+        // This unit died while holding the lock.  Ask for another turn.
+        if !plan.returned(&read_progress()?, &unit, at) {
+            log!(format!("Timed out upgrading to {}.  Queueing up again", plan.version));
+            report_progress(&plan.version, "upgrade-requested", clock.now())?;
+        }
+    }
+    if plan.complete() && leader {
+        bump_op_version()?;
+    }
+    Ok(())
+}
+
+// Remember what held this unit's upgrade back for update_status
+fn set_upgrade_waiting(reason: Option<&str>) -> Result<(), String> {
+    let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+    match reason {
+        Some(reason) => unit_storage.set(WAITING_KEY, reason).map_err(|e| e.to_string())?,
+        None => unit_storage.unset(WAITING_KEY).map_err(|e| e.to_string())?,
+    }
+    Ok(())
+}
+
+/// How the upgrade is going for the unit status
+pub fn upgrade_condition() -> Result<Option<Condition>, String> {
+    let unit_storage = unitdata::Storage::new(None).map_err(|e| e.to_string())?;
+    let waiting = unit_storage.get::<String>(WAITING_KEY).map_err(|e| e.to_string())?;
+    if let Some(reason) = waiting {
+        return Ok(Some(Condition::waiting(&reason)));
+    }
+    Ok(load_plan()?.and_then(|plan| plan.condition()))
+}
+
+/// What the checks around the upgrade of a peer look at
+#[derive(Debug)]
+pub struct ClusterState {
+    /// Every peer in the pool, this one included
+    pub peers: Vec<Peer>,
+    /// The status of every brick of every volume
+    pub bricks: Vec<BrickStatus>,
+    /// The bricks on this unit
+    pub local_bricks: Vec<Brick>,
+    /// Heal counts by volume
+    pub heals: Vec<(String, Vec<HealCount>)>,
+}
+
+fn cluster_state() -> Result<ClusterState, String> {
+    let mut state = ClusterState {
+        peers: peer_list().map_err(|e| e.to_string())?,
+        bricks: Vec::new(),
+        local_bricks: Vec::new(),
+        heals: Vec::new(),
     };
-    Some(decoded)
+    for volume in get_volumes()? {
+        match get_local_bricks(&volume.name) {
+            Ok(bricks) => state.local_bricks.extend(bricks),
+            // Nothing to protect on a volume that doesn't exist yet
+            Err(GlusterError::NoVolumesPresent) => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+        state.bricks.extend(volume_status(&volume.name).map_err(|e| e.to_string())?);
+        state.heals.push((volume.name.clone(), heal_count(&volume.name)?));
+    }
+    Ok(state)
+}
+
+fn is_local(state: &ClusterState, brick: &Brick) -> bool {
+    state.local_bricks.iter().any(|local| same_brick(local, brick))
 }
 
-fn gluster_key_set(key: &str, timestamp: DateTime<Local>) -> Result<(), String> {
-    if !Path::new("/mnt/glusterfs/.upgrade").exists() {
-        create_dir("/mnt/glusterfs/.upgrade").map_err(|e| e.to_string())?;
+fn heals_drained(state: &ClusterState) -> Result<(), String> {
+    for &(ref volume, ref counts) in &state.heals {
+        if let Some(count) = counts.iter().find(|count| count.entries.is_none()) {
+            return Err(format!("heal count of {} is unavailable", count.brick));
+        }
+        let pending = pending_heal_entries(counts);
+        if pending > 0 {
+            return Err(format!("{} heals pending on {}", pending, volume));
+        }
     }
-    let mut file = try!(OpenOptions::new()
-                            .write(true)
-                            .create(true)
-                            .open(&format!("/mnt/glusterfs/.upgrade/{}", key))
-                            .map_err(|e| e.to_string()));
-    let encoded = json::encode(&timestamp).map_err(|e| e.to_string())?;
-    try!(file.write(&encoded.as_bytes()).map_err(|e| e.to_string()));
     Ok(())
 }
 
-fn gluster_key_exists(key: &str) -> bool {
-    let location = format!("/mnt/glusterfs/.upgrade/{}", key);
-    let p = Path::new(&location);
-    return p.exists();
-}
-
-pub fn wait_on_previous_node(previous_node: &Peer, version: &Version) -> Result<(), String> {
-    log!(format!("Previous node is: {:?}", previous_node));
-
-    let mut previous_node_finished =
-        gluster_key_exists(&format!("{}_{}_done", previous_node.uuid, version));
-
-    while !previous_node_finished {
-        log!(format!("{} is not finished. Waiting", previous_node.uuid));
-        // Has this node been trying to upgrade for longer than
-        // 10 minutes?
-        // If so then move on and consider that node dead.
-
-        // NOTE: This assumes the clusters clocks are somewhat accurate
-        // If the hosts clock is really far off it may cause it to skip
-        // the previous node even though it shouldn't.
-        let current_timestamp = Local::now();
-
-        let previous_node_start_time =
-            gluster_key_get(&format!("{}_{}_start", previous_node.uuid, version));
-        match previous_node_start_time {
-            Some(previous_start_time) => {
-                if (current_timestamp - Duration::minutes(10)) > previous_start_time {
-                    // Previous node is probably dead.  Lets move on
-                    if previous_node_start_time.is_some() {
-                        log!(format!("Waited 10 mins on node {}. current time: {} > \
-                                            previous node start time: {} Moving on",
-                                     previous_node.uuid,
-                                     (current_timestamp - Duration::minutes(10)),
-                                     previous_start_time));
-                        return Ok(());
-                    }
-                } else {
-                    // I have to wait.  Sleep a random amount of time and then
-                    // check if I can lock,upgrade and roll.
-                    let between = Range::new(5, 30);
-                    let mut rng = rand::thread_rng();
-                    let wait_time = between.ind_sample(&mut rng);
-                    log!(format!("waiting for {} seconds", wait_time));
-                    thread::sleep(::std::time::Duration::from_secs(wait_time));
-                    previous_node_finished =
-                        gluster_key_exists(&format!("{}_{}_done", previous_node.uuid, version));
-                }
-            }
-            None => {
-                // There is no previous start time.  What should we do?
+/// Stopping this peer is only safe when every other copy of the data is
+/// online and in sync.  Otherwise the upgrade can take a replica set below
+/// quorum.
+pub fn preflight_checks(state: &ClusterState) -> Result<(), String> {
+    if let Some(peer) = state.peers.iter().find(|peer| peer.status != State::Connected) {
+        return Err(format!("peer {} is {}", peer.hostname, peer.status.to_string()));
+    }
+    if let Some(brick) = state.bricks
+        .iter()
+        .find(|brick| !brick.online && !is_local(state, &brick.brick)) {
+        return Err(format!("brick {}:{} is offline",
+                           brick.brick.peer.hostname,
+                           brick.brick.path.display()));
+    }
+    heals_drained(state)
+}
+
+/// The peer is only done once its bricks are serving again and caught up
+/// on the writes they missed
+pub fn postflight_checks(state: &ClusterState) -> Result<(), String> {
+    if let Some(brick) = local_bricks_offline(state).first() {
+        return Err(format!("brick {} is not back online", brick));
+    }
+    heals_drained(state)
+}
+
+fn local_bricks_offline(state: &ClusterState) -> Vec<String> {
+    state.bricks
+        .iter()
+        .filter(|brick| !brick.online && is_local(state, &brick.brick))
+        .map(|brick| brick.brick.path.display().to_string())
+        .collect()
+}
+
+/// Give the local bricks some time to start after glusterd comes back
+fn wait_for_local_bricks() -> Result<ClusterState, String> {
+    let mut state = cluster_state()?;
+    for _ in 0..BRICK_START_RETRIES {
+        if local_bricks_offline(&state).is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_secs(BRICK_START_INTERVAL));
+        state = cluster_state()?;
+    }
+    Ok(state)
+}
+
+/// Where this unit is with its turn at the upgrade
+#[derive(Debug, PartialEq)]
+pub enum UpgradeStep {
+    /// Not safe to go on yet.  The next hook checks again.
+    Waiting(String),
+    Done,
+}
+
+/// Upgrade the packages if the cluster can spare this peer and then wait
+/// for it to rejoin.  Running it again after a failed hook picks up where
+/// it left off.
+pub fn upgrade_peer(new_version: &PackageVersion) -> Result<UpgradeStep, String> {
+    let current_version = get_glusterfs_version().map_err(|e| e.to_string())?;
+    let packages = package_manager();
+    if current_version < *new_version {
+        let candidate = packages.candidate_version("glusterfs-server")?;
+        if candidate < *new_version {
+            // config-changed hasn't added the new source on this unit yet
+            return Ok(UpgradeStep::Waiting(format!("only {} is available", candidate)));
+        }
+        // An earlier attempt may have died with glusterd stopped
+        if peer_list().is_err() {
+            log!("glusterd is not responding.  Starting it", Info);
+            apt::service_start(packages.gluster_service())?;
+        }
+        if let Err(reason) = preflight_checks(&cluster_state()?) {
+            return Ok(UpgradeStep::Waiting(reason));
+        }
+        status_set!(Maintenance "Upgrading peer");
+        log!(format!("Current gluster version is {}", current_version));
+        log!(format!("Upgrading to: {}", new_version));
+
+        apt::service_stop(packages.gluster_service())?;
+        let installed =
+            packages.install(vec!["glusterfs-server", "glusterfs-common", "glusterfs-client"]);
+        // Start glusterd again even if the install failed so the bricks on
+        // this peer don't stay down
+        let started = apt::service_start(packages.gluster_service());
+        if let Err(e) = installed {
+            if let Err(start_error) = started {
+                log!(format!("Starting {} failed: {}", packages.gluster_service(), start_error),
+                     Error);
             }
+            return Err(format!("Installing gluster {} failed: {}", new_version, e));
         }
+        started?;
+    }
+    status_set!(Maintenance "Waiting for bricks and heals after the upgrade");
+    if let Err(reason) = postflight_checks(&wait_for_local_bricks()?) {
+        return Ok(UpgradeStep::Waiting(reason));
+    }
+    Ok(UpgradeStep::Done)
+}
+
+// Option                                  Value
+// ------                                  -----
+// cluster.max-op-version                  31202
+pub fn parse_volume_get(output: &str, key: &str) -> Option<String> {
+    output.lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() == 2 && parts[0] == key {
+                Some(parts[1].to_string())
+            } else {
+                None
+            }
+        })
+        .next()
+}
+
+fn cluster_option(key: &str) -> Result<u32, String> {
+    let output = Command::new("gluster").args(&["volume", "get", "all", key])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    match parse_volume_get(&String::from_utf8_lossy(&output.stdout), key) {
+        Some(value) => value.parse::<u32>().map_err(|e| e.to_string()),
+        None => Err(format!("gluster volume get all {} returned nothing", key)),
+    }
+}
+
+/// New features only turn on once the cluster op-version is raised.  That
+/// has to wait until every peer runs the new version.
+fn bump_op_version() -> Result<(), String> {
+    let current = cluster_option("cluster.op-version")?;
+    let max = cluster_option("cluster.max-op-version")?;
+    if current >= max {
+        return Ok(());
+    }
+    log!(format!("Raising cluster.op-version from {} to {}", current, max), Info);
+    let output = Command::new("gluster")
+        .args(&["volume", "set", "all", "cluster.op-version", &max.to_string()])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
     }
     Ok(())
 }
+
+#[cfg(test)]
+struct FakeClock {
+    now: Cell<i64>,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    fn tick(&self, seconds: i64) {
+        self.now.set(self.now.get() + seconds);
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> i64 {
+        self.now.get()
+    }
+}
+
+#[cfg(test)]
+fn report(progress: &mut BTreeMap<String, UnitProgress>,
+          unit: &str,
+          requested: Option<i64>,
+          done: Option<i64>) {
+    progress.insert(unit.to_string(),
+                    UnitProgress {
+                        version: "3.12.1".to_string(),
+                        requested: requested,
+                        done: done,
+                    });
+}
+
+#[test]
+fn test_rolling_upgrade_order() {
+    let clock = FakeClock { now: Cell::new(1000) };
+    let units = vec!["gluster/10".to_string(), "gluster/2".to_string(), "gluster/0".to_string()];
+    let mut plan = UpgradePlan::new("3.12.1", &units);
+    assert_eq!(vec!["gluster/0", "gluster/2", "gluster/10"], plan.order);
+
+    let mut progress: BTreeMap<String, UnitProgress> = BTreeMap::new();
+    assert!(plan.advance(&progress, &clock, 600));
+    assert_eq!(Some("gluster/0".to_string()), plan.current);
+    // Nothing moves until the lock holder reports back
+    clock.tick(300);
+    assert!(!plan.advance(&progress, &clock, 600));
+
+    report(&mut progress, "gluster/0", None, Some(clock.now()));
+    assert!(plan.advance(&progress, &clock, 600));
+    assert_eq!(Some("gluster/2".to_string()), plan.current);
+    assert_eq!(Some(1300), plan.granted);
+
+    report(&mut progress, "gluster/2", None, Some(clock.now()));
+    plan.advance(&progress, &clock, 600);
+    report(&mut progress, "gluster/10", None, Some(clock.now()));
+    plan.advance(&progress, &clock, 600);
+    assert!(plan.complete());
+    assert_eq!(None, plan.current);
+    assert_eq!(None, plan.condition());
+
+    // An old done from a previous upgrade doesn't count
+    let mut plan = UpgradePlan::new("3.13.0", &units);
+    plan.advance(&progress, &clock, 600);
+    assert!(!plan.advance(&progress, &clock, 600));
+    assert_eq!(Some("gluster/0".to_string()), plan.current);
+}
+
+#[test]
+fn test_rolling_upgrade_timeout_and_resume() {
+    let clock = FakeClock { now: Cell::new(1000) };
+    let units = vec!["gluster/0".to_string(), "gluster/1".to_string(), "gluster/2".to_string()];
+    let mut plan = UpgradePlan::new("3.12.1", &units);
+    let mut progress: BTreeMap<String, UnitProgress> = BTreeMap::new();
+    plan.advance(&progress, &clock, 600);
+
+    // gluster/0 dies while upgrading.  After the timeout gluster/1 goes.
+    clock.tick(600);
+    assert!(!plan.advance(&progress, &clock, 600));
+    clock.tick(1);
+    assert!(plan.advance(&progress, &clock, 600));
+    assert_eq!(Some("gluster/1".to_string()), plan.current);
+    assert_eq!(Some(&1601), plan.timed_out.get("gluster/0"));
+    assert_eq!(Some(Condition::active("Upgrading to 3.12.1: 0/3 done, gluster/1 upgrading, \
+                                       gluster/0 timed out")),
+               plan.condition());
+
+    // The plan survives a trip through leader settings
+    let saved = UpgradePlan::from_settings(&plan.to_settings()).unwrap();
+    assert_eq!(Some(plan.clone()), saved);
+
+    // gluster/0 comes back and gets the next turn
+    clock.tick(60);
+    report(&mut progress, "gluster/0", Some(clock.now()), None);
+    report(&mut progress, "gluster/1", None, Some(clock.now()));
+    assert!(plan.advance(&progress, &clock, 600));
+    assert!(plan.timed_out.is_empty());
+    assert_eq!(Some("gluster/0".to_string()), plan.current);
+
+    report(&mut progress, "gluster/0", Some(1661), Some(clock.now()));
+    plan.advance(&progress, &clock, 600);
+    assert_eq!(Some("gluster/2".to_string()), plan.current);
+    report(&mut progress, "gluster/2", None, Some(clock.now()));
+    plan.advance(&progress, &clock, 600);
+    assert!(plan.complete());
+    assert_eq!(vec!["gluster/1", "gluster/0", "gluster/2"], plan.done);
+
+    let empty: HashMap<String, String> = HashMap::new();
+    assert_eq!(None, UpgradePlan::from_settings(&empty).unwrap());
+}
+
+#[test]
+fn test_rolling_upgrade_departed_units() {
+    let clock = FakeClock { now: Cell::new(1000) };
+    let units = vec!["gluster/0".to_string(), "gluster/1".to_string(), "gluster/2".to_string()];
+    let mut plan = UpgradePlan::new("3.12.1", &units);
+    let mut progress: BTreeMap<String, UnitProgress> = BTreeMap::new();
+    plan.advance(&progress, &clock, 600);
+
+    // gluster/0 dies for good and gluster/1 upgrades in its place
+    clock.tick(601);
+    plan.advance(&progress, &clock, 600);
+    report(&mut progress, "gluster/1", None, Some(clock.now()));
+    plan.advance(&progress, &clock, 600);
+    assert_eq!(Some("gluster/2".to_string()), plan.current);
+    assert!(!plan.drop_departed(&units));
+
+    // Removing it lets the plan finish
+    let remaining = vec!["gluster/1".to_string(), "gluster/2".to_string()];
+    assert!(plan.drop_departed(&remaining));
+    assert!(plan.timed_out.is_empty());
+    assert_eq!(vec!["gluster/1", "gluster/2"], plan.order);
+    report(&mut progress, "gluster/2", None, Some(clock.now()));
+    plan.advance(&progress, &clock, 600);
+    assert!(plan.complete());
+
+    // The lock holder leaving hands the lock on
+    let mut plan = UpgradePlan::new("3.13.0", &units);
+    plan.advance(&progress, &clock, 600);
+    assert_eq!(Some("gluster/0".to_string()), plan.current);
+    assert!(plan.drop_departed(&remaining));
+    assert!(plan.advance(&progress, &clock, 600));
+    assert_eq!(Some("gluster/1".to_string()), plan.current);
+}
+
+#[test]
+fn test_upgrade_checks() {
+    use super::uuid::Uuid;
+    use std::path::PathBuf;
+
+    let peer = |hostname: &str, status: State| {
+        Peer {
+            uuid: Uuid::new_v4(),
+            hostname: hostname.to_string(),
+            status: status,
+        }
+    };
+    let brick = |hostname: &str, online: bool| {
+        BrickStatus {
+            brick: Brick {
+                peer: peer(hostname, State::Connected),
+                path: PathBuf::from("/mnt/sdb"),
+            },
+            tcp_port: 49152,
+            rdma_port: 0,
+            online: online,
+            pid: 1000,
+        }
+    };
+    let heal = |brick: &str, entries: Option<u64>| {
+        HealCount {
+            brick: brick.to_string(),
+            entries: entries,
+        }
+    };
+    let mut state = ClusterState {
+        peers: vec![peer("10.0.0.1", State::Connected), peer("10.0.0.2", State::Connected)],
+        bricks: vec![brick("10.0.0.1", true), brick("10.0.0.2", true)],
+        local_bricks: vec![brick("10.0.0.1", true).brick],
+        heals: vec![("test".to_string(),
+                     vec![heal("10.0.0.1:/mnt/sdb", Some(0)),
+                          heal("10.0.0.2:/mnt/sdb", Some(0))])],
+    };
+    assert_eq!(Ok(()), preflight_checks(&state));
+    assert_eq!(Ok(()), postflight_checks(&state));
+
+    state.heals[0].1[1].entries = Some(12);
+    assert_eq!(Err("12 heals pending on test".to_string()), preflight_checks(&state));
+    state.heals[0].1[1].entries = None;
+    assert_eq!(Err("heal count of 10.0.0.2:/mnt/sdb is unavailable".to_string()),
+               postflight_checks(&state));
+    state.heals[0].1[1].entries = Some(0);
+
+    // The other replica going offline stops the upgrade.  The local brick
+    // being down only matters after the restart.
+    state.bricks[1].online = false;
+    assert_eq!(Err("brick 10.0.0.2:/mnt/sdb is offline".to_string()),
+               preflight_checks(&state));
+    state.bricks[1].online = true;
+    state.bricks[0].online = false;
+    assert_eq!(Ok(()), preflight_checks(&state));
+    assert_eq!(Err("brick /mnt/sdb is not back online".to_string()),
+               postflight_checks(&state));
+
+    state.peers[1].status = State::Disconnected;
+    assert_eq!(Err("peer 10.0.0.2 is Disconnected".to_string()),
+               preflight_checks(&state));
+
+    let output = "Option                                  Value\n\
+                  ------                                  -----\n\
+                  cluster.max-op-version                  31202\n";
+    assert_eq!(Some("31202".to_string()),
+               parse_volume_get(output, "cluster.max-op-version"));
+    assert_eq!(None, parse_volume_get(output, "cluster.op-version"));
+}
+
+#[test]
+fn test_pause_upgrade() {
+    let clock = FakeClock { now: Cell::new(1000) };
+    let units = vec!["gluster/0".to_string(), "gluster/1".to_string()];
+    let mut plan = UpgradePlan::new("3.12.1", &units);
+    let mut progress: BTreeMap<String, UnitProgress> = BTreeMap::new();
+    plan.advance(&progress, &clock, 600);
+
+    // gluster/0 finishes while paused but gluster/1 has to wait
+    plan.paused = true;
+    clock.tick(60);
+    report(&mut progress, "gluster/0", None, Some(clock.now()));
+    assert!(plan.advance(&progress, &clock, 600));
+    assert_eq!(None, plan.current);
+    assert_eq!(Some(Condition::active("Upgrading to 3.12.1: 1/2 done, paused")),
+               plan.condition());
+    assert_eq!(Some(plan.clone()),
+               UpgradePlan::from_settings(&plan.to_settings()).unwrap());
+
+    plan.paused = false;
+    assert!(plan.advance(&progress, &clock, 600));
+    assert_eq!(Some("gluster/1".to_string()), plan.current);
+
+    assert_eq!(Ok(UpgradeMode::Auto), UpgradeMode::from_str(""));
+    assert_eq!(Ok(UpgradeMode::Manual), UpgradeMode::from_str("manual"));
+    assert!(UpgradeMode::from_str("sometimes").is_err());
+}
+
+#[test]
+fn test_upgrade_paths() {
+    let version = |v: &str| PackageVersion::parse(v).unwrap();
+    assert_eq!(Some((3, 12)), release(&version("3.12.1-ubuntu1~xenial1")));
+    assert!(check_upgrade_path(&version("3.8.15-1"), &version("3.10.5-1")).is_ok());
+    assert!(check_upgrade_path(&version("3.12.1-1"), &version("3.12.3-1")).is_ok());
+    assert_eq!(Err("Upgrading gluster from 3.7 to 3.12 is not supported.  Upgrade through the \
+                    releases in between"
+                       .to_string()),
+               check_upgrade_path(&version("3.7.20-1"), &version("3.12.1-1")));
+    // From 5 on the upgrade guides list what each release upgrades from
+    assert_eq!(Some((5, 0)), release(&version("5.13-1")));
+    assert!(check_upgrade_path(&version("3.12.15-1"), &version("5.13-1")).is_ok());
+    assert!(check_upgrade_path(&version("4.1.10-1"), &version("6.10-1")).is_ok());
+    assert!(check_upgrade_path(&version("4.1.10-1"), &version("7.9-1")).is_ok());
+    assert!(check_upgrade_path(&version("5.5-1"), &version("5.13-1")).is_ok());
+    assert_eq!(Err("Upgrading gluster from 3.12 to 7 is not supported.  Upgrade through the \
+                    releases in between"
+                       .to_string()),
+               check_upgrade_path(&version("3.12.15-1"), &version("7.9-1")));
+    assert_eq!(Err("Gluster 8 is an unknown release.  The charm knows the upgrade paths up \
+                    to 7"
+                       .to_string()),
+               check_upgrade_path(&version("7.9-1"), &version("8.6-1")));
+    assert!(check_upgrade_path(&version("4.0.2-1"), &version("5.13-1")).is_err());
+    assert_eq!(Err("Downgrading gluster from 3.10.5-1 to 3.8.15-1 is not supported".to_string()),
+               check_upgrade_path(&version("3.10.5-1"), &version("3.8.15-1")));
+
+    let mut versions: BTreeMap<String, String> = BTreeMap::new();
+    versions.insert("gluster/0".to_string(), "3.10.5-1".to_string());
+    versions.insert("gluster/1".to_string(), "3.10.5-1".to_string());
+    assert_eq!(None, mixed_versions(&versions));
+    versions.insert("gluster/2".to_string(), "3.12.1-1".to_string());
+    assert_eq!(Some("Mixed versions: 3.10.5-1 on gluster/0 gluster/1, 3.12.1-1 on gluster/2"
+                        .to_string()),
+               mixed_versions(&versions));
+}
@@ -0,0 +1,188 @@
+extern crate gluster;
+
+use std::process::Command;
+
+/// Per brick information from gluster volume status <vol> detail
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrickDetail {
+    /// host:/path as gluster prints it
+    pub brick: String,
+    pub online: bool,
+    pub device: String,
+    /// Free space in bytes
+    pub free: u64,
+    /// Total space in bytes
+    pub total: u64,
+    pub inode_count: u64,
+    pub free_inodes: u64,
+}
+
+impl BrickDetail {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+}
+
+fn run_gluster(args: Vec<&str>) -> Result<String, String> {
+    let mut cmd = Command::new("gluster");
+    cmd.arg("--mode=script");
+    cmd.args(&args);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Query the capacity and inode usage of every brick in the volume
+pub fn volume_status_detail(volume: &str) -> Result<Vec<BrickDetail>, String> {
+    let output = run_gluster(vec!["volume", "status", volume, "detail"])?;
+    Ok(parse_volume_status_detail(&output))
+}
+
+/// Returns how many bricks make up one replica or disperse set in the volume.
+/// A pure distribute volume returns 1.
+pub fn subvolume_size(volume: &str) -> Result<usize, String> {
+    let output = run_gluster(vec!["volume", "info", volume])?;
+    parse_subvolume_size(&output)
+}
+
+// Status of volume: test
+// ------------------------------------------------------------------------------
+// Brick                : Brick 172.31.41.135:/mnt/xvdf
+// Online               : Y
+// Device               : /dev/xvdf
+// Disk Space Free      : 9.9GB
+// Total Disk Space     : 10.0GB
+// Inode Count          : 5242880
+// Free Inodes          : 5242843
+pub fn parse_volume_status_detail(output: &str) -> Vec<BrickDetail> {
+    let mut bricks: Vec<BrickDetail> = Vec::new();
+    for line in output.lines() {
+        let parts: Vec<&str> = line.splitn(2, " : ").collect();
+        if parts.len() != 2 {
+            continue;
+        }
+        let key = parts[0].trim();
+        let value = parts[1].trim();
+        if key == "Brick" {
+            bricks.push(BrickDetail {
+                brick: value.trim_left_matches("Brick ").to_string(),
+                online: false,
+                device: String::new(),
+                free: 0,
+                total: 0,
+                inode_count: 0,
+                free_inodes: 0,
+            });
+            continue;
+        }
+        let brick = match bricks.last_mut() {
+            Some(brick) => brick,
+            None => continue,
+        };
+        match key {
+            "Online" => brick.online = value == "Y",
+            "Device" => brick.device = value.to_string(),
+            "Disk Space Free" => {
+                brick.free = gluster::translate_to_bytes::<f64>(value).unwrap_or(0.0) as u64
+            }
+            "Total Disk Space" => {
+                brick.total = gluster::translate_to_bytes::<f64>(value).unwrap_or(0.0) as u64
+            }
+            "Inode Count" => brick.inode_count = value.parse::<u64>().unwrap_or(0),
+            "Free Inodes" => brick.free_inodes = value.parse::<u64>().unwrap_or(0),
+            _ => {}
+        }
+    }
+    bricks
+}
+
+// Number of Bricks: 2 x 3 = 6
+// Number of Bricks: 1 x (2 + 1) = 3
+// Number of Bricks: 4
+pub fn parse_subvolume_size(output: &str) -> Result<usize, String> {
+    for line in output.lines() {
+        if !line.starts_with("Number of Bricks:") {
+            continue;
+        }
+        let value = line.trim_left_matches("Number of Bricks:").trim();
+        let parts: Vec<&str> = value.splitn(2, " x ").collect();
+        if parts.len() == 1 {
+            // Pure distribute
+            return Ok(1);
+        }
+        let set = parts[1].split('=').next().unwrap_or("").trim();
+        let set = set.trim_left_matches('(').trim_right_matches(')');
+        let mut size = 0;
+        for count in set.split('+') {
+            size += count.trim()
+                .parse::<usize>()
+                .map_err(|e| format!("Unable to parse brick count {}: {}", line, e))?;
+        }
+        return Ok(size);
+    }
+    Err(format!("Unable to find the number of bricks in: {}", output))
+}
+
+#[test]
+fn test_parse_volume_status_detail() {
+    let output = r#"Status of volume: test
+------------------------------------------------------------------------------
+Brick                : Brick 172.31.41.135:/mnt/xvdf
+TCP Port             : 49152
+RDMA Port            : 0
+Online               : Y
+Pid                  : 14228
+File System          : xfs
+Device               : /dev/xvdf
+Mount Options        : rw,relatime,attr2,inode64,noquota
+Inode Size           : 512
+Disk Space Free      : 9.0GB
+Total Disk Space     : 10.0GB
+Inode Count          : 5242880
+Free Inodes          : 5242843
+------------------------------------------------------------------------------
+Brick                : Brick 172.31.26.65:/mnt/xvdf
+TCP Port             : N/A
+RDMA Port            : N/A
+Online               : N
+Pid                  : N/A
+File System          : xfs
+Device               : /dev/xvdf
+Mount Options        : rw,relatime,attr2,inode64,noquota
+Inode Size           : 512
+Disk Space Free      : 10.0GB
+Total Disk Space     : 10.0GB
+Inode Count          : 5242880
+Free Inodes          : 5242880
+"#;
+    let result = parse_volume_status_detail(output);
+    println!("test_parse_volume_status_detail: {:?}", result);
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0],
+               BrickDetail {
+                   brick: "172.31.41.135:/mnt/xvdf".to_string(),
+                   online: true,
+                   device: "/dev/xvdf".to_string(),
+                   free: 9663676416,
+                   total: 10737418240,
+                   inode_count: 5242880,
+                   free_inodes: 5242843,
+               });
+    assert_eq!(result[0].used(), 1073741824);
+    assert_eq!(result[1].online, false);
+}
+
+#[test]
+fn test_parse_subvolume_size() {
+    assert_eq!(parse_subvolume_size("Type: Distributed-Replicate\nNumber of Bricks: 2 x 3 = 6\n")
+                   .unwrap(),
+               3);
+    assert_eq!(parse_subvolume_size("Type: Disperse\nNumber of Bricks: 1 x (4 + 2) = 6\n")
+                   .unwrap(),
+               6);
+    assert_eq!(parse_subvolume_size("Type: Distribute\nNumber of Bricks: 4\n").unwrap(),
+               1);
+    assert!(parse_subvolume_size("Type: Distribute\n").is_err());
+}
@@ -0,0 +1,158 @@
+extern crate gluster;
+extern crate juju;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use self::gluster::GlusterError;
+
+/// Why a volume couldn't be created or expanded
+#[derive(Debug, Clone, PartialEq)]
+pub enum VolumeError {
+    /// Not enough hosts have free bricks to fill a set yet
+    WaitingForPeers(String),
+    /// Peers that didn't connect or couldn't be reached
+    Peer(String),
+    /// Brick devices that couldn't be prepared or placed in sets
+    Brick(String),
+    /// The config asks for a volume gluster can't build
+    Config(String),
+    /// The gluster cli refused the request
+    Gluster(String),
+}
+
+// Gluster cli errors that clear up on their own.  glusterd holds a cluster
+// wide lock during every transaction and peers take a moment to reconnect
+// after a restart.
+const TRANSIENT_GLUSTER_ERRORS: &'static [&'static str] = &["Another transaction is in progress",
+                                                            "Locking failed",
+                                                            "not in 'Peer in Cluster' state",
+                                                            "is not connected"];
+
+impl VolumeError {
+    /// Transient errors go away if the same thing is tried again later.  The
+    /// rest need an operator to change something first.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            VolumeError::WaitingForPeers(_) |
+            VolumeError::Peer(_) => true,
+            VolumeError::Gluster(ref msg) => {
+                TRANSIENT_GLUSTER_ERRORS.iter().any(|transient| msg.contains(transient))
+            }
+            VolumeError::Brick(_) |
+            VolumeError::Config(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for VolumeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VolumeError::WaitingForPeers(ref msg) => write!(f, "{}", msg),
+            VolumeError::Peer(ref msg) => write!(f, "peer error: {}", msg),
+            VolumeError::Brick(ref msg) => write!(f, "brick error: {}", msg),
+            VolumeError::Config(ref msg) => write!(f, "invalid config: {}", msg),
+            VolumeError::Gluster(ref msg) => write!(f, "gluster error: {}", msg.trim()),
+        }
+    }
+}
+
+impl From<GlusterError> for VolumeError {
+    fn from(err: GlusterError) -> VolumeError {
+        VolumeError::Gluster(err.to_string())
+    }
+}
+
+/// Run f up to attempts times, waiting delay between tries, for as long as
+/// it fails with a transient error.
+pub fn retry<T, F>(attempts: usize, delay: Duration, mut f: F) -> Result<T, VolumeError>
+    where F: FnMut() -> Result<T, VolumeError>
+{
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(ref e) if e.is_transient() && attempt < attempts => {
+                log!(format!("Attempt {} of {} failed: {}.  Retrying", attempt, attempts, e));
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Leader settings key holding why setting up the volume last failed
+fn volume_error_key(volume: &str) -> String {
+    format!("volume-error-{}", volume)
+}
+
+/// Remember why setting up the volume failed so update_status keeps
+/// reporting it.  Transient errors are retried and None clears it.  Only the
+/// leader sets up volumes so only the leader calls this.
+pub fn set_volume_error(volume: &str, error: Option<&VolumeError>) -> Result<(), String> {
+    let message = match error {
+        Some(e) if !e.is_transient() => e.to_string(),
+        _ => String::new(),
+    };
+    let key = volume_error_key(volume);
+    let current = juju::leader_get(Some(key.clone())).map_err(|e| e.to_string())?;
+    if current.unwrap_or_default() == message {
+        return Ok(());
+    }
+    let mut settings: HashMap<String, String> = HashMap::new();
+    settings.insert(key, message);
+    juju::leader_set(settings).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Why setting up the volume last failed
+pub fn volume_error(volume: &str) -> Result<Option<String>, String> {
+    let message = juju::leader_get(Some(volume_error_key(volume))).map_err(|e| e.to_string())?;
+    Ok(message.and_then(|m| if m.is_empty() { None } else { Some(m) }))
+}
+
+#[test]
+fn test_is_transient() {
+    assert!(VolumeError::Gluster("volume create: test: failed: Another transaction is in \
+                                  progress for test. Please try again after sometime."
+            .to_string())
+        .is_transient());
+    assert!(!VolumeError::Gluster("volume create: test: failed: /mnt/brick is already part of \
+                                   a volume"
+            .to_string())
+        .is_transient());
+    assert!(VolumeError::WaitingForPeers("need 3 hosts".to_string()).is_transient());
+    assert!(!VolumeError::Config("replicas 1".to_string()).is_transient());
+}
+
+#[test]
+fn test_retry() {
+    let mut calls = 0;
+    let result: Result<(), VolumeError> = retry(3, Duration::from_secs(0), || {
+        calls += 1;
+        Err(VolumeError::Gluster("Locking failed on host-2".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, 3);
+
+    // Permanent errors aren't retried
+    let mut calls = 0;
+    let result: Result<(), VolumeError> = retry(3, Duration::from_secs(0), || {
+        calls += 1;
+        Err(VolumeError::Brick("no bricks".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+
+    let mut calls = 0;
+    let result = retry(3, Duration::from_secs(0), || {
+        calls += 1;
+        if calls < 2 {
+            return Err(VolumeError::Peer("host-2 is not connected".to_string()));
+        }
+        Ok(calls)
+    });
+    assert_eq!(result, Ok(2));
+}
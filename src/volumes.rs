@@ -0,0 +1,440 @@
+extern crate gluster;
+extern crate juju;
+extern crate serde_yaml;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use self::gluster::volume::VolumeType;
+use self::serde_yaml::Value;
+use super::get_config_value;
+
+/// A gluster volume the charm manages.  Every volume gets a brick on every
+/// brick device, optionally inside its own subdirectory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeConfig {
+    pub name: String,
+    /// Same values as the cluster_type config option
+    pub volume_type: String,
+    pub replicas: usize,
+    /// How many of the replicas are arbiters for Arbiter volumes
+    pub arbiter_count: usize,
+    /// Bricks per stripe for the striped volume types
+    pub stripe_count: usize,
+    /// Data bricks per disperse set
+    pub disperse_data: usize,
+    /// Bricks per disperse set that can be lost without losing data
+    pub disperse_redundancy: usize,
+    /// Directory inside each brick device that holds this volume's brick.  None
+    /// places the brick at the root of the device.
+    pub brick_dir: Option<String>,
+    /// Where the volume is mounted with fuse on every unit
+    pub mount_point: String,
+}
+
+impl VolumeConfig {
+    /// The brick path for this volume on a brick device mounted at device_mount
+    pub fn brick_path(&self, device_mount: &str) -> String {
+        match self.brick_dir {
+            Some(ref dir) => Path::new(device_mount).join(dir).to_string_lossy().into_owned(),
+            None => device_mount.to_string(),
+        }
+    }
+
+    /// The gluster volume type.  VolumeType::from_str only knows gluster's own
+    /// names like Distributed-Replicate so the config names are matched here.
+    /// Unknown names are Replicate like they are in gluster.
+    pub fn gluster_type(&self) -> VolumeType {
+        match parse_volume_type(&self.volume_type) {
+            Some(volume_type) => volume_type,
+            None => VolumeType::Replicate,
+        }
+    }
+
+    /// How many consecutive bricks gluster groups into one replica, stripe or
+    /// disperse set.  This has to match the counts used to create the volume.
+    pub fn set_size(&self) -> usize {
+        match self.gluster_type() {
+            VolumeType::Distribute => 1,
+            VolumeType::Stripe | VolumeType::DistributedAndStripe => self.stripe_count,
+            VolumeType::Replicate |
+            VolumeType::Arbiter |
+            VolumeType::DistributedAndReplicate => self.replicas,
+            VolumeType::StripedAndReplicate |
+            VolumeType::DistributedAndStripedAndReplicate => self.stripe_count * self.replicas,
+            VolumeType::Disperse |
+            VolumeType::DistributedAndDisperse => self.disperse_data + self.disperse_redundancy,
+        }
+    }
+
+    // The plain types hold exactly one set.  Gluster quietly turns them into
+    // the distributed variant when given more bricks.
+    fn single_set(&self) -> bool {
+        match self.gluster_type() {
+            VolumeType::Stripe |
+            VolumeType::Replicate |
+            VolumeType::StripedAndReplicate |
+            VolumeType::Disperse => true,
+            _ => false,
+        }
+    }
+
+    /// Check that the counts make sense for the volume type.  Gluster would
+    /// either refuse them or build a different volume than asked for.
+    pub fn validate(&self) -> Result<(), String> {
+        let volume_type = match parse_volume_type(&self.volume_type) {
+            Some(volume_type) => volume_type,
+            None => {
+                return Err(format!("Volume {} has unknown type {}", self.name, self.volume_type))
+            }
+        };
+        match volume_type {
+            VolumeType::Distribute => {}
+            VolumeType::Replicate | VolumeType::DistributedAndReplicate => {
+                if self.replicas < 2 {
+                    return Err(format!("Volume {} needs at least 2 replicas, not {}",
+                                       self.name,
+                                       self.replicas));
+                }
+            }
+            VolumeType::Arbiter => {
+                if self.replicas != 3 || self.arbiter_count != 1 {
+                    return Err(format!("Volume {} has {} replicas with {} arbiters.  Gluster \
+                                        only supports 3 replicas with 1 arbiter",
+                                       self.name,
+                                       self.replicas,
+                                       self.arbiter_count));
+                }
+            }
+            VolumeType::Stripe | VolumeType::DistributedAndStripe => {
+                if self.stripe_count < 2 {
+                    return Err(format!("Volume {} needs a stripe_count of at least 2, not {}",
+                                       self.name,
+                                       self.stripe_count));
+                }
+            }
+            VolumeType::StripedAndReplicate |
+            VolumeType::DistributedAndStripedAndReplicate => {
+                if self.stripe_count < 2 || self.replicas < 2 {
+                    return Err(format!("Volume {} needs a stripe_count and replicas of at \
+                                        least 2, not {} and {}",
+                                       self.name,
+                                       self.stripe_count,
+                                       self.replicas));
+                }
+            }
+            VolumeType::Disperse | VolumeType::DistributedAndDisperse => {
+                // Gluster needs more data than redundancy bricks in a set
+                if self.disperse_redundancy < 1 || self.disperse_data <= self.disperse_redundancy {
+                    return Err(format!("Volume {} needs at least 1 redundancy brick and more \
+                                        data than redundancy bricks, not {} data and {} \
+                                        redundancy",
+                                       self.name,
+                                       self.disperse_data,
+                                       self.disperse_redundancy));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a new volume can be built out of brick_count bricks on
+    /// host_count hosts without changing its type.
+    pub fn check_bricks(&self, brick_count: usize, host_count: usize) -> Result<(), String> {
+        let set_size = self.set_size();
+        if host_count < set_size {
+            return Err(format!("Volume {} needs {} hosts to hold a set of {} bricks but only \
+                                {} have bricks",
+                               self.name,
+                               set_size,
+                               set_size,
+                               host_count));
+        }
+        if brick_count % set_size != 0 {
+            return Err(format!("Volume {} can't split {} bricks into sets of {}",
+                               self.name,
+                               brick_count,
+                               set_size));
+        }
+        if self.single_set() && brick_count != set_size {
+            return Err(format!("A {} volume takes exactly {} bricks but {} has {}.  Use \
+                                Distributed{} to spread it over more bricks",
+                               self.volume_type,
+                               set_size,
+                               self.name,
+                               brick_count,
+                               self.volume_type));
+        }
+        Ok(())
+    }
+}
+
+fn parse_volume_type(name: &str) -> Option<VolumeType> {
+    match name {
+        "Arbiter" => Some(VolumeType::Arbiter),
+        "Distribute" => Some(VolumeType::Distribute),
+        "Stripe" => Some(VolumeType::Stripe),
+        "Replicate" => Some(VolumeType::Replicate),
+        "StripedAndReplicate" => Some(VolumeType::StripedAndReplicate),
+        "Disperse" => Some(VolumeType::Disperse),
+        "DistributedAndStripe" => Some(VolumeType::DistributedAndStripe),
+        "DistributedAndReplicate" => Some(VolumeType::DistributedAndReplicate),
+        "DistributedAndStripedAndReplicate" => {
+            Some(VolumeType::DistributedAndStripedAndReplicate)
+        }
+        "DistributedAndDisperse" => Some(VolumeType::DistributedAndDisperse),
+        _ => None,
+    }
+}
+
+// The first volume keeps the original mount point.  ctdb's recovery lock and
+// the upgrade keys live on it.
+fn default_mount_point(index: usize, name: &str) -> String {
+    if index == 0 {
+        "/mnt/glusterfs".to_string()
+    } else {
+        format!("/mnt/glusterfs-{}", name)
+    }
+}
+
+fn yaml_string(entry: &BTreeMap<String, Value>, key: &str) -> Result<Option<String>, String> {
+    match entry.get(key) {
+        Some(&Value::String(ref s)) => Ok(Some(s.clone())),
+        Some(&Value::I64(i)) => Ok(Some(i.to_string())),
+        Some(&Value::Null) | None => Ok(None),
+        Some(other) => Err(format!("Invalid value for {}: {:?}", key, other)),
+    }
+}
+
+fn yaml_count(entry: &BTreeMap<String, Value>,
+              key: &str,
+              default: usize,
+              name: &str)
+              -> Result<usize, String> {
+    match entry.get(key) {
+        Some(&Value::I64(count)) if count > 0 => Ok(count as usize),
+        None => Ok(default),
+        Some(other) => Err(format!("Invalid {} for volume {}: {:?}", key, name, other)),
+    }
+}
+
+/// Parse the volumes config option.  The type and the counts fall back to
+/// the ones in defaults when an entry leaves them out.
+// - name: home
+//   type: DistributedAndReplicate
+//   replicas: 3
+//   brick_dir: home
+// - name: scratch
+//   type: Distribute
+//   brick_dir: scratch
+pub fn parse_volumes(yaml: &str, defaults: &VolumeConfig) -> Result<Vec<VolumeConfig>, String> {
+    let entries: Vec<BTreeMap<String, Value>> =
+        serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+    if entries.is_empty() {
+        return Err("volumes must list at least one volume".to_string());
+    }
+    let mut volumes: Vec<VolumeConfig> = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let name = match yaml_string(entry, "name")? {
+            Some(name) => name,
+            None => return Err(format!("Volume {} is missing a name", index)),
+        };
+        if name.is_empty() ||
+           !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("Invalid volume name: {:?}", name));
+        }
+        if volumes.iter().any(|v| v.name == name) {
+            return Err(format!("Volume {} is listed more than once", name));
+        }
+        let brick_dir = yaml_string(entry, "brick_dir")?;
+        if let Some(ref dir) = brick_dir {
+            if dir.is_empty() || dir.contains('/') || dir == ".." || dir == "." {
+                return Err(format!("Invalid brick_dir for volume {}: {:?}", name, dir));
+            }
+        }
+        let mount_point = match yaml_string(entry, "mount_point")? {
+            Some(mount_point) => mount_point,
+            None => default_mount_point(index, &name),
+        };
+        let volume = VolumeConfig {
+            volume_type: yaml_string(entry, "type")?
+                .unwrap_or(defaults.volume_type.clone()),
+            replicas: yaml_count(entry, "replicas", defaults.replicas, &name)?,
+            arbiter_count: yaml_count(entry, "arbiter_count", defaults.arbiter_count, &name)?,
+            stripe_count: yaml_count(entry, "stripe_count", defaults.stripe_count, &name)?,
+            disperse_data: yaml_count(entry, "disperse_data", defaults.disperse_data, &name)?,
+            disperse_redundancy: yaml_count(entry,
+                                            "disperse_redundancy",
+                                            defaults.disperse_redundancy,
+                                            &name)?,
+            brick_dir: brick_dir,
+            mount_point: mount_point,
+            name: name,
+        };
+        volumes.push(volume);
+    }
+    // Two volumes can't share a brick so they each need their own directory
+    if volumes.len() > 1 {
+        for volume in &volumes {
+            if volume.brick_dir.is_none() {
+                return Err(format!("Volume {} needs a brick_dir when more than one volume \
+                                    is configured",
+                                   volume.name));
+            }
+            if volumes.iter().filter(|v| v.brick_dir == volume.brick_dir).count() > 1 {
+                return Err(format!("brick_dir {:?} is used by more than one volume",
+                                   volume.brick_dir));
+            }
+            if volumes.iter().filter(|v| v.mount_point == volume.mount_point).count() > 1 {
+                return Err(format!("mount_point {} is used by more than one volume",
+                                   volume.mount_point));
+            }
+        }
+    }
+    Ok(volumes)
+}
+
+fn get_config_count(name: &str, default: usize) -> Result<usize, String> {
+    Ok(get_config_value(name)?.parse::<usize>().unwrap_or(default))
+}
+
+/// The volumes this charm manages.  When the volumes config option is unset
+/// this is the single volume described by volume_name, cluster_type and the
+/// count options with its bricks at the root of every device.
+pub fn get_volumes() -> Result<Vec<VolumeConfig>, String> {
+    let defaults = VolumeConfig {
+        name: get_config_value("volume_name")?,
+        volume_type: get_config_value("cluster_type")?,
+        replicas: get_config_count("replication_level", 3)?,
+        arbiter_count: get_config_count("arbiter_count", 1)?,
+        stripe_count: get_config_count("stripe_count", 3)?,
+        disperse_data: get_config_count("disperse_data", 2)?,
+        disperse_redundancy: get_config_count("disperse_redundancy", 1)?,
+        brick_dir: None,
+        mount_point: default_mount_point(0, ""),
+    };
+    match juju::config_get("volumes").map_err(|e| e.to_string())? {
+        Some(ref yaml) if !yaml.trim().is_empty() => parse_volumes(yaml, &defaults),
+        _ => Ok(vec![defaults]),
+    }
+}
+
+/// The first configured volume.  Actions use it when no volume is given.
+pub fn default_volume_name() -> Result<String, String> {
+    match get_volumes()?.into_iter().next() {
+        Some(volume) => Ok(volume.name),
+        None => Err("No volumes are configured".to_string()),
+    }
+}
+
+#[cfg(test)]
+fn test_defaults(volume_type: &str) -> VolumeConfig {
+    VolumeConfig {
+        name: "test".to_string(),
+        volume_type: volume_type.to_string(),
+        replicas: 3,
+        arbiter_count: 1,
+        stripe_count: 3,
+        disperse_data: 2,
+        disperse_redundancy: 1,
+        brick_dir: None,
+        mount_point: "/mnt/glusterfs".to_string(),
+    }
+}
+
+#[test]
+fn test_parse_volumes() {
+    let yaml = r#"
+- name: home
+  type: Replicate
+  replicas: 2
+  brick_dir: home
+- name: scratch
+  brick_dir: scratch
+"#;
+    let volumes = parse_volumes(yaml, &test_defaults("DistributedAndReplicate")).unwrap();
+    assert_eq!(volumes,
+               vec![VolumeConfig {
+                        name: "home".to_string(),
+                        volume_type: "Replicate".to_string(),
+                        replicas: 2,
+                        arbiter_count: 1,
+                        stripe_count: 3,
+                        disperse_data: 2,
+                        disperse_redundancy: 1,
+                        brick_dir: Some("home".to_string()),
+                        mount_point: "/mnt/glusterfs".to_string(),
+                    },
+                    VolumeConfig {
+                        name: "scratch".to_string(),
+                        volume_type: "DistributedAndReplicate".to_string(),
+                        replicas: 3,
+                        arbiter_count: 1,
+                        stripe_count: 3,
+                        disperse_data: 2,
+                        disperse_redundancy: 1,
+                        brick_dir: Some("scratch".to_string()),
+                        mount_point: "/mnt/glusterfs-scratch".to_string(),
+                    }]);
+    assert_eq!(volumes[0].brick_path("/mnt/xvdb"), "/mnt/xvdb/home");
+
+    // A single volume may use the whole device
+    let volumes = parse_volumes("- name: test", &test_defaults("Distribute")).unwrap();
+    assert_eq!(volumes[0].brick_path("/mnt/xvdb"), "/mnt/xvdb");
+}
+
+#[test]
+fn test_parse_volumes_invalid() {
+    let defaults = test_defaults("Distribute");
+    // Sharing a brick
+    assert!(parse_volumes("- name: a\n- name: b\n", &defaults).is_err());
+    assert!(parse_volumes("- {name: a, brick_dir: x}\n- {name: b, brick_dir: x}\n", &defaults)
+        .is_err());
+    // Duplicate names
+    assert!(parse_volumes("- {name: a, brick_dir: x}\n- {name: a, brick_dir: y}\n", &defaults)
+        .is_err());
+    assert!(parse_volumes("- {name: a, brick_dir: ../x}\n", &defaults).is_err());
+    assert!(parse_volumes("- {name: a, replicas: 0}\n", &defaults).is_err());
+    assert!(parse_volumes("- brick_dir: x\n", &defaults).is_err());
+    assert!(parse_volumes("[]", &defaults).is_err());
+}
+
+#[test]
+fn test_volume_counts() {
+    let yaml = "- {name: ec, type: Disperse, disperse_data: 4, disperse_redundancy: 2}";
+    let volume = parse_volumes(yaml, &test_defaults("Distribute")).unwrap().remove(0);
+    assert_eq!(volume.set_size(), 6);
+    assert!(volume.validate().is_ok());
+    assert!(volume.check_bricks(6, 6).is_ok());
+    // Fewer hosts than bricks in a set
+    assert!(volume.check_bricks(6, 5).is_err());
+    // More bricks would make it DistributedAndDisperse
+    assert!(volume.check_bricks(12, 6).is_err());
+
+    let mut volume = test_defaults("DistributedAndDisperse");
+    assert_eq!(volume.gluster_type(), VolumeType::DistributedAndDisperse);
+    assert!(volume.check_bricks(6, 3).is_ok());
+    volume.disperse_redundancy = 2;
+    assert!(volume.validate().is_err());
+
+    let mut volume = test_defaults("DistributedAndReplicate");
+    volume.replicas = 2;
+    assert_eq!(volume.set_size(), 2);
+    assert!(volume.check_bricks(6, 3).is_ok());
+    assert!(volume.check_bricks(5, 3).is_err());
+    volume.replicas = 1;
+    assert!(volume.validate().is_err());
+
+    let mut volume = test_defaults("Arbiter");
+    assert!(volume.validate().is_ok());
+    volume.arbiter_count = 2;
+    assert!(volume.validate().is_err());
+
+    let mut volume = test_defaults("StripedAndReplicate");
+    volume.stripe_count = 2;
+    volume.replicas = 2;
+    assert_eq!(volume.set_size(), 4);
+    assert!(volume.validate().is_ok());
+
+    assert!(test_defaults("Mirror").validate().is_err());
+}
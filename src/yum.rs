@@ -0,0 +1,144 @@
+use std::process::Command;
+
+use super::package::PackageVersion;
+
+/// Where yum looks for repository definitions
+const REPO_DIR: &'static str = "/etc/yum.repos.d";
+
+/// The rpm that has what a Debian package provides.  Packages that are
+/// named the same on both aren't listed.
+const RPM_NAMES: [(&'static str, &'static str); 9] =
+    [("btrfs-tools", "btrfs-progs"),
+     ("glusterfs-client", "glusterfs-fuse"),
+     ("glusterfs-common", "glusterfs-cli"),
+     ("krb5-user", "krb5-workstation"),
+     ("libnss-winbind", "samba-winbind-clients"),
+     ("nfs-common", "nfs-utils"),
+     ("samba-vfs-modules", "samba-vfs-glusterfs"),
+     ("winbind", "samba-winbind"),
+     ("zfsutils-linux", "zfs")];
+
+pub fn rpm_name(package: &str) -> &str {
+    RPM_NAMES.iter()
+        .find(|&&(deb, _)| deb == package)
+        .map(|&(_, rpm)| rpm)
+        .unwrap_or(package)
+}
+
+fn run(cmd: &mut Command) -> Result<String, String> {
+    log!(format!("Running {:?}", cmd), Debug);
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        // rpm -q tells about missing packages on stdout
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(if stderr.trim().is_empty() { stdout } else { stderr });
+    }
+    Ok(stdout)
+}
+
+/// source is either the url of a .repo file, like the ones
+/// download.gluster.org publishes, or a release package such as
+/// centos-release-gluster312 from the CentOS Storage SIG.  Guessing a
+/// repository for an apt source would pick a release nobody asked for.
+pub fn check_source(source: &str) -> Result<(), String> {
+    let hint = "Set source to the url of a .repo file or a release package";
+    if source.is_empty() {
+        return Err(format!("source is not set.  {}", hint));
+    }
+    if source.starts_with("ppa:") || source.starts_with("cloud:") || source.contains(' ') {
+        return Err(format!("source {} is for apt.  {}", source, hint));
+    }
+    Ok(())
+}
+
+/// Add a repository
+pub fn add_source(command: &str, source: &str) -> Result<(), String> {
+    check_source(source)?;
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let name = source.rsplit('/').next().unwrap_or("gluster.repo");
+        run(Command::new("curl")
+            .args(&["-s", "-S", "-f", "-L", "-o"])
+            .arg(format!("{}/{}", REPO_DIR, name))
+            .arg(source))?;
+        return Ok(());
+    }
+    install(command, vec![source])
+}
+
+/// Refresh the repository metadata
+pub fn update(command: &str) -> Result<(), String> {
+    run(Command::new(command).args(&["makecache", "-q"]))?;
+    Ok(())
+}
+
+/// Install the rpms matching a list of Debian package names
+pub fn install(command: &str, packages: Vec<&str>) -> Result<(), String> {
+    let mut cmd = Command::new(command);
+    cmd.args(&["install", "-q", "-y"]);
+    let mut rpms: Vec<&str> = Vec::new();
+    for package in packages {
+        let rpm = rpm_name(package);
+        if !rpms.contains(&rpm) {
+            rpms.push(rpm);
+        }
+    }
+    cmd.args(&rpms);
+    run(&mut cmd)?;
+    Ok(())
+}
+
+/// Ask rpm which version of a package is installed
+pub fn installed_version(rpm: &str) -> Result<PackageVersion, String> {
+    let output = run(Command::new("rpm")
+        .args(&["-q", "--queryformat", "%{EPOCH}:%{VERSION}-%{RELEASE}\n"])
+        .arg(rpm))?;
+    PackageVersion::parse(&output)
+}
+
+/// The newest version yum knows of, installed or not
+pub fn candidate_version(command: &str, rpm: &str) -> Result<PackageVersion, String> {
+    let output = run(Command::new(command).args(&["list", "-q", "--showduplicates", rpm]))?;
+    parse_yum_list(&output, rpm)
+}
+
+/// yum list prints name.arch, version and repo columns but wraps long
+/// names onto their own line so go by the words rather than the lines
+pub fn parse_yum_list(output: &str, rpm: &str) -> Result<PackageVersion, String> {
+    let prefix = format!("{}.", rpm);
+    let words: Vec<&str> = output.split_whitespace().collect();
+    let mut newest: Option<PackageVersion> = None;
+    for pair in words.windows(2) {
+        if !pair[0].starts_with(&prefix) {
+            continue;
+        }
+        let version = PackageVersion::parse(pair[1])?;
+        if newest.as_ref().map(|newest| version > *newest).unwrap_or(true) {
+            newest = Some(version);
+        }
+    }
+    newest.ok_or(format!("No version of {} is listed", rpm))
+}
+
+#[test]
+fn test_parse_yum_output() {
+    let newest = parse_yum_list(include_str!("testdata/yum_list.txt"), "glusterfs-server");
+    assert_eq!(Ok("3.12.15-1.el7".to_string()), newest.map(|v| v.to_string()));
+    assert!(parse_yum_list("Installed Packages\n", "glusterfs-server").is_err());
+    assert_eq!("3.12.2-18.el7",
+               PackageVersion::parse("(none):3.12.2-18.el7\n").unwrap().to_string());
+
+    assert!(check_source("centos-release-gluster312").is_ok());
+    assert!(check_source("https://download.gluster.org/pub/gluster/glusterfs/3.12/LATEST/RHEL/\
+                          glusterfs-rhel.repo")
+        .is_ok());
+    assert_eq!(Err("source ppa:gluster/glusterfs-3.10 is for apt.  Set source to the url of a \
+                    .repo file or a release package"
+                       .to_string()),
+               check_source("ppa:gluster/glusterfs-3.10"));
+    assert!(check_source("http://my.archive.com/ubuntu main").is_err());
+    assert!(check_source("").is_err());
+
+    assert_eq!("glusterfs-fuse", rpm_name("glusterfs-client"));
+    assert_eq!("glusterfs-server", rpm_name("glusterfs-server"));
+}